@@ -0,0 +1,5 @@
+-- Deleting a subscriber must also delete their confirmation token
+ALTER TABLE subscription_tokens
+    DROP CONSTRAINT subscription_tokens_subscriber_id_fkey,
+    ADD CONSTRAINT subscription_tokens_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions(id) ON DELETE CASCADE;
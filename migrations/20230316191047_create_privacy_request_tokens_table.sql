@@ -0,0 +1,7 @@
+CREATE TABLE privacy_request_tokens(
+    privacy_request_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    requested_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (privacy_request_token)
+);
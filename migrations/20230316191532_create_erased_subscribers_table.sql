@@ -0,0 +1,6 @@
+-- Only a hash of the address survives an erasure, enough to keep it from being re-imported
+CREATE TABLE erased_subscribers(
+    email_hash TEXT NOT NULL,
+    erased_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (email_hash)
+);
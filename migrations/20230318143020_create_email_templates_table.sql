@@ -0,0 +1,9 @@
+-- Overrides for the templates shipped in `templates/email`
+CREATE TABLE email_templates(
+    name TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    text_body TEXT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (name)
+);
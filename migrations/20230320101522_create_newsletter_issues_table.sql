@@ -0,0 +1,26 @@
+-- Drafts keep their full edit history in `newsletter_issue_revisions`;
+-- the `published_*` columns snapshot what was actually sent, so later edits
+-- to the draft never change what the archive shows
+CREATE TABLE newsletter_issues(
+    id uuid NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    published_at TIMESTAMPTZ NULL,
+    published_revision INTEGER NULL,
+    published_title TEXT NULL,
+    published_html TEXT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE newsletter_issue_revisions(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (issue_id, revision)
+);
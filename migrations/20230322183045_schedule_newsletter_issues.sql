@@ -0,0 +1,15 @@
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_at TIMESTAMPTZ NULL;
+-- IANA name the schedule was given in, kept so it can be shown back as entered
+ALTER TABLE newsletter_issues ADD COLUMN schedule_time_zone TEXT NULL;
+
+-- One row per (issue, subscriber) still to be sent. Workers claim rows with
+-- `FOR UPDATE SKIP LOCKED`, so several replicas can drain it side by side.
+CREATE TABLE issue_delivery_queue(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    execute_after TIMESTAMPTZ NOT NULL,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (issue_id, subscriber_id)
+);
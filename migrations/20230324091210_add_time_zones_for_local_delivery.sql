@@ -0,0 +1,5 @@
+-- IANA time zone of the subscriber, when known
+ALTER TABLE subscriptions ADD COLUMN time_zone TEXT NULL;
+-- Set when an issue goes out at this wall-clock time in each subscriber's own
+-- time zone rather than at one instant; `scheduled_at` is then the first wave
+ALTER TABLE newsletter_issues ADD COLUMN local_send_time TIMESTAMP NULL;
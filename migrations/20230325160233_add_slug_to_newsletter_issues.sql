@@ -0,0 +1,2 @@
+-- Assigned when an issue is published; its address in the public archive
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
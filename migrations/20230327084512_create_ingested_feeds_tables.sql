@@ -0,0 +1,19 @@
+-- External feeds polled for new items, with the validators of the last fetch
+CREATE TABLE ingested_feeds(
+    url TEXT NOT NULL,
+    PRIMARY KEY (url),
+    etag TEXT NULL,
+    last_modified TEXT NULL,
+    -- NULL until the first successful fetch, which only records what is there
+    last_polled_at TIMESTAMPTZ NULL
+);
+
+CREATE TABLE ingested_feed_items(
+    feed_url TEXT NOT NULL
+        REFERENCES ingested_feeds(url) ON DELETE CASCADE,
+    item_key TEXT NOT NULL,
+    issue_id uuid NULL
+        REFERENCES newsletter_issues(id) ON DELETE SET NULL,
+    seen_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (feed_url, item_key)
+);
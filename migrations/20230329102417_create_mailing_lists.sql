@@ -0,0 +1,60 @@
+-- A subscriber is a person; which newsletters they get, and whether they
+-- confirmed each one, lives in `list_subscriptions`
+CREATE TABLE lists(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+INSERT INTO lists (id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'default', 'Newsletter', now());
+
+CREATE TABLE list_subscriptions(
+    list_id uuid NOT NULL
+        REFERENCES lists(id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    subscribed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT l.id, s.id, s.status, s.subscribed_at
+FROM subscriptions s, lists l
+WHERE l.slug = 'default';
+
+ALTER TABLE subscriptions DROP COLUMN status;
+
+-- Each token confirms, and later unsubscribes from, a single list
+ALTER TABLE subscription_tokens
+    ADD COLUMN list_id uuid NULL REFERENCES lists(id) ON DELETE CASCADE;
+UPDATE subscription_tokens SET list_id = (SELECT id FROM lists WHERE slug = 'default');
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+ALTER TABLE subscription_tokens
+    DROP CONSTRAINT subscription_tokens_pkey,
+    ADD PRIMARY KEY (subscriber_id, list_id);
+-- Tokens are still looked up on their own when confirming or unsubscribing
+CREATE UNIQUE INDEX subscription_tokens_subscription_token_idx
+    ON subscription_tokens (subscription_token);
+
+CREATE TABLE newsletter_issue_lists(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    list_id uuid NOT NULL
+        REFERENCES lists(id) ON DELETE CASCADE,
+    PRIMARY KEY (issue_id, list_id)
+);
+
+INSERT INTO newsletter_issue_lists (issue_id, list_id)
+SELECT i.id, l.id
+FROM newsletter_issues i, lists l
+WHERE l.slug = 'default';
+
+-- The list a queued email goes out for, whose token builds its unsubscribe link
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN list_id uuid NULL REFERENCES lists(id) ON DELETE CASCADE;
+UPDATE issue_delivery_queue SET list_id = (SELECT id FROM lists WHERE slug = 'default');
+ALTER TABLE issue_delivery_queue ALTER COLUMN list_id SET NOT NULL;
@@ -0,0 +1,4 @@
+-- Custom attributes are checked against the rules in the configuration
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
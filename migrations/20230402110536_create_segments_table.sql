@@ -0,0 +1,12 @@
+-- Saved subsets of subscribers, see `domain::Segment` for the language
+CREATE TABLE segments(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    name TEXT NOT NULL UNIQUE,
+    definition TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+-- Narrows the recipients of an issue to the part of its lists in the segment
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid NULL REFERENCES segments(id);
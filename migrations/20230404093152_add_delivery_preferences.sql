@@ -0,0 +1,16 @@
+-- 'every_issue', or 'weekly_digest' to get the week's issues in one email
+ALTER TABLE subscriptions ADD COLUMN frequency TEXT NOT NULL DEFAULT 'every_issue';
+-- Nothing is sent to the subscriber before this instant
+ALTER TABLE subscriptions ADD COLUMN paused_until TIMESTAMPTZ NULL;
+
+-- Audit log of the changes subscribers make in the preference center
+CREATE TABLE preference_changes(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    field TEXT NOT NULL,
+    old_value TEXT NULL,
+    new_value TEXT NULL,
+    changed_at TIMESTAMPTZ NOT NULL
+);
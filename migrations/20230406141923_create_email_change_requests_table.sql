@@ -0,0 +1,9 @@
+-- Addresses waiting to be verified before they replace a subscriber's email
+CREATE TABLE email_change_requests(
+    email_change_token TEXT NOT NULL,
+    PRIMARY KEY (email_change_token),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL
+);
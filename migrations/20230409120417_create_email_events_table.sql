@@ -0,0 +1,19 @@
+-- Subscribers who don't want their engagement with our emails tracked
+ALTER TABLE subscriptions ADD COLUMN tracking_opt_out BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- What subscribers did with the issues we sent them, e.g. opened one
+CREATE TABLE email_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    user_agent TEXT NULL
+);
+-- Finding the last issue a subscriber was sent, when a bounce or an
+-- unsubscribe comes in
+CREATE INDEX email_events_subscriber_id_kind_idx
+    ON email_events (subscriber_id, kind, occurred_at);
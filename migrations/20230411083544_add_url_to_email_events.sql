@@ -0,0 +1,2 @@
+-- The link followed, for click events
+ALTER TABLE email_events ADD COLUMN url TEXT NULL;
@@ -0,0 +1,76 @@
+-- Running totals of each kind of event per issue, kept up to date as events
+-- come in so that reports never scan email_events. Each total is split into
+-- shards picked from the subscriber, so opens and clicks on a popular issue
+-- don't all queue up on one row; reports add the shards back up.
+CREATE TABLE issue_stats(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    shard SMALLINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL,
+    unique_subscribers BIGINT NOT NULL,
+    PRIMARY KEY (issue_id, kind, shard)
+);
+
+-- The same per hour since the issue was published, for its first 48 hours
+CREATE TABLE issue_hourly_stats(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    hour SMALLINT NOT NULL,
+    shard SMALLINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL,
+    PRIMARY KEY (issue_id, kind, hour, shard)
+);
+
+-- Clicks on each link of an issue
+CREATE TABLE issue_link_stats(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    shard SMALLINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL,
+    unique_subscribers BIGINT NOT NULL,
+    PRIMARY KEY (issue_id, url, shard)
+);
+
+-- Who already did what with an issue, so each subscriber is counted once.
+-- `url` is only set for clicks on a given link.
+CREATE TABLE issue_event_subscribers(
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues(id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    url TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (issue_id, subscriber_id, kind, url)
+);
+
+-- Count the events recorded so far
+INSERT INTO issue_event_subscribers (issue_id, subscriber_id, kind)
+SELECT DISTINCT issue_id, subscriber_id, kind FROM email_events;
+
+INSERT INTO issue_event_subscribers (issue_id, subscriber_id, kind, url)
+SELECT DISTINCT issue_id, subscriber_id, kind, url FROM email_events
+WHERE url IS NOT NULL;
+
+INSERT INTO issue_stats (issue_id, kind, total, unique_subscribers)
+SELECT issue_id, kind, COUNT(*), COUNT(DISTINCT subscriber_id)
+FROM email_events
+GROUP BY issue_id, kind;
+
+INSERT INTO issue_hourly_stats (issue_id, kind, hour, total)
+SELECT e.issue_id, e.kind,
+    FLOOR(EXTRACT(EPOCH FROM e.occurred_at - i.published_at) / 3600)::SMALLINT,
+    COUNT(*)
+FROM email_events e
+JOIN newsletter_issues i ON i.id = e.issue_id
+WHERE e.occurred_at >= i.published_at
+    AND e.occurred_at < i.published_at + INTERVAL '48 hours'
+GROUP BY 1, 2, 3;
+
+INSERT INTO issue_link_stats (issue_id, url, total, unique_subscribers)
+SELECT issue_id, url, COUNT(*), COUNT(DISTINCT subscriber_id)
+FROM email_events
+WHERE url IS NOT NULL
+GROUP BY issue_id, url;
@@ -0,0 +1,13 @@
+-- Soft bounces since the last delivery, hard-bouncing the address past a limit
+ALTER TABLE subscriptions ADD COLUMN soft_bounces INTEGER NOT NULL DEFAULT 0;
+
+-- Events the email provider already told us about, so a batch it sends again
+-- after a failure doesn't count the same bounce twice
+CREATE TABLE provider_events(
+    event_id TEXT NOT NULL,
+    PRIMARY KEY (event_id),
+    received_at TIMESTAMPTZ NOT NULL
+);
+
+-- Provider events name the recipient in whatever case they were sent to
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
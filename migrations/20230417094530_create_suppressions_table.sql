@@ -0,0 +1,37 @@
+-- Addresses that must never be mailed again, whichever list they turn up on.
+-- Like the erasure records it replaces, it only keeps a hash of the address.
+CREATE TABLE suppressions(
+    email_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (email_hash)
+);
+
+INSERT INTO suppressions (email_hash, reason, source, created_at)
+SELECT email_hash, 'erasure', 'privacy_request', erased_at FROM erased_subscribers;
+
+-- Addresses already taken off every list by bounces, complaints or
+-- unsubscribes, with a bounce or complaint winning over an unsubscribe
+INSERT INTO suppressions (email_hash, reason, source, created_at)
+SELECT DISTINCT ON (s.id)
+    encode(sha256(convert_to(lower(trim(s.email)), 'UTF8')), 'hex'),
+    CASE ls.status
+        WHEN 'bounced' THEN 'bounce'
+        WHEN 'complained' THEN 'complaint'
+        ELSE 'unsubscribe'
+    END,
+    'backfill',
+    now()
+FROM subscriptions s
+JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+WHERE ls.status IN ('bounced', 'complained', 'unsubscribed')
+    AND NOT EXISTS (
+        SELECT 1 FROM list_subscriptions active
+        WHERE active.subscriber_id = s.id
+            AND active.status IN ('confirmed', 'pending_confirmation')
+    )
+ORDER BY s.id, ls.status = 'unsubscribed'
+ON CONFLICT (email_hash) DO NOTHING;
+
+DROP TABLE erased_subscribers;
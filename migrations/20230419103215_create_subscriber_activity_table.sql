@@ -0,0 +1,45 @@
+-- Everything that happened to a subscriber, from signup to every delivery
+-- attempt, so support can tell why someone didn't get an issue
+CREATE TABLE subscriber_activity(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    issue_id uuid NULL
+        REFERENCES newsletter_issues(id) ON DELETE SET NULL,
+    list_id uuid NULL
+        REFERENCES lists(id) ON DELETE SET NULL,
+    -- e.g. what the email provider answered, or the link that was clicked
+    detail TEXT NULL
+);
+CREATE INDEX subscriber_activity_subscriber_id_idx
+    ON subscriber_activity (subscriber_id, occurred_at);
+
+-- What earlier tables already tell us about the past
+INSERT INTO subscriber_activity (id, subscriber_id, kind, occurred_at, list_id)
+SELECT gen_random_uuid(), subscriber_id, 'signed_up', subscribed_at, list_id
+FROM list_subscriptions;
+
+INSERT INTO subscriber_activity (id, subscriber_id, kind, occurred_at, issue_id, detail)
+SELECT gen_random_uuid(), subscriber_id,
+    CASE kind WHEN 'sent' THEN 'delivery_attempt' ELSE kind END,
+    occurred_at, issue_id,
+    CASE kind WHEN 'sent' THEN 'Accepted by the provider' END
+FROM email_events
+WHERE kind NOT IN ('open', 'click');
+
+-- Opens, clicks and preference changes already have tables of their own, so
+-- the timeline reads them from there instead of keeping copies
+CREATE VIEW subscriber_timeline AS
+SELECT subscriber_id, kind, occurred_at, issue_id, list_id, detail
+FROM subscriber_activity
+UNION ALL
+SELECT subscriber_id, kind, occurred_at, issue_id, NULL, url
+FROM email_events
+WHERE kind IN ('open', 'click')
+UNION ALL
+SELECT subscriber_id, 'preference_change', changed_at, NULL, NULL,
+    field || ': ' || COALESCE(old_value, 'none') || ' -> ' || COALESCE(new_value, 'none')
+FROM preference_changes;
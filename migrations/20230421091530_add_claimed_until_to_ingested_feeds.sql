@@ -0,0 +1,3 @@
+-- Set while a replica fetches the feed, so others leave it alone without a
+-- row lock being held across the fetch
+ALTER TABLE ingested_feeds ADD COLUMN claimed_until TIMESTAMPTZ NULL;
@@ -0,0 +1,48 @@
+use crate::domain::ActivityKind;
+use crate::email_client::ProviderResponse;
+use chrono::Utc;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Add an entry to a subscriber's activity timeline.
+pub async fn record_activity(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    kind: ActivityKind,
+    issue_id: Option<Uuid>,
+    list_id: Option<Uuid>,
+    detail: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_activity
+            (id, subscriber_id, kind, occurred_at, issue_id, list_id, detail)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind.as_str(),
+        Utc::now(),
+        issue_id,
+        list_id,
+        detail
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to record subscriber activity: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// What the provider made of an email, as shown on the timeline.
+pub fn describe_send<E: std::fmt::Display>(
+    outcome: &Result<Option<ProviderResponse>, E>,
+) -> String {
+    match outcome {
+        Ok(Some(response)) => response.to_string(),
+        Ok(None) => "Not sent, the address is suppressed".to_string(),
+        Err(e) => format!("Failed: {}", e),
+    }
+}
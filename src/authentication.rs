@@ -0,0 +1,204 @@
+use crate::configuration::{AdminSettings, WebhookSettings};
+use actix_web::dev::Payload;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use base64::Engine;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use std::future::{ready, Ready};
+use subtle::{Choice, ConstantTimeEq};
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug)]
+pub struct AuthError(String);
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Unauthorized()
+            .insert_header((
+                "WWW-Authenticate",
+                HeaderValue::from_static(r#"Basic realm="admin""#),
+            ))
+            .finish()
+    }
+}
+
+/// Extractor that only succeeds when the request carries the admin's basic auth credentials.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let outcome = request
+            .app_data::<web::Data<AdminSettings>>()
+            .ok_or_else(|| AuthError("Admin credentials are not configured.".into()))
+            .and_then(|settings| {
+                let credentials = basic_authentication(request.headers())?;
+                validate_credentials(&credentials, &settings.username, &settings.password)
+            })
+            .map(|_| Admin);
+        if let Err(e) = &outcome {
+            tracing::warn!("Rejected admin request: {}", e);
+        }
+        ready(outcome)
+    }
+}
+
+/// Extractor that only succeeds for webhook calls carrying the credentials
+/// configured with the email provider.
+pub struct EmailProvider;
+
+impl FromRequest for EmailProvider {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let outcome = request
+            .app_data::<web::Data<WebhookSettings>>()
+            .ok_or_else(|| AuthError("Webhook credentials are not configured.".into()))
+            .and_then(|settings| {
+                let credentials = basic_authentication(request.headers())?;
+                validate_credentials(&credentials, &settings.username, &settings.password)
+            })
+            .map(|_| EmailProvider);
+        if let Err(e) = &outcome {
+            tracing::warn!("Rejected webhook call: {}", e);
+        }
+        ready(outcome)
+    }
+}
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, AuthError> {
+    let header_value = headers
+        .get("Authorization")
+        .ok_or_else(|| AuthError("The 'Authorization' header was missing.".into()))?
+        .to_str()
+        .map_err(|_| AuthError("The 'Authorization' header was not a valid UTF8 string.".into()))?;
+    let base64_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or_else(|| AuthError("The authorization scheme was not 'Basic'.".into()))?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64_segment)
+        .map_err(|_| AuthError("Failed to base64-decode 'Basic' credentials.".into()))?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| AuthError("The decoded credential string is not valid UTF8.".into()))?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or_else(|| AuthError("Credentials must be in the 'username:password' form.".into()))?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+fn validate_credentials(
+    credentials: &Credentials,
+    username: &str,
+    password: &Secret<String>,
+) -> Result<(), AuthError> {
+    let username_matches = constant_time_eq(&credentials.username, username);
+    let password_matches = constant_time_eq(
+        credentials.password.expose_secret(),
+        password.expose_secret(),
+    );
+    if bool::from(username_matches & password_matches) {
+        Ok(())
+    } else {
+        Err(AuthError("Invalid username or password.".into()))
+    }
+}
+
+/// Compare digests rather than the strings themselves, so neither where they
+/// first differ nor how long the expected one is shows in the timing.
+fn constant_time_eq(a: &str, b: &str) -> Choice {
+    Sha256::digest(a.as_bytes())
+        .as_slice()
+        .ct_eq(Sha256::digest(b.as_bytes()).as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{basic_authentication, validate_credentials, Credentials};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use claims::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    fn headers(authorization: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("authorization"),
+            HeaderValue::from_static(authorization),
+        );
+        headers
+    }
+
+    #[test]
+    fn valid_basic_credentials_are_decoded() {
+        // "ursula:le:guin", passwords may contain colons
+        let credentials = basic_authentication(&headers("Basic dXJzdWxhOmxlOmd1aW4="));
+        let credentials = assert_ok!(credentials);
+        assert_eq!(credentials.username, "ursula");
+        assert_eq!(credentials.password.expose_secret(), "le:guin");
+    }
+
+    #[test]
+    fn missing_header_is_rejected() {
+        assert_err!(basic_authentication(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn non_basic_scheme_is_rejected() {
+        assert_err!(basic_authentication(&headers("Bearer dXJzdWxh")));
+    }
+
+    fn credentials(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.into(),
+            password: Secret::new(password.into()),
+        }
+    }
+
+    #[test]
+    fn matching_credentials_are_accepted() {
+        let password = Secret::new("le:guin".to_string());
+        assert_ok!(validate_credentials(
+            &credentials("ursula", "le:guin"),
+            "ursula",
+            &password
+        ));
+    }
+
+    #[test]
+    fn wrong_username_or_password_is_rejected() {
+        let password = Secret::new("le:guin".to_string());
+        assert_err!(validate_credentials(
+            &credentials("ursula", "le:gui"),
+            "ursula",
+            &password
+        ));
+        assert_err!(validate_credentials(
+            &credentials("Ursula", "le:guin"),
+            "ursula",
+            &password
+        ));
+    }
+}
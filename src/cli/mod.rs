@@ -0,0 +1,86 @@
+mod output;
+mod send_test_email;
+mod subscribers;
+
+pub use output::*;
+pub use send_test_email::*;
+pub use subscribers::*;
+
+use crate::domain::SubscriptionStatus;
+use crate::routes::DEFAULT_LIST;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "email_newsletter", about = "Email newsletter service")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Output format for commands that print records
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (default)
+    Serve,
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Manage subscribers
+    Subscribers {
+        #[command(subcommand)]
+        command: SubscribersCommand,
+    },
+    /// Send a test email through the configured email client
+    SendTestEmail { address: String },
+}
+
+#[derive(Subcommand)]
+pub enum SubscribersCommand {
+    /// List the subscribers of a list, optionally filtered by status
+    List {
+        #[arg(long, default_value = DEFAULT_LIST)]
+        list: String,
+        #[arg(long, value_parser = parse_status)]
+        status: Option<SubscriptionStatus>,
+    },
+    /// Add a subscriber, pending confirmation unless `--confirmed` is given
+    Add {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        confirmed: bool,
+        /// IANA time zone, e.g. `Europe/Lisbon`
+        #[arg(long)]
+        time_zone: Option<String>,
+        #[arg(long, default_value = DEFAULT_LIST)]
+        list: String,
+        /// Tag the subscriber; can be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Mark a subscriber as confirmed on a list
+    Confirm {
+        email: String,
+        #[arg(long, default_value = DEFAULT_LIST)]
+        list: String,
+    },
+    /// Remove a subscriber from every list, along with their confirmation tokens
+    Remove { email: String },
+    /// Import subscribers from a CSV file with `email`, `name` and optional `subscribed_at` columns
+    Import {
+        path: PathBuf,
+        #[arg(long, default_value = DEFAULT_LIST)]
+        list: String,
+        /// Replace the name of existing subscribers with the one in the file
+        #[arg(long)]
+        overwrite_names: bool,
+    },
+}
+
+fn parse_status(status: &str) -> Result<SubscriptionStatus, String> {
+    SubscriptionStatus::parse(status.into())
+}
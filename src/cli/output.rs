@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+/// A record that can be printed by the CLI, either as a table row or as JSON.
+pub trait Record: Serialize {
+    const HEADERS: &'static [&'static str];
+
+    fn row(&self) -> Vec<String>;
+}
+
+pub fn render_records<R: Record>(records: &[R], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(records).expect("Failed to serialize records.")
+        }
+        OutputFormat::Table => render_table(records),
+    }
+}
+
+pub fn print_records<R: Record>(records: &[R], format: OutputFormat) {
+    println!("{}", render_records(records, format));
+}
+
+fn render_table<R: Record>(records: &[R]) -> String {
+    let rows: Vec<Vec<String>> = records.iter().map(Record::row).collect();
+    let mut widths: Vec<usize> = R::HEADERS.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![format_line(
+        R::HEADERS.iter().map(|h| h.to_string()).collect(),
+    )];
+    lines.extend(rows.into_iter().map(format_line));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_records, OutputFormat, Record};
+
+    #[derive(serde::Serialize)]
+    struct Fruit {
+        name: &'static str,
+        colour: &'static str,
+    }
+
+    impl Record for Fruit {
+        const HEADERS: &'static [&'static str] = &["NAME", "COLOUR"];
+
+        fn row(&self) -> Vec<String> {
+            vec![self.name.into(), self.colour.into()]
+        }
+    }
+
+    fn fruits() -> Vec<Fruit> {
+        vec![
+            Fruit {
+                name: "banana",
+                colour: "yellow",
+            },
+            Fruit {
+                name: "fig",
+                colour: "purple",
+            },
+        ]
+    }
+
+    #[test]
+    fn table_columns_are_aligned_to_the_widest_cell() {
+        let table = render_records(&fruits(), OutputFormat::Table);
+        assert_eq!(table, "NAME    COLOUR\nbanana  yellow\nfig     purple");
+    }
+
+    #[test]
+    fn json_output_is_an_array_of_objects() {
+        let json = render_records(&fruits(), OutputFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[1]["name"], "fig");
+        assert_eq!(value.as_array().unwrap().len(), 2);
+    }
+}
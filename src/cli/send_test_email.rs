@@ -0,0 +1,21 @@
+use crate::configuration::Settings;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+
+pub async fn send_test_email(address: String, configuration: Settings) -> anyhow::Result<()> {
+    let recipient = SubscriberEmail::parse(address).map_err(anyhow::Error::msg)?;
+    let email_client = configuration
+        .email_client
+        .client(&configuration.application.base_url);
+    email_client
+        .send_email(
+            recipient.clone(),
+            "Test email",
+            "<p>This is a test email from <b>email_newsletter</b>.</p>",
+            "This is a test email from email_newsletter.",
+        )
+        .await
+        .context("Failed to send the test email.")?;
+    println!("Test email sent to {}", recipient.as_ref());
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use crate::activity::record_activity;
+use crate::cli::{print_records, OutputFormat, Record, SubscribersCommand};
+use crate::domain::{
+    ActivityKind, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName,
+    SubscriberRecord, SubscriberTag, SubscriberTimeZone, SubscriptionStatus,
+};
+use crate::routes::{
+    create_subscription_token, find_list_id, import_subscribers, insert_subscriber, ImportRow,
+};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+impl Record for SubscriberRecord {
+    const HEADERS: &'static [&'static str] = &["ID", "EMAIL", "NAME", "STATUS", "SUBSCRIBED AT"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.email.clone(),
+            self.name.clone(),
+            self.status.clone(),
+            self.subscribed_at.to_rfc3339(),
+        ]
+    }
+}
+
+impl Record for ImportRow {
+    const HEADERS: &'static [&'static str] = &["ROW", "EMAIL", "OUTCOME", "REASON"];
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.row.to_string(),
+            self.email.clone(),
+            self.outcome.as_str().to_string(),
+            self.reason.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+pub async fn run_subscribers_command(
+    command: SubscribersCommand,
+    format: OutputFormat,
+    pool: &PgPool,
+) -> anyhow::Result<()> {
+    let records = match command {
+        SubscribersCommand::List { list, status } => {
+            let list_id = resolve_list(pool, &list).await?;
+            list_subscribers(pool, list_id, status)
+                .await
+                .context("Failed to list subscribers.")?
+        }
+        SubscribersCommand::Add {
+            email,
+            name,
+            confirmed,
+            time_zone,
+            list,
+            tags,
+        } => {
+            let list_id = resolve_list(pool, &list).await?;
+            let new_subscriber = NewSubscriber {
+                email: SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?,
+                name: SubscriberName::parse(name).map_err(anyhow::Error::msg)?,
+                time_zone: time_zone
+                    .map(SubscriberTimeZone::parse)
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?,
+                tags: SubscriberTag::parse_all(tags).map_err(anyhow::Error::msg)?,
+                attributes: SubscriberAttributes::default(),
+            };
+            let status = match confirmed {
+                true => SubscriptionStatus::Confirmed,
+                false => SubscriptionStatus::PendingConfirmation,
+            };
+            let record = add_subscriber(pool, list_id, &new_subscriber, status)
+                .await
+                .context("Failed to add subscriber.")?;
+            vec![record]
+        }
+        SubscribersCommand::Confirm { email, list } => {
+            let list_id = resolve_list(pool, &list).await?;
+            let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+            let record = confirm_subscriber(pool, list_id, &email)
+                .await
+                .context("Failed to confirm subscriber.")?
+                .with_context(|| {
+                    format!("No subscriber with email {} on {}.", email.as_ref(), list)
+                })?;
+            vec![record]
+        }
+        SubscribersCommand::Remove { email } => {
+            let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+            let record = remove_subscriber(pool, &email)
+                .await
+                .context("Failed to remove subscriber.")?
+                .with_context(|| format!("No subscriber with email {}.", email.as_ref()))?;
+            vec![record]
+        }
+        SubscribersCommand::Import {
+            path,
+            list,
+            overwrite_names,
+        } => {
+            let list_id = resolve_list(pool, &list).await?;
+            let file = tokio::fs::File::open(&path)
+                .await
+                .with_context(|| format!("Failed to open {}.", path.display()))?;
+            let report = import_subscribers(pool, list_id, overwrite_names, file).await?;
+            print_records(&report.rows, format);
+            eprintln!(
+                "{} accepted, {} duplicate, {} rejected, {} names updated",
+                report.accepted, report.duplicate, report.rejected, report.names_updated
+            );
+            return Ok(());
+        }
+    };
+    print_records(&records, format);
+    Ok(())
+}
+
+async fn resolve_list(pool: &PgPool, slug: &str) -> anyhow::Result<Uuid> {
+    find_list_id(pool, slug)
+        .await
+        .context("Failed to look up the list.")?
+        .with_context(|| format!("No list named {}.", slug))
+}
+
+pub async fn list_subscribers(
+    pool: &PgPool,
+    list_id: Uuid,
+    status: Option<SubscriptionStatus>,
+) -> Result<Vec<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT s.id, s.email, s.name, ls.status, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.list_id = $1 AND ($2::TEXT IS NULL OR ls.status = $2)
+        ORDER BY ls.subscribed_at
+        "#,
+        list_id,
+        status.map(|s| s.as_str())
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Put a subscriber on a list with the given status, creating them if they
+/// are new. Existing subscribers keep their name and time zone.
+pub async fn add_subscriber(
+    pool: &PgPool,
+    list_id: Uuid,
+    new_subscriber: &NewSubscriber,
+    status: SubscriptionStatus,
+) -> Result<SubscriberRecord, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = insert_subscriber(new_subscriber, &mut transaction).await?;
+    let record = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        WITH subscription AS (
+            INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE SET status = EXCLUDED.status
+            RETURNING subscriber_id, status, subscribed_at
+        )
+        SELECT s.id, s.email, s.name, subscription.status, subscription.subscribed_at
+        FROM subscription JOIN subscriptions s ON s.id = subscription.subscriber_id
+        "#,
+        list_id,
+        subscriber_id,
+        status.as_str(),
+        Utc::now()
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    create_subscription_token(&mut transaction, subscriber_id, list_id).await?;
+    let detail = format!("Added from the command line as {}", status.as_str());
+    record_activity(
+        &mut transaction,
+        subscriber_id,
+        ActivityKind::SignedUp,
+        None,
+        Some(list_id),
+        Some(&detail),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(record)
+}
+
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    list_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        UPDATE list_subscriptions ls SET status = 'confirmed'
+        FROM subscriptions s
+        WHERE s.id = ls.subscriber_id AND ls.list_id = $1 AND s.email = $2
+        RETURNING s.id, s.email, s.name, ls.status, ls.subscribed_at
+        "#,
+        list_id,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Delete a subscriber from every list.
+pub async fn remove_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE email = $1)
+        "#,
+        email.as_ref()
+    )
+    .execute(&mut transaction)
+    .await?;
+    let record = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        DELETE FROM subscriptions
+        WHERE email = $1
+        RETURNING id, email, name, 'removed' AS "status!", subscribed_at
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(record)
+}
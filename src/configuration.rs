@@ -1,8 +1,12 @@
+use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::ConnectOptions;
 use std::time::Duration;
 // use serde_aux::field_attributes::deserialize_number_from_string;
-use crate::domain::SubscriberEmail;
+use crate::domain::{AttributeRule, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::feed_client::FeedClient;
+use crate::tracking::Tracker;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
 #[derive(Clone, serde::Deserialize)]
@@ -10,6 +14,12 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub admin: AdminSettings,
+    pub scheduler: SchedulerSettings,
+    pub feed_ingestion: FeedIngestionSettings,
+    pub subscriber_fields: SubscriberFieldSettings,
+    pub tracking: TrackingSettings,
+    pub webhooks: WebhookSettings,
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -28,6 +38,124 @@ pub struct ApplicationSettings {
     pub host: String,
     // #[serde(deserialize_with="deserialize_number_from_string")]
     pub port: u16,
+    pub base_url: String,
+    pub templates_directory: String,
+    pub run_migrations_on_startup: bool,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct SchedulerSettings {
+    /// Run the scheduler and delivery worker alongside the HTTP server
+    pub enabled: bool,
+    pub poll_interval_milliseconds: u64,
+}
+
+impl SchedulerSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct FeedIngestionSettings {
+    /// Poll the configured feeds alongside the HTTP server
+    pub enabled: bool,
+    pub poll_interval_milliseconds: u64,
+    pub timeout_milliseconds: u64,
+    /// Tera template that turns the new items of a feed into issue Markdown
+    pub digest_template: String,
+    #[serde(default)]
+    pub feeds: Vec<FeedSettings>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct FeedSettings {
+    pub url: String,
+    /// Schedule each digest this long after it is created, leaving time to
+    /// review it; digests stay drafts when unset
+    pub schedule_after_minutes: Option<u32>,
+    /// Slugs of the lists digests go out to, the default list when empty
+    #[serde(default)]
+    pub lists: Vec<String>,
+}
+
+impl FeedIngestionSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    /// How long a replica may keep a feed to itself while polling it: the
+    /// fetch can't outlast the timeout, recording its items takes much less.
+    pub fn claim_for(&self) -> Duration {
+        self.timeout() + Duration::from_secs(60)
+    }
+
+    pub fn client(&self) -> Result<FeedClient, reqwest::Error> {
+        FeedClient::new(self.timeout())
+    }
+}
+
+/// Tags and custom attributes subscribers can carry beyond their email and name.
+#[derive(Clone, serde::Deserialize)]
+pub struct SubscriberFieldSettings {
+    /// Tags the signup form may set through its hidden `tags` field
+    #[serde(default)]
+    pub signup_form_tags: Vec<String>,
+    #[serde(default)]
+    pub attributes: Vec<AttributeRule>,
+}
+
+/// Engagement tracking in newsletter emails. Subscribers can still opt out
+/// from their preferences.
+#[derive(Clone, serde::Deserialize)]
+pub struct TrackingSettings {
+    /// Put a pixel in each email to record when it is opened
+    pub open_tracking: bool,
+    /// Send links through a redirect recording when they are clicked
+    pub click_tracking: bool,
+    /// Key signing the tracking links, so they can't be forged. Required
+    /// whenever either kind of tracking is on.
+    #[serde(default)]
+    pub signing_key: Option<Secret<String>>,
+}
+
+impl TrackingSettings {
+    /// Refuse tracking without a key: anyone could then sign their own links
+    /// and use the click redirect to send people anywhere.
+    pub fn validate(&self) -> Result<(), String> {
+        let has_key = self
+            .signing_key
+            .as_ref()
+            .is_some_and(|key| !key.expose_secret().trim().is_empty());
+        if (self.open_tracking || self.click_tracking) && !has_key {
+            return Err("Tracking is on but no tracking.signing_key is set.".into());
+        }
+        Ok(())
+    }
+
+    pub fn tracker(self) -> Tracker {
+        Tracker::new(self.signing_key, self.open_tracking, self.click_tracking)
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct AdminSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// How the email provider's webhooks reach us.
+#[derive(Clone, serde::Deserialize)]
+pub struct WebhookSettings {
+    /// Basic auth credentials configured on the provider's webhook
+    pub username: String,
+    pub password: Secret<String>,
+    /// Soft bounces after which an address is treated as hard-bounced
+    pub soft_bounce_limit: i32,
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -46,6 +174,24 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> Duration {
         Duration::from_millis(self.timeout_milliseconds)
     }
+
+    /// Relative URLs in HTML bodies are resolved against `base_url`, the
+    /// application's public URL.
+    pub fn client(self, base_url: &str) -> EmailClient {
+        let sender_email = self
+            .sender()
+            .expect("Failed to parse sender email address.");
+        let timeout = self.timeout();
+        let html_base_url =
+            Url::parse(base_url).expect("Failed to parse the application base URL.");
+        EmailClient::new(
+            self.base_url,
+            sender_email,
+            self.authorization_token,
+            timeout,
+            Some(html_base_url),
+        )
+    }
 }
 
 pub enum Environment {
@@ -105,7 +251,12 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .build()
         .expect("Failed to load configuration files.");
 
-    settings.try_deserialize()
+    let settings: Settings = settings.try_deserialize()?;
+    settings
+        .tracking
+        .validate()
+        .map_err(config::ConfigError::Message)?;
+    Ok(settings)
 }
 
 impl DatabaseSettings {
@@ -0,0 +1,564 @@
+use crate::activity::record_activity;
+use crate::domain::{
+    local_instant, ActivityKind, DeliveryFrequency, EmailEventKind, SubscriberEmail,
+    SubscriberTimeZone,
+};
+use crate::email_client::{EmailClient, ProviderResponse};
+use crate::email_templates::{EmailTemplates, RenderedEmail};
+use crate::routes::{
+    archive_url, issue_segment, push_segment, render_newsletter_email, store_event,
+    unsubscribe_url, NewsletterLinks,
+};
+use crate::suppression::suppression_reason;
+use crate::tracking::{inject_open_pixel, Tracker};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{Acquire, PgPool, Postgres, QueryBuilder, Transaction};
+use tera::escape_html;
+use uuid::Uuid;
+
+/// Attempts per email before it is dropped from the queue
+const MAX_RETRIES: i16 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct Task {
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    n_retries: i16,
+}
+
+/// What came of trying to send the email for a task.
+enum Attempt {
+    Sent(ProviderResponse),
+    /// Not sent, and not to be retried, for the given reason
+    Skipped(String),
+    Failed(reqwest::Error),
+}
+
+/// Send the next due email from `issue_delivery_queue`, if any.
+///
+/// The row stays locked while the email is sent, so other workers skip it and
+/// it is only removed once the send went through. Whatever goes wrong once it
+/// is dequeued, the task is put back with a backoff rather than left at the
+/// head of the queue.
+#[tracing::instrument(
+    skip_all,
+    fields(issue_id = tracing::field::Empty, subscriber_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    tracker: &Tracker,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = match dequeue_task(&mut transaction).await? {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    tracing::Span::current()
+        .record("issue_id", tracing::field::display(task.issue_id))
+        .record("subscriber_id", tracing::field::display(task.subscriber_id));
+
+    let mut tasks = vec![task];
+    let attempt = attempt_delivery(
+        &mut transaction,
+        pool,
+        email_client,
+        templates,
+        tracker,
+        base_url,
+        &tasks[0],
+    )
+    .await;
+    let (skipped, sent) = match attempt {
+        Ok((others, attempt)) => {
+            tasks.extend(others);
+            match attempt {
+                Attempt::Sent(response) => (None, Some(response)),
+                Attempt::Skipped(reason) => (Some(reason), None),
+                Attempt::Failed(e) => return retry_tasks(transaction, &tasks, e.into()).await,
+            }
+        }
+        Err(e) => return retry_tasks(transaction, &tasks, e).await,
+    };
+    if let Err(e) = finish_tasks(&mut transaction, &tasks, skipped.as_deref()).await {
+        return retry_tasks(transaction, &tasks, e.into()).await;
+    }
+    transaction.commit().await?;
+    // Only once the tasks are gone, so that a failure here can't resend them
+    if let Some(response) = sent {
+        let detail = response.to_string();
+        for task in &tasks {
+            let _ = store_event(
+                pool,
+                task.issue_id,
+                task.subscriber_id,
+                EmailEventKind::Sent,
+                None,
+                None,
+            )
+            .await;
+            let _ = record_activity(
+                pool,
+                task.subscriber_id,
+                ActivityKind::DeliveryAttempt,
+                Some(task.issue_id),
+                None,
+                Some(&detail),
+            )
+            .await;
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Render and send the email for `task`, along with the other issues due in
+/// the same digest, which are returned.
+///
+/// Runs in a savepoint, so that an error rolls back only what this did and
+/// the task can still be retried in `transaction`. Digest tasks are then left
+/// in the queue as they were.
+#[allow(clippy::too_many_arguments)]
+async fn attempt_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    tracker: &Tracker,
+    base_url: &str,
+    task: &Task,
+) -> Result<(Vec<Task>, Attempt), anyhow::Error> {
+    let mut savepoint = transaction.begin().await?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, ls.status, s.frequency, s.paused_until, s.tracking_opt_out,
+            (SELECT t.subscription_token FROM subscription_tokens t
+             WHERE t.subscriber_id = s.id AND t.list_id = ls.list_id
+             LIMIT 1) AS subscription_token
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.subscriber_id = $1 AND ls.list_id = $2
+        "#,
+        task.subscriber_id,
+        task.list_id
+    )
+    .fetch_one(&mut savepoint)
+    .await?;
+
+    // Subscribers who left or paused after the issue was queued don't get it
+    let is_paused = subscriber
+        .paused_until
+        .is_some_and(|paused_until| paused_until > Utc::now());
+    // The last check before sending, whatever put the address on a list
+    let suppression = suppression_reason(&mut savepoint, &subscriber.email).await?;
+    let mut others = Vec::new();
+    let attempt = match (subscriber.status.as_str(), subscriber.subscription_token) {
+        ("confirmed", _) if is_paused => {
+            tracing::info!("Skipping a subscriber who paused delivery");
+            Attempt::Skipped("Delivery is paused".to_string())
+        }
+        ("confirmed", Some(_)) if suppression.is_some() => {
+            tracing::info!("Skipping a subscriber whose address is suppressed");
+            let reason = suppression.map_or("", |reason| reason.as_str());
+            Attempt::Skipped(format!("Address suppressed after a {}", reason))
+        }
+        ("confirmed", Some(subscription_token)) => match SubscriberEmail::parse(subscriber.email) {
+            Ok(recipient) => {
+                let tracker = (!subscriber.tracking_opt_out).then_some(tracker);
+                let email = match subscriber.frequency.as_str() {
+                    "weekly_digest" => {
+                        others = dequeue_digest_tasks(&mut savepoint, task).await?;
+                        let issue_ids: Vec<Uuid> = std::iter::once(task)
+                            .chain(&others)
+                            .map(|task| task.issue_id)
+                            .collect();
+                        render_digest_email(
+                            &mut savepoint,
+                            templates,
+                            pool,
+                            base_url,
+                            task.subscriber_id,
+                            &issue_ids,
+                            subscriber.name,
+                            &subscription_token,
+                            tracker,
+                        )
+                        .await?
+                    }
+                    _ => {
+                        let issue = sqlx::query!(
+                            r#"
+                            SELECT published_title AS "title!", published_html AS "html!",
+                                slug AS "slug!"
+                            FROM newsletter_issues
+                            WHERE id = $1
+                            "#,
+                            task.issue_id
+                        )
+                        .fetch_one(&mut savepoint)
+                        .await?;
+                        let mut email = render_newsletter_email(
+                            templates,
+                            pool,
+                            &issue.title,
+                            &issue.html,
+                            subscriber.name,
+                            NewsletterLinks::new(
+                                base_url,
+                                &subscription_token,
+                                archive_url(base_url, &issue.slug),
+                            ),
+                        )
+                        .await?;
+                        if let Some(tracker) = tracker {
+                            email.html = tracker.track_email(
+                                &email.html,
+                                base_url,
+                                task.issue_id,
+                                task.subscriber_id,
+                            );
+                        }
+                        email
+                    }
+                };
+                match email_client
+                    .send_list_email(
+                        recipient,
+                        &email.subject,
+                        &email.html,
+                        &email.text,
+                        &unsubscribe_url(base_url, &subscription_token),
+                    )
+                    .await
+                {
+                    Ok(response) => Attempt::Sent(response),
+                    Err(e) => Attempt::Failed(e),
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping a confirmed subscriber with an invalid email: {}",
+                    e
+                );
+                Attempt::Skipped("Invalid email address".to_string())
+            }
+        },
+        (status, _) => {
+            tracing::info!("Skipping a subscriber who is now {}", status);
+            Attempt::Skipped(format!("Subscription is {}", status))
+        }
+    };
+    savepoint.commit().await?;
+    Ok((others, attempt))
+}
+
+/// Remove tasks that were sent or skipped from the queue, recording why the
+/// skipped ones were.
+async fn finish_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    tasks: &[Task],
+    skipped: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut savepoint = transaction.begin().await?;
+    for task in tasks {
+        delete_task(&mut savepoint, task).await?;
+        if let Some(skipped) = skipped {
+            record_activity(
+                &mut savepoint,
+                task.subscriber_id,
+                ActivityKind::DeliverySkipped,
+                Some(task.issue_id),
+                None,
+                Some(skipped),
+            )
+            .await?;
+        }
+    }
+    savepoint.commit().await
+}
+
+/// The other issues due for the same subscriber, to send in one digest.
+async fn dequeue_digest_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+) -> Result<Vec<Task>, sqlx::Error> {
+    sqlx::query_as!(
+        Task,
+        r#"
+        SELECT issue_id, subscriber_id, list_id, n_retries
+        FROM issue_delivery_queue
+        WHERE subscriber_id = $1 AND issue_id <> $2 AND execute_after <= now()
+        FOR UPDATE SKIP LOCKED
+        "#,
+        task.subscriber_id,
+        task.issue_id
+    )
+    .fetch_all(transaction)
+    .await
+}
+
+/// Every issue in a weekly digest, oldest first, in a single email. Clicks
+/// and opens are tracked for each issue on its own.
+#[allow(clippy::too_many_arguments)]
+async fn render_digest_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    base_url: &str,
+    subscriber_id: Uuid,
+    issue_ids: &[Uuid],
+    subscriber_name: String,
+    subscription_token: &str,
+    tracker: Option<&Tracker>,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let issues = sqlx::query!(
+        r#"
+        SELECT id, published_title AS "title!", published_html AS "html!", slug AS "slug!"
+        FROM newsletter_issues
+        WHERE id = ANY($1)
+        ORDER BY published_at
+        "#,
+        issue_ids
+    )
+    .fetch_all(transaction)
+    .await?;
+    let content: String = issues
+        .iter()
+        .map(|issue| {
+            let section = format!(
+                "<h2><a href=\"{}\">{}</a></h2>\n{}\n",
+                archive_url(base_url, &issue.slug),
+                escape_html(&issue.title),
+                issue.html
+            );
+            match tracker {
+                Some(tracker) => tracker.track_links(&section, base_url, issue.id, subscriber_id),
+                None => section,
+            }
+        })
+        .collect();
+    let mut email = render_newsletter_email(
+        templates,
+        pool,
+        "Your weekly digest",
+        &content,
+        subscriber_name,
+        NewsletterLinks::new(
+            base_url,
+            subscription_token,
+            format!("{}/archive", base_url),
+        ),
+    )
+    .await?;
+    if let Some(tracker) = tracker {
+        for issue in &issues {
+            if let Some(pixel_url) = tracker.open_pixel_url(base_url, issue.id, subscriber_id) {
+                email.html = inject_open_pixel(&email.html, &pixel_url);
+            }
+        }
+    }
+    Ok(email)
+}
+
+async fn dequeue_task(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Task>, sqlx::Error> {
+    sqlx::query_as!(
+        Task,
+        r#"
+        SELECT issue_id, subscriber_id, list_id, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+/// Put the tasks of a failed attempt back in the queue with an exponential
+/// backoff, or drop those that have failed too many times.
+async fn retry_tasks(
+    mut transaction: Transaction<'_, Postgres>,
+    tasks: &[Task],
+    error: anyhow::Error,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    for task in tasks {
+        let detail = format!(
+            "Failed: {} (attempt {} of {})",
+            error,
+            task.n_retries + 1,
+            MAX_RETRIES
+        );
+        record_activity(
+            &mut transaction,
+            task.subscriber_id,
+            ActivityKind::DeliveryAttempt,
+            Some(task.issue_id),
+            None,
+            Some(&detail),
+        )
+        .await?;
+        if task.n_retries + 1 >= MAX_RETRIES {
+            tracing::error!(
+                "Giving up on delivering a newsletter issue after {} attempts: {:?}",
+                MAX_RETRIES,
+                error
+            );
+            delete_task(&mut transaction, task).await?;
+        } else {
+            tracing::warn!(
+                "Failed to deliver a newsletter issue, retrying later: {:?}",
+                error
+            );
+            let backoff = chrono::Duration::minutes(1 << task.n_retries);
+            sqlx::query!(
+                r#"
+                UPDATE issue_delivery_queue
+                SET n_retries = n_retries + 1, execute_after = $3
+                WHERE issue_id = $1 AND subscriber_id = $2
+                "#,
+                task.issue_id,
+                task.subscriber_id,
+                Utc::now() + backoff
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn delete_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE issue_id = $1 AND subscriber_id = $2",
+        task.issue_id,
+        task.subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Queue an issue once for every subscriber currently confirmed on one of
+/// its lists, unless they paused delivery. Those who take the weekly digest
+/// get it with their next one.
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let segment = issue_segment(transaction, issue_id).await?;
+    let now = Utc::now();
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_delivery_queue (issue_id, subscriber_id, list_id, execute_after)
+        SELECT DISTINCT ON (s.id) il.issue_id, s.id, ls.list_id,
+            CASE WHEN s.frequency = 'weekly_digest' THEN "#,
+    );
+    query
+        .push_bind(DeliveryFrequency::WeeklyDigest.next_delivery(now))
+        .push(" ELSE ")
+        .push_bind(now)
+        .push(
+            r#" END
+        FROM newsletter_issue_lists il
+        JOIN list_subscriptions ls ON ls.list_id = il.list_id
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.status = 'confirmed' AND il.issue_id = "#,
+        )
+        .push_bind(issue_id)
+        .push(" AND (s.paused_until IS NULL OR s.paused_until <= ")
+        .push_bind(now)
+        .push(")");
+    if let Some(segment) = &segment {
+        query.push(" AND ");
+        push_segment(&mut query, segment);
+    }
+    query.push(" ORDER BY s.id, ls.list_id");
+    let result = query.build().execute(transaction).await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(sqlx::FromRow)]
+struct QueuedSubscriber {
+    id: Uuid,
+    list_id: Uuid,
+    time_zone: Option<String>,
+    frequency: String,
+}
+
+/// Queue an issue once for every subscriber currently confirmed on one of
+/// its lists, each due when
+/// `local_send_time` comes round in their time zone, or in `fallback` for
+/// subscribers whose zone is unknown. Zones already past it get it now, and
+/// weekly digests go out as usual.
+pub async fn enqueue_local_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    local_send_time: NaiveDateTime,
+    fallback: Tz,
+) -> Result<u64, sqlx::Error> {
+    let segment = issue_segment(transaction, issue_id).await?;
+    let mut query = QueryBuilder::new(
+        r#"
+        SELECT DISTINCT ON (s.id) s.id, ls.list_id, s.time_zone, s.frequency
+        FROM newsletter_issue_lists il
+        JOIN list_subscriptions ls ON ls.list_id = il.list_id
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+            AND il.issue_id = "#,
+    );
+    query.push_bind(issue_id);
+    if let Some(segment) = &segment {
+        query.push(" AND ");
+        push_segment(&mut query, segment);
+    }
+    query.push(" ORDER BY s.id, ls.list_id");
+    let subscribers: Vec<QueuedSubscriber> =
+        query.build_query_as().fetch_all(&mut *transaction).await?;
+    let now = Utc::now();
+    let mut subscriber_ids = Vec::with_capacity(subscribers.len());
+    let mut list_ids = Vec::with_capacity(subscribers.len());
+    let mut execute_after: Vec<DateTime<Utc>> = Vec::with_capacity(subscribers.len());
+    for subscriber in subscribers {
+        let time_zone = subscriber
+            .time_zone
+            .and_then(|time_zone| SubscriberTimeZone::parse(time_zone).ok())
+            .map_or(fallback, |time_zone| time_zone.tz());
+        subscriber_ids.push(subscriber.id);
+        list_ids.push(subscriber.list_id);
+        execute_after.push(match subscriber.frequency.as_str() {
+            "weekly_digest" => DeliveryFrequency::WeeklyDigest.next_delivery(now),
+            _ => local_instant(local_send_time, time_zone).max(now),
+        });
+    }
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (issue_id, subscriber_id, list_id, execute_after)
+        SELECT $1, * FROM UNNEST($2::uuid[], $3::uuid[], $4::timestamptz[])
+        "#,
+        issue_id,
+        &subscriber_ids,
+        &list_ids,
+        &execute_after
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
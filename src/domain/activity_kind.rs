@@ -0,0 +1,94 @@
+/// An entry on a subscriber's activity timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    SignedUp,
+    /// Sending the confirmation email, whether or not the provider took it
+    ConfirmationEmail,
+    Confirmed,
+    /// Sending an issue, whether or not the provider took it
+    DeliveryAttempt,
+    /// An issue the subscriber was due but was not sent, and why
+    DeliverySkipped,
+    /// The provider reported that the recipient's server took an email
+    Delivered,
+    Open,
+    Click,
+    Bounce,
+    Complaint,
+    PreferenceChange,
+    Unsubscribe,
+}
+
+impl ActivityKind {
+    pub fn parse(kind: String) -> Result<Self, String> {
+        match kind.as_str() {
+            "signed_up" => Ok(Self::SignedUp),
+            "confirmation_email" => Ok(Self::ConfirmationEmail),
+            "confirmed" => Ok(Self::Confirmed),
+            "delivery_attempt" => Ok(Self::DeliveryAttempt),
+            "delivery_skipped" => Ok(Self::DeliverySkipped),
+            "delivered" => Ok(Self::Delivered),
+            "open" => Ok(Self::Open),
+            "click" => Ok(Self::Click),
+            "bounce" => Ok(Self::Bounce),
+            "complaint" => Ok(Self::Complaint),
+            "preference_change" => Ok(Self::PreferenceChange),
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            other => Err(format!("Invalid activity kind: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SignedUp => "signed_up",
+            Self::ConfirmationEmail => "confirmation_email",
+            Self::Confirmed => "confirmed",
+            Self::DeliveryAttempt => "delivery_attempt",
+            Self::DeliverySkipped => "delivery_skipped",
+            Self::Delivered => "delivered",
+            Self::Open => "open",
+            Self::Click => "click",
+            Self::Bounce => "bounce",
+            Self::Complaint => "complaint",
+            Self::PreferenceChange => "preference_change",
+            Self::Unsubscribe => "unsubscribe",
+        }
+    }
+}
+
+impl AsRef<str> for ActivityKind {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ActivityKind;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_kinds_round_trip() {
+        for kind in [
+            ActivityKind::SignedUp,
+            ActivityKind::ConfirmationEmail,
+            ActivityKind::Confirmed,
+            ActivityKind::DeliveryAttempt,
+            ActivityKind::DeliverySkipped,
+            ActivityKind::Delivered,
+            ActivityKind::Open,
+            ActivityKind::Click,
+            ActivityKind::Bounce,
+            ActivityKind::Complaint,
+            ActivityKind::PreferenceChange,
+            ActivityKind::Unsubscribe,
+        ] {
+            assert_ok_eq!(ActivityKind::parse(kind.as_str().into()), kind);
+        }
+    }
+
+    #[test]
+    fn unknown_kind_is_rejected() {
+        assert_err!(ActivityKind::parse("forwarded".into()));
+    }
+}
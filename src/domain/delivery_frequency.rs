@@ -0,0 +1,99 @@
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc};
+
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    /// Issues are held back and sent together on Monday mornings (UTC)
+    WeeklyDigest,
+}
+
+impl DeliveryFrequency {
+    pub fn parse(frequency: String) -> Result<Self, String> {
+        match frequency.as_str() {
+            "every_issue" => Ok(Self::EveryIssue),
+            "weekly_digest" => Ok(Self::WeeklyDigest),
+            other => Err(format!("Invalid delivery frequency: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::EveryIssue => "every_issue",
+            Self::WeeklyDigest => "weekly_digest",
+        }
+    }
+
+    /// When an issue published at `now` should go out.
+    pub fn next_delivery(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::EveryIssue => now,
+            Self::WeeklyDigest => {
+                let digest_time = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+                let monday =
+                    now.date_naive() - Duration::days(now.weekday().num_days_from_monday().into());
+                let this_week = monday.and_time(digest_time).and_utc();
+                match this_week > now {
+                    true => this_week,
+                    false => this_week + Duration::weeks(1),
+                }
+            }
+        }
+    }
+}
+
+impl AsRef<str> for DeliveryFrequency {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryFrequency;
+    use chrono::{DateTime, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    fn instant(rfc3339: &str) -> DateTime<Utc> {
+        rfc3339.parse().unwrap()
+    }
+
+    #[test]
+    fn known_frequencies_round_trip() {
+        for frequency in [
+            DeliveryFrequency::EveryIssue,
+            DeliveryFrequency::WeeklyDigest,
+        ] {
+            assert_ok_eq!(
+                DeliveryFrequency::parse(frequency.as_str().into()),
+                frequency
+            );
+        }
+        assert_err!(DeliveryFrequency::parse("daily".into()));
+    }
+
+    #[test]
+    fn weekly_digests_go_out_on_the_next_monday_morning() {
+        let digest = DeliveryFrequency::WeeklyDigest;
+        // A Wednesday
+        assert_eq!(
+            digest.next_delivery(instant("2023-04-05T15:30:00Z")),
+            instant("2023-04-10T09:00:00Z")
+        );
+        // Monday, before and at the digest time
+        assert_eq!(
+            digest.next_delivery(instant("2023-04-10T08:59:00Z")),
+            instant("2023-04-10T09:00:00Z")
+        );
+        assert_eq!(
+            digest.next_delivery(instant("2023-04-10T09:00:00Z")),
+            instant("2023-04-17T09:00:00Z")
+        );
+    }
+
+    #[test]
+    fn other_issues_go_out_right_away() {
+        let now = instant("2023-04-05T15:30:00Z");
+        assert_eq!(DeliveryFrequency::EveryIssue.next_delivery(now), now);
+    }
+}
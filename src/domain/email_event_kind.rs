@@ -0,0 +1,78 @@
+/// What happened to an issue sent to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailEventKind {
+    Sent,
+    Open,
+    Click,
+    Unsubscribe,
+    /// The email could not be delivered
+    Bounce,
+    /// The email was turned away for now, and may get through later
+    SoftBounce,
+    /// The subscriber reported the email as spam
+    Complaint,
+}
+
+impl EmailEventKind {
+    pub fn parse(kind: String) -> Result<Self, String> {
+        match kind.as_str() {
+            "sent" => Ok(Self::Sent),
+            "open" => Ok(Self::Open),
+            "click" => Ok(Self::Click),
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            "bounce" => Ok(Self::Bounce),
+            "soft_bounce" => Ok(Self::SoftBounce),
+            "complaint" => Ok(Self::Complaint),
+            other => Err(format!("Invalid email event kind: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Open => "open",
+            Self::Click => "click",
+            Self::Unsubscribe => "unsubscribe",
+            Self::Bounce => "bounce",
+            Self::SoftBounce => "soft_bounce",
+            Self::Complaint => "complaint",
+        }
+    }
+
+    /// Events that subscribers can opt out of being tracked for.
+    pub fn is_engagement(&self) -> bool {
+        matches!(self, Self::Open | Self::Click)
+    }
+}
+
+impl AsRef<str> for EmailEventKind {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailEventKind;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_kinds_round_trip() {
+        for kind in [
+            EmailEventKind::Sent,
+            EmailEventKind::Open,
+            EmailEventKind::Click,
+            EmailEventKind::Unsubscribe,
+            EmailEventKind::Bounce,
+            EmailEventKind::SoftBounce,
+            EmailEventKind::Complaint,
+        ] {
+            assert_ok_eq!(EmailEventKind::parse(kind.as_str().into()), kind);
+        }
+    }
+
+    #[test]
+    fn unknown_kind_is_rejected() {
+        assert_err!(EmailEventKind::parse("forwarded".into()));
+    }
+}
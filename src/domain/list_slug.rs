@@ -0,0 +1,56 @@
+/// How a mailing list is named in URLs and forms, e.g. `release-notes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(slug: String) -> Result<Self, String> {
+        let is_valid = !slug.is_empty()
+            && slug.len() <= 64
+            && slug.split('-').all(|word| {
+                !word.is_empty()
+                    && word
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            });
+        match is_valid {
+            true => Ok(Self(slug)),
+            false => Err(format!(
+                "Invalid list slug: {}. Use lowercase letters and digits separated by single dashes.",
+                slug
+            )),
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn dashed_lowercase_words_are_valid() {
+        assert_ok!(ListSlug::parse("default".into()));
+        assert_ok!(ListSlug::parse("release-notes-2023".into()));
+    }
+
+    #[test]
+    fn other_slugs_are_rejected() {
+        for slug in [
+            "",
+            "Release",
+            "release notes",
+            "-release",
+            "release--notes",
+            "release/notes",
+        ] {
+            assert_err!(ListSlug::parse(slug.into()));
+        }
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+}
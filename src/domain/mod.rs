@@ -1,7 +1,29 @@
+mod activity_kind;
+mod delivery_frequency;
+mod email_event_kind;
+mod list_slug;
 mod new_subscriber;
+mod segment;
+mod send_time;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_record;
+mod subscriber_tag;
+mod subscriber_time_zone;
+mod subscription_status;
 
+pub use activity_kind::ActivityKind;
+pub use delivery_frequency::DeliveryFrequency;
+pub use email_event_kind::EmailEventKind;
+pub use list_slug::ListSlug;
 pub use new_subscriber::NewSubscriber;
+pub use segment::{Comparison, Condition, Segment, TextField};
+pub use send_time::{local_instant, SendTime};
+pub use subscriber_attributes::{AttributeKind, AttributeRule, SubscriberAttributes};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_record::SubscriberRecord;
+pub use subscriber_tag::SubscriberTag;
+pub use subscriber_time_zone::SubscriberTimeZone;
+pub use subscription_status::SubscriptionStatus;
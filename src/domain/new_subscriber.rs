@@ -1,8 +1,14 @@
+use crate::domain::subscriber_attributes::SubscriberAttributes;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::domain::subscriber_time_zone::SubscriberTimeZone;
 
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub time_zone: Option<SubscriberTimeZone>,
+    pub tags: Vec<SubscriberTag>,
+    pub attributes: SubscriberAttributes,
 }
@@ -0,0 +1,425 @@
+use crate::domain::SubscriberTag;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+const MAX_LENGTH: usize = 2000;
+const MAX_DEPTH: usize = 32;
+
+/// A subset of subscribers, written in a small query language:
+///
+/// ```text
+/// tag:rust AND subscribed_at > 2023-01-01 AND NOT attr.country = "BR"
+/// ```
+///
+/// Conditions test a tag (`tag:<tag>`), a column (`email`, `name`,
+/// `time_zone`, `subscribed_at`) or a custom attribute (`attr.<name>`) with
+/// one of `=`, `!=`, `<`, `<=`, `>`, `>=`. They combine with `AND`, `OR`,
+/// `NOT` and parentheses. A condition on a missing attribute is false.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+    Not(Box<Segment>),
+    Condition(Condition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Tag(SubscriberTag),
+    SubscribedAt(Comparison, DateTime<Utc>),
+    Text(TextField, Comparison, String),
+    Attribute(String, Comparison, Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    Email,
+    Name,
+    TimeZone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "<>",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Segment {
+    pub fn parse(definition: &str) -> Result<Self, String> {
+        if definition.len() > MAX_LENGTH {
+            return Err(format!(
+                "The segment is longer than {} characters.",
+                MAX_LENGTH
+            ));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(definition)?,
+            position: 0,
+            depth: 0,
+        };
+        let segment = parser.or()?;
+        match parser.next() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {}.", token)),
+        }
+    }
+
+    /// The custom attributes the segment looks at, with the value each
+    /// condition compares them to.
+    pub fn attribute_conditions(&self) -> Vec<(&str, &Value)> {
+        match self {
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let mut conditions = left.attribute_conditions();
+                conditions.extend(right.attribute_conditions());
+                conditions
+            }
+            Segment::Not(segment) => segment.attribute_conditions(),
+            Segment::Condition(Condition::Attribute(name, _, value)) => {
+                vec![(name.as_str(), value)]
+            }
+            Segment::Condition(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    Operator(Comparison),
+    Quoted(String),
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::OpenParen => write!(f, "`(`"),
+            Token::CloseParen => write!(f, "`)`"),
+            Token::Operator(comparison) => write!(f, "`{}`", comparison.as_sql()),
+            Token::Quoted(text) => write!(f, "\"{}\"", text),
+            Token::Word(word) => write!(f, "`{}`", word),
+        }
+    }
+}
+
+fn tokenize(definition: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = definition.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '=' => Token::Operator(Comparison::Equal),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Operator(Comparison::NotEqual),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Operator(Comparison::LessOrEqual),
+            '<' => Token::Operator(Comparison::Less),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Operator(Comparison::GreaterOrEqual),
+            '>' => Token::Operator(Comparison::Greater),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => text.push(escaped),
+                            None => return Err("Unterminated string.".into()),
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err("Unterminated string.".into()),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            c if is_word_char(c) => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| is_word_char(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            c => return Err(format!("Unexpected character `{}`.", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '+')
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Segment, String> {
+        let mut segment = self.and()?;
+        while self.next_is_keyword("OR") {
+            segment = Segment::Or(Box::new(segment), Box::new(self.and()?));
+        }
+        Ok(segment)
+    }
+
+    fn and(&mut self) -> Result<Segment, String> {
+        let mut segment = self.not()?;
+        while self.next_is_keyword("AND") {
+            segment = Segment::And(Box::new(segment), Box::new(self.not()?));
+        }
+        Ok(segment)
+    }
+
+    fn not(&mut self) -> Result<Segment, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply.".into());
+        }
+        let segment = match self.next_is_keyword("NOT") {
+            true => Segment::Not(Box::new(self.not()?)),
+            false => self.term()?,
+        };
+        self.depth -= 1;
+        Ok(segment)
+    }
+
+    fn term(&mut self) -> Result<Segment, String> {
+        match self.next() {
+            Some(Token::OpenParen) => {
+                let segment = self.or()?;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(segment),
+                    _ => Err("Missing `)`.".into()),
+                }
+            }
+            Some(Token::Word(word)) => self.condition(word).map(Segment::Condition),
+            Some(token) => Err(format!("Expected a condition, found {}.", token)),
+            None => Err("Expected a condition, found the end of the segment.".into()),
+        }
+    }
+
+    fn condition(&mut self, field: String) -> Result<Condition, String> {
+        if let Some(tag) = field.strip_prefix("tag:") {
+            return SubscriberTag::parse(tag.into()).map(Condition::Tag);
+        }
+        let comparison = match self.next() {
+            Some(Token::Operator(comparison)) => comparison,
+            _ => return Err(format!("Expected a comparison after `{}`.", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Quoted(text)) => Ok(text),
+            Some(Token::Word(word)) => Err(word),
+            _ => return Err(format!("Expected a value after `{}`.", field)),
+        };
+        let text_field = match field.as_str() {
+            "email" => Some(TextField::Email),
+            "name" => Some(TextField::Name),
+            "time_zone" => Some(TextField::TimeZone),
+            _ => None,
+        };
+        if let Some(text_field) = text_field {
+            return match value {
+                Ok(text) => Ok(Condition::Text(text_field, comparison, text)),
+                Err(word) => Err(format!("Quote the value `{}` of `{}`.", word, field)),
+            };
+        }
+        if field == "subscribed_at" {
+            let value = value.unwrap_or_else(|word| word);
+            return parse_instant(&value)
+                .map(|instant| Condition::SubscribedAt(comparison, instant))
+                .ok_or_else(|| format!("Invalid date: {}. Use e.g. 2023-01-31.", value));
+        }
+        if let Some(name) = field.strip_prefix("attr.").filter(|name| !name.is_empty()) {
+            let value = match value {
+                Ok(text) => Value::String(text),
+                Err(word) => match word.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => word
+                        .parse::<serde_json::Number>()
+                        .map(Value::Number)
+                        .map_err(|_| format!("Quote the value `{}` of `{}`.", word, field))?,
+                },
+            };
+            return Ok(Condition::Attribute(name.into(), comparison, value));
+        }
+        Err(format!("Unknown field: {}.", field))
+    }
+}
+
+fn parse_instant(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(instant) = DateTime::parse_from_rfc3339(value) {
+        return Some(instant.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparison, Condition, Segment, TextField};
+    use crate::domain::SubscriberTag;
+    use chrono::{TimeZone, Utc};
+    use claims::assert_err;
+    use serde_json::json;
+
+    fn tag(tag: &str) -> Segment {
+        Segment::Condition(Condition::Tag(SubscriberTag::parse(tag.into()).unwrap()))
+    }
+
+    #[test]
+    fn the_example_from_the_docs_parses() {
+        let segment = Segment::parse(
+            r#"tag:rust AND subscribed_at > 2023-01-01 AND NOT attr.country = "BR""#,
+        )
+        .unwrap();
+
+        let since = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(
+                Box::new(Segment::And(
+                    Box::new(tag("rust")),
+                    Box::new(Segment::Condition(Condition::SubscribedAt(
+                        Comparison::Greater,
+                        since
+                    )))
+                )),
+                Box::new(Segment::Not(Box::new(Segment::Condition(
+                    Condition::Attribute("country".into(), Comparison::Equal, json!("BR"))
+                ))))
+            )
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = Segment::parse("tag:a or tag:b AND tag:c").unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::Or(
+                Box::new(tag("a")),
+                Box::new(Segment::And(Box::new(tag("b")), Box::new(tag("c"))))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_group_conditions() {
+        let segment = Segment::parse("(tag:a OR tag:b) AND tag:c").unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::And(
+                Box::new(Segment::Or(Box::new(tag("a")), Box::new(tag("b")))),
+                Box::new(tag("c"))
+            )
+        );
+    }
+
+    #[test]
+    fn attribute_values_keep_their_type() {
+        let segment = Segment::parse("attr.seats >= 10 AND attr.trial != true").unwrap();
+
+        assert_eq!(
+            segment.attribute_conditions(),
+            vec![("seats", &json!(10)), ("trial", &json!(true))]
+        );
+        let expected = Segment::And(
+            Box::new(Segment::Condition(Condition::Attribute(
+                "seats".into(),
+                Comparison::GreaterOrEqual,
+                json!(10),
+            ))),
+            Box::new(Segment::Condition(Condition::Attribute(
+                "trial".into(),
+                Comparison::NotEqual,
+                json!(true),
+            ))),
+        );
+        assert_eq!(segment, expected);
+    }
+
+    #[test]
+    fn quoted_strings_can_hold_anything() {
+        let segment = Segment::parse(r#"name = "Ursula \"le\" Guin AND (""#).unwrap();
+
+        assert_eq!(
+            segment,
+            Segment::Condition(Condition::Text(
+                TextField::Name,
+                Comparison::Equal,
+                r#"Ursula "le" Guin AND ("#.into()
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for definition in [
+            "",
+            "tag:",
+            "tag:a AND",
+            "tag:a tag:b",
+            "(tag:a",
+            "tag:a)",
+            "country = \"BR\"",
+            "attr. = 1",
+            "email = ursula",
+            "email ~ \"ursula\"",
+            "subscribed_at > yesterday",
+            "attr.country = BR",
+            "name = \"unterminated",
+            "tag:a; DROP TABLE subscriptions",
+        ] {
+            assert_err!(Segment::parse(definition), "{} was accepted", definition);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let definition = format!("{}tag:a{}", "(".repeat(100), ")".repeat(100));
+
+        assert_err!(Segment::parse(&definition));
+        assert_err!(Segment::parse(&"NOT ".repeat(100)));
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Furthest ahead of UTC any time zone runs (Pacific/Kiritimati), i.e. where
+/// a given wall-clock time comes round first
+const MAX_UTC_OFFSET_HOURS: i64 = 14;
+
+/// When to send an issue, entered as wall-clock time in a named time zone,
+/// e.g. "2023-03-21T09:00" in "America/Sao_Paulo".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendTime {
+    at: DateTime<Utc>,
+    local_time: NaiveDateTime,
+    time_zone: Tz,
+}
+
+impl SendTime {
+    /// `now` is passed in so that times in the past can be rejected.
+    pub fn parse(local_time: &str, time_zone: &str, now: DateTime<Utc>) -> Result<Self, String> {
+        let time_zone: Tz = time_zone
+            .parse()
+            .map_err(|_| format!("Unknown time zone: {}", time_zone))?;
+        let local_time = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(local_time, format).ok())
+            .ok_or_else(|| {
+                format!(
+                    "Invalid send time, expected YYYY-MM-DDTHH:MM: {}",
+                    local_time
+                )
+            })?;
+        let at = match time_zone.from_local_datetime(&local_time) {
+            LocalResult::Single(at) => at.with_timezone(&Utc),
+            LocalResult::None => {
+                return Err(format!(
+                    "{} does not exist in {}, the clocks skip it.",
+                    local_time, time_zone
+                ))
+            }
+            LocalResult::Ambiguous(..) => {
+                return Err(format!(
+                    "{} happens twice in {}, the clocks go back over it.",
+                    local_time, time_zone
+                ))
+            }
+        };
+        if at <= now {
+            return Err(format!("{} in {} is in the past.", local_time, time_zone));
+        }
+        Ok(Self {
+            at,
+            local_time,
+            time_zone,
+        })
+    }
+
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+
+    pub fn time_zone(&self) -> &'static str {
+        self.time_zone.name()
+    }
+
+    pub fn local_time(&self) -> NaiveDateTime {
+        self.local_time
+    }
+
+    /// The first moment the local time comes round anywhere, which is when
+    /// delivery in each subscriber's own time zone has to start.
+    pub fn first_wave(&self) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&(self.local_time - Duration::hours(MAX_UTC_OFFSET_HOURS)))
+    }
+}
+
+/// When `local_time` happens in `time_zone`. Unlike [`SendTime::parse`] this
+/// never fails: a time skipped by a DST change moves past the gap, and a
+/// repeated one is taken the first time round.
+pub fn local_instant(local_time: NaiveDateTime, time_zone: Tz) -> DateTime<Utc> {
+    let mut candidate = local_time;
+    loop {
+        match time_zone.from_local_datetime(&candidate) {
+            LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => {
+                return at.with_timezone(&Utc)
+            }
+            LocalResult::None => candidate += Duration::minutes(30),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{local_instant, SendTime};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use claims::assert_err;
+
+    fn now() -> chrono::DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 3, 20, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn local_time_is_converted_to_utc() {
+        let send_time = SendTime::parse("2023-03-21T09:00", "America/Sao_Paulo", now()).unwrap();
+
+        assert_eq!(
+            send_time.at(),
+            Utc.with_ymd_and_hms(2023, 3, 21, 12, 0, 0).unwrap()
+        );
+        assert_eq!(send_time.time_zone(), "America/Sao_Paulo");
+    }
+
+    #[test]
+    fn seconds_are_optional() {
+        assert_eq!(
+            SendTime::parse("2023-03-21T09:00:00", "UTC", now()),
+            SendTime::parse("2023-03-21T09:00", "UTC", now())
+        );
+    }
+
+    #[test]
+    fn unknown_time_zones_are_rejected() {
+        assert_err!(SendTime::parse(
+            "2023-03-21T09:00",
+            "Mars/Olympus_Mons",
+            now()
+        ));
+    }
+
+    #[test]
+    fn malformed_times_are_rejected() {
+        assert_err!(SendTime::parse("Tuesday 9:00", "UTC", now()));
+    }
+
+    #[test]
+    fn times_in_the_past_are_rejected() {
+        assert_err!(SendTime::parse("2023-03-20T08:00", "UTC", now()));
+    }
+
+    #[test]
+    fn times_skipped_by_a_dst_change_are_rejected() {
+        // Clocks in New York jumped from 02:00 to 03:00 on 2023-03-12
+        let before = Utc.with_ymd_and_hms(2023, 3, 1, 0, 0, 0).unwrap();
+        assert_err!(SendTime::parse(
+            "2023-03-12T02:30",
+            "America/New_York",
+            before
+        ));
+    }
+
+    #[test]
+    fn times_repeated_by_a_dst_change_are_rejected() {
+        // Clocks in New York went back from 02:00 to 01:00 on 2023-11-05
+        assert_err!(SendTime::parse(
+            "2023-11-05T01:30",
+            "America/New_York",
+            now()
+        ));
+    }
+
+    #[test]
+    fn the_first_wave_is_when_the_local_time_comes_round_first() {
+        let send_time = SendTime::parse("2023-03-21T08:00", "Europe/Lisbon", now()).unwrap();
+
+        assert_eq!(
+            send_time.first_wave(),
+            Utc.with_ymd_and_hms(2023, 3, 20, 18, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn local_instants_follow_each_time_zone() {
+        let eight_am = NaiveDate::from_ymd_opt(2023, 3, 21)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+
+        assert_eq!(
+            local_instant(eight_am, chrono_tz::Asia::Tokyo),
+            Utc.with_ymd_and_hms(2023, 3, 20, 23, 0, 0).unwrap()
+        );
+        assert_eq!(
+            local_instant(eight_am, chrono_tz::America::Sao_Paulo),
+            Utc.with_ymd_and_hms(2023, 3, 21, 11, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn local_instants_skip_past_dst_gaps() {
+        let half_past_two = NaiveDate::from_ymd_opt(2023, 3, 12)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+
+        // 03:00 EDT, the first valid time after the gap
+        assert_eq!(
+            local_instant(half_past_two, chrono_tz::America::New_York),
+            Utc.with_ymd_and_hms(2023, 3, 12, 7, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use serde_json::{Map, Value};
+
+/// How one custom attribute may be set, declared in the configuration.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct AttributeRule {
+    pub name: String,
+    #[serde(default)]
+    pub kind: AttributeKind,
+    /// Whether the signup form may set it through a hidden field
+    #[serde(default)]
+    pub signup_form: bool,
+    /// Longest text value, in characters
+    pub max_length: Option<usize>,
+    /// The only values a text attribute may take, any when unset
+    pub allowed_values: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeKind {
+    #[default]
+    Text,
+    Number,
+    Boolean,
+}
+
+impl AttributeKind {
+    /// What a value of this kind looks like, for error messages.
+    pub fn expected(&self) -> &'static str {
+        match self {
+            AttributeKind::Text => "text",
+            AttributeKind::Number => "a number",
+            AttributeKind::Boolean => "true or false",
+        }
+    }
+
+    pub fn accepts(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (AttributeKind::Text, Value::String(_))
+                | (AttributeKind::Number, Value::Number(_))
+                | (AttributeKind::Boolean, Value::Bool(_))
+        )
+    }
+}
+
+impl AttributeRule {
+    fn check(&self, value: &Value) -> Result<(), String> {
+        let invalid = |reason: &str| Err(format!("Invalid {}: {}", self.name, reason));
+        match (self.kind, value) {
+            (AttributeKind::Text, Value::String(text)) => {
+                if let Some(max_length) = self.max_length {
+                    if text.chars().count() > max_length {
+                        return invalid(&format!("longer than {} characters.", max_length));
+                    }
+                }
+                match &self.allowed_values {
+                    Some(allowed) if !allowed.contains(text) => {
+                        invalid(&format!("{} is not one of {}.", text, allowed.join(", ")))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            (kind, value) if kind.accepts(value) => Ok(()),
+            (kind, _) => invalid(&format!("expected {}.", kind.expected())),
+        }
+    }
+
+    /// Read the value of a form field, where everything is text.
+    fn read_field(&self, field: String) -> Result<Value, String> {
+        match self.kind {
+            AttributeKind::Text => Ok(Value::String(field)),
+            AttributeKind::Number => field
+                .trim()
+                .parse::<serde_json::Number>()
+                .map(Value::Number)
+                .map_err(|_| format!("Invalid {}: expected a number.", self.name)),
+            AttributeKind::Boolean => match field.trim() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(format!("Invalid {}: expected true or false.", self.name)),
+            },
+        }
+    }
+}
+
+/// Custom attributes of a subscriber, each declared and checked by a rule.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse(values: Map<String, Value>, rules: &[AttributeRule]) -> Result<Self, String> {
+        for (name, value) in &values {
+            find_rule(rules, name)?.check(value)?;
+        }
+        Ok(Self(values))
+    }
+
+    /// Read the attributes set through the signup form, which may only set
+    /// those whose rule allows it. Empty fields are left out.
+    pub fn from_form(
+        fields: impl IntoIterator<Item = (String, String)>,
+        rules: &[AttributeRule],
+    ) -> Result<Self, String> {
+        let mut values = Map::new();
+        for (name, field) in fields {
+            if field.trim().is_empty() {
+                continue;
+            }
+            let rule = find_rule(rules, &name)?;
+            if !rule.signup_form {
+                return Err(format!("The signup form can't set {}.", name));
+            }
+            let value = rule.read_field(field)?;
+            rule.check(&value)?;
+            values.insert(name, value);
+        }
+        Ok(Self(values))
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+impl AsRef<Map<String, Value>> for SubscriberAttributes {
+    fn as_ref(&self) -> &Map<String, Value> {
+        &self.0
+    }
+}
+
+fn find_rule<'a>(rules: &'a [AttributeRule], name: &str) -> Result<&'a AttributeRule, String> {
+    rules
+        .iter()
+        .find(|rule| rule.name == name)
+        .ok_or_else(|| format!("Unknown attribute: {}.", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeKind, AttributeRule, SubscriberAttributes};
+    use claims::{assert_err, assert_ok};
+    use serde_json::json;
+
+    fn rules() -> Vec<AttributeRule> {
+        vec![
+            AttributeRule {
+                name: "company".into(),
+                kind: AttributeKind::Text,
+                signup_form: false,
+                max_length: Some(10),
+                allowed_values: None,
+            },
+            AttributeRule {
+                name: "locale".into(),
+                kind: AttributeKind::Text,
+                signup_form: true,
+                max_length: None,
+                allowed_values: Some(vec!["en".into(), "pt-BR".into()]),
+            },
+            AttributeRule {
+                name: "seats".into(),
+                kind: AttributeKind::Number,
+                signup_form: true,
+                max_length: None,
+                allowed_values: None,
+            },
+        ]
+    }
+
+    fn parse(value: serde_json::Value) -> Result<SubscriberAttributes, String> {
+        SubscriberAttributes::parse(value.as_object().unwrap().clone(), &rules())
+    }
+
+    #[test]
+    fn attributes_that_follow_their_rules_are_valid() {
+        assert_ok!(parse(
+            json!({ "company": "Acme", "locale": "pt-BR", "seats": 3 })
+        ));
+        assert_ok!(parse(json!({})));
+    }
+
+    #[test]
+    fn attributes_that_break_their_rules_are_rejected() {
+        for value in [
+            json!({ "country": "Brazil" }),
+            json!({ "company": "A very long name" }),
+            json!({ "company": 3 }),
+            json!({ "locale": "fr" }),
+            json!({ "seats": "3" }),
+        ] {
+            assert_err!(parse(value));
+        }
+    }
+
+    #[test]
+    fn form_fields_are_read_as_the_kind_of_their_attribute() {
+        let fields = vec![
+            ("locale".to_string(), "en".to_string()),
+            ("seats".to_string(), "12".to_string()),
+        ];
+
+        let attributes = SubscriberAttributes::from_form(fields, &rules()).unwrap();
+
+        assert_eq!(
+            attributes.into_value(),
+            json!({ "locale": "en", "seats": 12 })
+        );
+    }
+
+    #[test]
+    fn the_form_can_only_set_whitelisted_attributes() {
+        let fields = vec![("company".to_string(), "Acme".to_string())];
+
+        assert_err!(SubscriberAttributes::from_form(fields, &rules()));
+    }
+}
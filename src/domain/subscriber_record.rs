@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// A subscriber as listed by the CLI and the admin export.
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// Status on the list the subscriber was read from
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
@@ -0,0 +1,70 @@
+/// A label on a subscriber, e.g. `beta-tester`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(tag: String) -> Result<Self, String> {
+        let tag = tag.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.len() <= 64
+            && tag.split('-').all(|word| {
+                !word.is_empty()
+                    && word
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            });
+        match is_valid {
+            true => Ok(Self(tag)),
+            false => Err(format!(
+                "Invalid tag: {}. Use letters and digits separated by single dashes.",
+                tag
+            )),
+        }
+    }
+
+    /// Parse every tag, dropping repeats.
+    pub fn parse_all(tags: Vec<String>) -> Result<Vec<Self>, String> {
+        let mut tags = tags
+            .into_iter()
+            .map(Self::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        tags.sort();
+        tags.dedup();
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Beta-Tester ".into()).unwrap();
+        assert_eq!(tag.as_ref(), "beta-tester");
+        assert_ok!(SubscriberTag::parse("conference-2023".into()));
+    }
+
+    #[test]
+    fn other_tags_are_rejected() {
+        for tag in ["", "beta tester", "-beta", "beta--tester", "beta,tester"] {
+            assert_err!(SubscriberTag::parse(tag.into()));
+        }
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn repeated_tags_are_dropped() {
+        let tags =
+            SubscriberTag::parse_all(vec!["beta".into(), "alpha".into(), "Beta".into()]).unwrap();
+        let tags: Vec<_> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["alpha", "beta"]);
+    }
+}
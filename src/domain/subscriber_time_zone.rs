@@ -0,0 +1,42 @@
+use chrono_tz::Tz;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberTimeZone(Tz);
+
+impl SubscriberTimeZone {
+    pub fn parse(time_zone: String) -> Result<Self, String> {
+        time_zone
+            .trim()
+            .parse()
+            .map(Self)
+            .map_err(|_| format!("Unknown time zone: {}", time_zone))
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.0
+    }
+}
+
+impl AsRef<str> for SubscriberTimeZone {
+    fn as_ref(&self) -> &str {
+        self.0.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTimeZone;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn iana_names_are_valid() {
+        assert_ok!(SubscriberTimeZone::parse("America/Sao_Paulo".into()));
+        assert_ok!(SubscriberTimeZone::parse("UTC".into()));
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_err!(SubscriberTimeZone::parse("Sao Paulo".into()));
+        assert_err!(SubscriberTimeZone::parse("".into()));
+    }
+}
@@ -0,0 +1,63 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// The address bounced for good
+    Bounced,
+    /// The subscriber reported an email as spam
+    Complained,
+}
+
+impl SubscriptionStatus {
+    pub fn parse(status: String) -> Result<Self, String> {
+        match status.as_str() {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            "bounced" => Ok(Self::Bounced),
+            "complained" => Ok(Self::Complained),
+            other => Err(format!("Invalid subscription status: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Bounced => "bounced",
+            Self::Complained => "complained",
+        }
+    }
+}
+
+impl AsRef<str> for SubscriptionStatus {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_statuses_round_trip() {
+        for status in [
+            SubscriptionStatus::PendingConfirmation,
+            SubscriptionStatus::Confirmed,
+            SubscriptionStatus::Unsubscribed,
+            SubscriptionStatus::Bounced,
+            SubscriptionStatus::Complained,
+        ] {
+            assert_ok_eq!(SubscriptionStatus::parse(status.as_str().into()), status);
+        }
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(SubscriptionStatus::parse("deleted".into()));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::email_html::{prepare_html, PreparedHtml};
+use reqwest::{Client, StatusCode, Url};
 use secrecy::{ExposeSecret, Secret};
 use std::time::Duration;
 
@@ -9,6 +10,7 @@ pub struct EmailClient {
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    html_base_url: Option<Url>,
 }
 
 impl EmailClient {
@@ -17,35 +19,89 @@ impl EmailClient {
         sender: SubscriberEmail,
         authorization_token: Secret<String>,
         timeout: Duration,
+        html_base_url: Option<Url>,
     ) -> Self {
         Self {
             http_client: Client::builder().timeout(timeout).build().unwrap(),
             base_url,
             sender,
             authorization_token,
+            html_base_url,
         }
     }
 
+    /// Run an HTML body through the compatibility pipeline applied before sending.
+    ///
+    /// If the pipeline fails the body is returned untouched, as a badly styled
+    /// email is better than no email.
+    pub fn prepare_html(&self, html_content: &str) -> PreparedHtml {
+        prepare_html(html_content, self.html_base_url.as_ref()).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            PreparedHtml {
+                html: html_content.to_string(),
+                lint: Vec::new(),
+            }
+        })
+    }
+
     pub async fn send_email(
         &self,
         recipient: SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<ProviderResponse, reqwest::Error> {
+        self.send(recipient, subject, html_content, text_content, None)
+            .await
+    }
+
+    /// Send an email from a mailing list, with the `List-Unsubscribe` headers
+    /// that let mail clients offer a one-click unsubscribe (RFC 8058).
+    pub async fn send_list_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_url: &str,
+    ) -> Result<ProviderResponse, reqwest::Error> {
+        let headers = ListHeaders {
+            list_unsubscribe: format!("<{}>", unsubscribe_url),
+            list_unsubscribe_post: "List-Unsubscribe=One-Click",
+        };
+        self.send(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            Some(headers),
+        )
+        .await
+    }
+
+    async fn send(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: Option<ListHeaders<'_>>,
+    ) -> Result<ProviderResponse, reqwest::Error> {
         let url = format!("{}/api/v1/transmissions", self.base_url);
+        let prepared_html = self.prepare_html(html_content);
         let request_body = SendEmailRequest {
             content: SendEmailRequestContent {
                 from: self.sender.as_ref(),
                 subject,
-                html: html_content,
+                html: &prepared_html.html,
                 text: text_content,
+                headers,
             },
             recipients: Vec::from([SendEmailRequestRecipients {
                 address: recipient.as_ref(),
             }]),
         };
-        let _builder = self
+        let response = self
             .http_client
             .post(&url)
             .header("Content-Type", "application/json")
@@ -54,11 +110,48 @@ impl EmailClient {
             .send()
             .await?
             .error_for_status()?;
+        let status = response.status();
+        // The email is accepted either way, the body only helps to trace it
+        let transmission_id = response
+            .json::<TransmissionResponse>()
+            .await
+            .ok()
+            .map(|body| body.results.id);
+
+        Ok(ProviderResponse {
+            status,
+            transmission_id,
+        })
+    }
+}
 
+/// What the provider answered when it accepted an email.
+#[derive(Debug)]
+pub struct ProviderResponse {
+    pub status: StatusCode,
+    pub transmission_id: Option<String>,
+}
+
+impl std::fmt::Display for ProviderResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Accepted by the provider ({})", self.status)?;
+        if let Some(transmission_id) = &self.transmission_id {
+            write!(f, ", transmission {}", transmission_id)?;
+        }
         Ok(())
     }
 }
 
+#[derive(serde::Deserialize)]
+struct TransmissionResponse {
+    results: TransmissionResults,
+}
+
+#[derive(serde::Deserialize)]
+struct TransmissionResults {
+    id: String,
+}
+
 #[derive(serde::Serialize)]
 struct SendEmailRequest<'a> {
     content: SendEmailRequestContent<'a>,
@@ -71,6 +164,16 @@ struct SendEmailRequestContent<'a> {
     subject: &'a str,
     html: &'a str,
     text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<ListHeaders<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct ListHeaders<'a> {
+    #[serde(rename = "List-Unsubscribe")]
+    list_unsubscribe: String,
+    #[serde(rename = "List-Unsubscribe-Post")]
+    list_unsubscribe_post: &'a str,
 }
 
 #[derive(serde::Serialize)]
@@ -88,7 +191,7 @@ mod tests {
     use fake::{Fake, Faker};
     use secrecy::Secret;
     use std::time::Duration;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     struct SendEmailBodyMatcher;
@@ -130,6 +233,7 @@ mod tests {
             sender,
             Secret::new(Faker.fake()),
             Duration::from_millis(200),
+            None,
         );
 
         Mock::given(any())
@@ -145,6 +249,37 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[actix_web::test]
+    async fn send_email_returns_the_transmission_id() {
+        let mock_server = MockServer::start().await;
+        let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let email_client = EmailClient::new(
+            mock_server.uri(),
+            sender,
+            Secret::new(Faker.fake()),
+            Duration::from_millis(200),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": { "id": "11668787484950529", "total_accepted_recipients": 1 }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let response = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.transmission_id.as_deref(),
+            Some("11668787484950529")
+        );
+    }
+
     #[actix_web::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -154,6 +289,7 @@ mod tests {
             sender,
             Secret::new(Faker.fake()),
             Duration::from_millis(200),
+            None,
         );
 
         Mock::given(any())
@@ -178,6 +314,7 @@ mod tests {
             sender,
             Secret::new(Faker.fake()),
             Duration::from_millis(200),
+            None,
         );
 
         Mock::given(SendEmailBodyMatcher)
@@ -194,4 +331,38 @@ mod tests {
             .send_email(email(), &subject(), &content(), &content())
             .await;
     }
+
+    #[actix_web::test]
+    async fn list_emails_carry_one_click_unsubscribe_headers() {
+        let mock_server = MockServer::start().await;
+        let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let email_client = EmailClient::new(
+            mock_server.uri(),
+            sender,
+            Secret::new(Faker.fake()),
+            Duration::from_millis(200),
+            None,
+        );
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "content": { "headers": {
+                "List-Unsubscribe": "<https://example.com/unsubscribe?t=abc>",
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click"
+            }}
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let _ = email_client
+            .send_list_email(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                "https://example.com/unsubscribe?t=abc",
+            )
+            .await;
+    }
 }
@@ -0,0 +1,230 @@
+use css_inline::CSSInliner;
+use lol_html::html_content::Element;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use reqwest::Url;
+use std::cell::RefCell;
+
+/// Elements that the major email clients strip or refuse to render.
+const UNSUPPORTED_ELEMENTS: &[(&str, &str)] = &[
+    ("script", "Scripts are removed by every email client."),
+    (
+        "form",
+        "Forms are not supported by Outlook and most webmail clients.",
+    ),
+    (
+        "input",
+        "Form controls are not supported by Outlook and most webmail clients.",
+    ),
+    (
+        "button",
+        "Form controls are not supported by Outlook and most webmail clients.",
+    ),
+    (
+        "iframe",
+        "Embedded frames are blocked by every major email client.",
+    ),
+    (
+        "object",
+        "Embedded objects are blocked by every major email client.",
+    ),
+    (
+        "embed",
+        "Embedded objects are blocked by every major email client.",
+    ),
+    ("video", "Video is not supported by Gmail and Outlook."),
+    ("audio", "Audio is not supported by Gmail and Outlook."),
+    ("svg", "Inline SVG is not supported by Gmail and Outlook."),
+];
+
+/// CSS that survives inlining but is ignored by Outlook's Word-based renderer.
+const UNSUPPORTED_CSS: &[(&str, &str)] = &[
+    (
+        "display:flex",
+        "Flexbox layouts are not supported by Outlook.",
+    ),
+    ("display:grid", "Grid layouts are not supported by Outlook."),
+    (
+        "position:",
+        "CSS positioning is not supported by Gmail and Outlook.",
+    ),
+    (
+        "background-image:",
+        "CSS background images are not supported by Outlook.",
+    ),
+];
+
+/// Attributes holding URLs that must be absolute once the email leaves our server.
+const URL_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a[href]", "href"),
+    ("area[href]", "href"),
+    ("img[src]", "src"),
+    ("*[background]", "background"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LintWarning {
+    /// The element or CSS declaration that was flagged
+    pub construct: String,
+    pub message: &'static str,
+}
+
+#[derive(Debug)]
+pub struct PreparedHtml {
+    pub html: String,
+    pub lint: Vec<LintWarning>,
+}
+
+#[derive(Debug)]
+pub struct HtmlPipelineError(String);
+
+impl std::fmt::Display for HtmlPipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to prepare HTML email body: {}", self.0)
+    }
+}
+
+impl std::error::Error for HtmlPipelineError {}
+
+/// Make an HTML body safe to hand to email clients.
+///
+/// `<style>` blocks are inlined into `style` attributes, relative URLs are made
+/// absolute against `base_url`, and constructs that common clients don't
+/// support are reported as lint warnings. Media queries are kept in a
+/// `<style>` block for the clients that honour them.
+pub fn prepare_html(html: &str, base_url: Option<&Url>) -> Result<PreparedHtml, HtmlPipelineError> {
+    let inlined = CSSInliner::options()
+        .keep_at_rules(true)
+        .keep_link_tags(true)
+        .load_remote_stylesheets(false)
+        .build()
+        .inline(html)
+        .map_err(|e| HtmlPipelineError(e.to_string()))?;
+
+    let lint = RefCell::new(Vec::new());
+    let warn = |construct: String, message: &'static str| {
+        let warning = LintWarning { construct, message };
+        let mut lint = lint.borrow_mut();
+        if !lint.contains(&warning) {
+            lint.push(warning);
+        }
+    };
+
+    let mut handlers = vec![
+        element!("*", |el| {
+            if let Some((tag, message)) = UNSUPPORTED_ELEMENTS
+                .iter()
+                .find(|(tag, _)| el.tag_name() == *tag)
+            {
+                warn(format!("<{}>", tag), message);
+            }
+            if let Some(style) = el.get_attribute("style") {
+                let style = style.replace(' ', "").to_lowercase();
+                for (declaration, message) in UNSUPPORTED_CSS {
+                    if style.contains(declaration) {
+                        warn(declaration.to_string(), message);
+                    }
+                }
+            }
+            Ok(())
+        }),
+        element!("link[rel=stylesheet]", |_el| {
+            warn(
+                "<link rel=\"stylesheet\">".into(),
+                "External stylesheets are not loaded by Gmail and Outlook.",
+            );
+            Ok(())
+        }),
+    ];
+    if let Some(base_url) = base_url {
+        for (selector, attribute) in URL_ATTRIBUTES {
+            handlers.push(element!(selector, move |el| {
+                make_absolute(el, attribute, base_url);
+                Ok(())
+            }));
+        }
+    }
+
+    let settings = handlers
+        .into_iter()
+        .fold(RewriteStrSettings::new(), |settings, handler| {
+            settings.append_element_content_handler(handler)
+        });
+    let html = rewrite_str(&inlined, settings).map_err(|e| HtmlPipelineError(e.to_string()))?;
+    Ok(PreparedHtml {
+        html,
+        lint: lint.into_inner(),
+    })
+}
+
+fn make_absolute(el: &mut Element, attribute: &str, base_url: &Url) {
+    let Some(value) = el.get_attribute(attribute) else {
+        return;
+    };
+    let value = value.trim();
+    // Absolute URLs, in-page anchors and schemes like `mailto:` are left alone
+    if value.is_empty() || value.starts_with('#') || Url::parse(value).is_ok() {
+        return;
+    }
+    if let Ok(absolute) = base_url.join(value) {
+        let _ = el.set_attribute(attribute, absolute.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prepare_html;
+    use reqwest::Url;
+
+    fn base_url() -> Url {
+        Url::parse("https://blog.example.com/posts/").unwrap()
+    }
+
+    #[test]
+    fn style_blocks_are_inlined() {
+        let html = r#"<html><head><style>h1 { color: red; }</style></head><body><h1>Hi</h1></body></html>"#;
+        let prepared = prepare_html(html, None).unwrap();
+        assert!(prepared.html.contains(r#"<h1 style="color: red;">Hi</h1>"#));
+        assert!(!prepared.html.contains("<style>"));
+    }
+
+    #[test]
+    fn relative_urls_are_made_absolute() {
+        let html =
+            r#"<a href="/about">About</a><a href="first-issue">Issue</a><img src="../logo.png">"#;
+        let prepared = prepare_html(html, Some(&base_url())).unwrap();
+        assert!(prepared
+            .html
+            .contains(r#"href="https://blog.example.com/about""#));
+        assert!(prepared
+            .html
+            .contains(r#"href="https://blog.example.com/posts/first-issue""#));
+        assert!(prepared
+            .html
+            .contains(r#"src="https://blog.example.com/logo.png""#));
+    }
+
+    #[test]
+    fn absolute_urls_and_anchors_are_left_alone() {
+        let html = r##"<a href="https://other.com/x">x</a><a href="#top">top</a><a href="mailto:a@b.com">mail</a>"##;
+        let prepared = prepare_html(html, Some(&base_url())).unwrap();
+        assert!(prepared.html.contains(r#"href="https://other.com/x""#));
+        assert!(prepared.html.contains(r##"href="#top""##));
+        assert!(prepared.html.contains(r#"href="mailto:a@b.com""#));
+    }
+
+    #[test]
+    fn unsupported_constructs_are_reported_once() {
+        let html = r#"<html><head><style>.row { display: flex; }</style></head>
+            <body><div class="row">a</div><div class="row">b</div><video></video></body></html>"#;
+        let prepared = prepare_html(html, None).unwrap();
+        let constructs: Vec<_> = prepared.lint.iter().map(|w| w.construct.as_str()).collect();
+        assert_eq!(constructs, vec!["display:flex", "<video>"]);
+    }
+
+    #[test]
+    fn supported_html_has_no_warnings() {
+        let html = r#"<table><tr><td style="color: blue;"><p>Hello</p></td></tr></table>"#;
+        let prepared = prepare_html(html, Some(&base_url())).unwrap();
+        assert!(prepared.lint.is_empty());
+    }
+}
@@ -0,0 +1,364 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tera::{Context, Tera};
+
+/// How long overrides read from the database are reused. Edits made through
+/// this process show up at once, those made through another replica within
+/// this long.
+const OVERRIDES_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateName {
+    Confirmation,
+    Welcome,
+    Newsletter,
+    UnsubscribeReceipt,
+    /// Sent to the address a subscriber wants to move to
+    EmailChangeVerification,
+    /// Sent to the old address once the move is done
+    EmailChangeNotice,
+    /// Links to download or erase a subscriber's data
+    PrivacyLinks,
+}
+
+impl TemplateName {
+    pub fn parse(name: String) -> Result<Self, String> {
+        match name.as_str() {
+            "confirmation" => Ok(Self::Confirmation),
+            "welcome" => Ok(Self::Welcome),
+            "newsletter" => Ok(Self::Newsletter),
+            "unsubscribe_receipt" => Ok(Self::UnsubscribeReceipt),
+            "email_change_verification" => Ok(Self::EmailChangeVerification),
+            "email_change_notice" => Ok(Self::EmailChangeNotice),
+            "privacy_links" => Ok(Self::PrivacyLinks),
+            other => Err(format!("Unknown email template: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::Welcome => "welcome",
+            Self::Newsletter => "newsletter",
+            Self::UnsubscribeReceipt => "unsubscribe_receipt",
+            Self::EmailChangeVerification => "email_change_verification",
+            Self::EmailChangeNotice => "email_change_notice",
+            Self::PrivacyLinks => "privacy_links",
+        }
+    }
+
+    fn default_subject(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "Please confirm your subscription",
+            Self::Welcome => "Welcome!",
+            Self::Newsletter => "{{ title }}",
+            Self::UnsubscribeReceipt => "You have been unsubscribed",
+            Self::EmailChangeVerification => "Confirm your new address",
+            Self::EmailChangeNotice => "Your subscription address was changed",
+            Self::PrivacyLinks => "Your data request",
+        }
+    }
+}
+
+/// Variables available to every template. Fields that don't apply to a given
+/// email are left empty.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct TemplateContext {
+    pub subscriber_name: String,
+    pub confirmation_link: String,
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+    /// "View in browser" link to the issue in the public archive
+    pub archive_url: String,
+    pub title: String,
+    /// Pre-rendered HTML, inserted verbatim by the newsletter wrapper
+    pub content: String,
+    /// Where an email change moves the subscription to
+    pub new_email: String,
+    /// Privacy links, to download and to erase a subscriber's data
+    pub data_url: String,
+    pub erasure_url: String,
+}
+
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Render(tera::Error),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::Render(e) => write!(f, "Failed to render email template: {}", e),
+            TemplateError::Database(e) => write!(f, "Failed to load email template: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<tera::Error> for TemplateError {
+    fn from(e: tera::Error) -> Self {
+        TemplateError::Render(e)
+    }
+}
+
+impl From<sqlx::Error> for TemplateError {
+    fn from(e: sqlx::Error) -> Self {
+        TemplateError::Database(e)
+    }
+}
+
+#[derive(Clone)]
+struct StoredTemplate {
+    name: String,
+    subject: String,
+    html_body: String,
+    text_body: Option<String>,
+}
+
+struct CachedOverrides {
+    loaded_at: Instant,
+    templates: HashMap<String, StoredTemplate>,
+}
+
+/// Email templates shipped on disk as `<name>.html`, with an optional
+/// `<name>.txt` plain-text version. A row in `email_templates` overrides the
+/// files of the same name.
+///
+/// Clones share the cache of overrides, so saving one through any of them
+/// invalidates it for all.
+#[derive(Clone)]
+pub struct EmailTemplates {
+    tera: Tera,
+    overrides: Arc<RwLock<Option<CachedOverrides>>>,
+}
+
+impl EmailTemplates {
+    pub fn from_directory(directory: &Path) -> Result<Self, tera::Error> {
+        let glob = directory.join("*");
+        let tera = Tera::new(&glob.to_string_lossy())?;
+        Ok(Self {
+            tera,
+            overrides: Arc::default(),
+        })
+    }
+
+    /// The override for a template, reading them all from the database when
+    /// the cache is empty or stale.
+    async fn stored_override(
+        &self,
+        pool: &PgPool,
+        name: TemplateName,
+    ) -> Result<Option<StoredTemplate>, sqlx::Error> {
+        if let Some(cached) = self.overrides.read().unwrap().as_ref() {
+            if cached.loaded_at.elapsed() < OVERRIDES_TTL {
+                return Ok(cached.templates.get(name.as_str()).cloned());
+            }
+        }
+        let templates: HashMap<String, StoredTemplate> = sqlx::query_as!(
+            StoredTemplate,
+            "SELECT name, subject, html_body, text_body FROM email_templates"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|stored| (stored.name.clone(), stored))
+        .collect();
+        let stored = templates.get(name.as_str()).cloned();
+        *self.overrides.write().unwrap() = Some(CachedOverrides {
+            loaded_at: Instant::now(),
+            templates,
+        });
+        Ok(stored)
+    }
+
+    fn invalidate_overrides(&self) {
+        *self.overrides.write().unwrap() = None;
+    }
+
+    #[tracing::instrument(name = "Rendering email template", skip(self, pool, context))]
+    pub async fn render(
+        &self,
+        pool: &PgPool,
+        name: TemplateName,
+        context: &TemplateContext,
+    ) -> Result<RenderedEmail, TemplateError> {
+        let context = Context::from_serialize(context)?;
+        let stored = self.stored_override(pool, name).await?;
+
+        let (subject, html, text) = match stored {
+            Some(stored) => render_stored(&stored, &context)?,
+            None => {
+                let html = self
+                    .tera
+                    .render(&format!("{}.html", name.as_str()), &context)?;
+                let text_template = format!("{}.txt", name.as_str());
+                let text = match self.tera.get_template_names().any(|t| t == text_template) {
+                    true => Some(self.tera.render(&text_template, &context)?),
+                    false => None,
+                };
+                let subject = Tera::one_off(name.default_subject(), &context, false)?;
+                (subject, html, text)
+            }
+        };
+
+        let text = match text {
+            Some(text) => text,
+            None => html_to_text(&html),
+        };
+        Ok(RenderedEmail {
+            subject,
+            html,
+            text,
+        })
+    }
+
+    /// Store a template override, after checking that it renders.
+    #[tracing::instrument(
+        name = "Saving email template",
+        skip(self, pool, subject, html_body, text_body)
+    )]
+    pub async fn save_override(
+        &self,
+        pool: &PgPool,
+        name: TemplateName,
+        subject: &str,
+        html_body: &str,
+        text_body: Option<&str>,
+    ) -> Result<(), TemplateError> {
+        let stored = StoredTemplate {
+            name: name.as_str().to_string(),
+            subject: subject.to_string(),
+            html_body: html_body.to_string(),
+            text_body: text_body.map(str::to_string),
+        };
+        render_stored(
+            &stored,
+            &Context::from_serialize(TemplateContext::default())?,
+        )?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_templates (name, subject, html_body, text_body, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (name) DO UPDATE
+            SET subject = EXCLUDED.subject,
+                html_body = EXCLUDED.html_body,
+                text_body = EXCLUDED.text_body,
+                updated_at = EXCLUDED.updated_at
+            "#,
+            name.as_str(),
+            subject,
+            html_body,
+            text_body,
+            Utc::now()
+        )
+        .execute(pool)
+        .await?;
+        self.invalidate_overrides();
+        Ok(())
+    }
+
+    /// Drop a template override, going back to the template on disk.
+    #[tracing::instrument(name = "Removing email template override", skip(self, pool))]
+    pub async fn remove_override(
+        &self,
+        pool: &PgPool,
+        name: TemplateName,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM email_templates WHERE name = $1", name.as_str())
+            .execute(pool)
+            .await?;
+        self.invalidate_overrides();
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn render_stored(
+    stored: &StoredTemplate,
+    context: &Context,
+) -> Result<(String, String, Option<String>), tera::Error> {
+    let subject = Tera::one_off(&stored.subject, context, false)?;
+    let html = Tera::one_off(&stored.html_body, context, true)?;
+    let text = stored
+        .text_body
+        .as_deref()
+        .map(|text| Tera::one_off(text, context, false))
+        .transpose()?;
+    Ok((subject, html, text))
+}
+
+/// Derive the plain-text part of an email from its HTML body.
+pub fn html_to_text(html: &str) -> String {
+    html2text::from_read(html.as_bytes(), 78).unwrap_or_else(|_| html.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{html_to_text, EmailTemplates, TemplateContext};
+    use tera::Context;
+
+    fn templates() -> EmailTemplates {
+        EmailTemplates::from_directory("templates/email".as_ref()).unwrap()
+    }
+
+    #[test]
+    fn html_templates_escape_variables() {
+        let context = Context::from_serialize(TemplateContext {
+            subscriber_name: "<script>alert(1)</script>".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let html = templates().tera.render("welcome.html", &context).unwrap();
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn text_templates_are_not_escaped() {
+        let context = Context::from_serialize(TemplateContext {
+            subscriber_name: "Ursula & co".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let text = templates()
+            .tera
+            .render("confirmation.txt", &context)
+            .unwrap();
+        assert!(text.contains("Ursula & co"));
+    }
+
+    #[test]
+    fn the_newsletter_wrapper_keeps_the_content_html() {
+        let context = Context::from_serialize(TemplateContext {
+            content: "<h1>Issue #1</h1>".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let html = templates()
+            .tera
+            .render("newsletter.html", &context)
+            .unwrap();
+        assert!(html.contains("<h1>Issue #1</h1>"));
+    }
+
+    #[test]
+    fn plain_text_is_derived_from_html() {
+        let text = html_to_text("<p>Hello <b>there</b></p><p>Second paragraph</p>");
+        assert!(text.contains("Hello **there**"));
+        assert!(text.contains("Second paragraph"));
+        assert!(!text.contains("<p>"));
+    }
+}
@@ -0,0 +1,374 @@
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+use std::time::Duration;
+
+/// Fetches RSS and Atom feeds from other sites.
+#[derive(Clone)]
+pub struct FeedClient {
+    http_client: Client,
+}
+
+/// Validators from the last fetch of a feed, sent back so that an unchanged
+/// feed only costs a `304 Not Modified`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum FetchedFeed {
+    NotModified,
+    Updated {
+        feed: Feed,
+        validators: CacheValidators,
+    },
+}
+
+#[derive(Debug)]
+pub struct Feed {
+    pub title: String,
+    pub link: Option<String>,
+    /// In the order the feed lists them, usually newest first
+    pub items: Vec<FeedItem>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FeedItem {
+    /// Stable identity of the item: its guid or id, falling back to its link
+    pub key: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum FeedError {
+    Request(reqwest::Error),
+    Parse(String),
+}
+
+impl std::fmt::Display for FeedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FeedError::Request(e) => write!(f, "Failed to fetch the feed: {}", e),
+            FeedError::Parse(e) => write!(f, "Failed to parse the feed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FeedError {}
+
+impl From<reqwest::Error> for FeedError {
+    fn from(e: reqwest::Error) -> Self {
+        FeedError::Request(e)
+    }
+}
+
+impl FeedClient {
+    pub fn new(timeout: Duration) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            http_client: Client::builder().timeout(timeout).build()?,
+        })
+    }
+
+    pub async fn fetch(
+        &self,
+        url: &str,
+        validators: &CacheValidators,
+    ) -> Result<FetchedFeed, FeedError> {
+        let mut request = self.http_client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(FetchedFeed::NotModified);
+        }
+        let response = response.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = CacheValidators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let body = response.bytes().await?;
+        Ok(FetchedFeed::Updated {
+            feed: parse_feed(&body)?,
+            validators,
+        })
+    }
+}
+
+/// Read an RSS 2.0 or Atom document.
+pub fn parse_feed(body: &[u8]) -> Result<Feed, FeedError> {
+    if let Ok(channel) = rss::Channel::read_from(body) {
+        return Ok(from_rss(channel));
+    }
+    atom_syndication::Feed::read_from(body)
+        .map(from_atom)
+        .map_err(|e| FeedError::Parse(e.to_string()))
+}
+
+fn from_rss(channel: rss::Channel) -> Feed {
+    let items = channel
+        .items
+        .into_iter()
+        .filter_map(|item| {
+            // Items without a guid or a link can't be told apart between fetches
+            let key = item
+                .guid
+                .map(|guid| guid.value)
+                .or_else(|| item.link.clone())?;
+            Some(FeedItem {
+                title: item.title.unwrap_or_else(|| key.clone()),
+                key,
+                link: item.link,
+                summary: item.description,
+            })
+        })
+        .collect();
+    Feed {
+        title: channel.title,
+        link: Some(channel.link).filter(|link| !link.is_empty()),
+        items,
+    }
+}
+
+fn from_atom(feed: atom_syndication::Feed) -> Feed {
+    let items = feed
+        .entries
+        .into_iter()
+        .map(|entry| FeedItem {
+            link: alternate_link(&entry.links),
+            summary: entry
+                .summary
+                .map(|summary| summary.value)
+                .or_else(|| entry.content.and_then(|content| content.value)),
+            title: entry.title.value,
+            key: entry.id,
+        })
+        .collect();
+    Feed {
+        link: alternate_link(&feed.links),
+        title: feed.title.value,
+        items,
+    }
+}
+
+fn alternate_link(links: &[atom_syndication::Link]) -> Option<String> {
+    links
+        .iter()
+        .find(|link| link.rel == "alternate")
+        .map(|link| link.href.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::feed_client::{CacheValidators, FeedClient, FetchedFeed};
+    use claims::{assert_err, assert_matches};
+    use std::time::Duration;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+    <title>The blog</title>
+    <link>https://blog.example.com</link>
+    <description>Posts</description>
+    <item>
+        <title>Second post</title>
+        <link>https://blog.example.com/second</link>
+        <guid>post-2</guid>
+        <description>More words.</description>
+    </item>
+    <item>
+        <link>https://blog.example.com/first</link>
+    </item>
+    <item><title>No way to tell this one apart</title></item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <id>urn:example:blog</id>
+    <title>The blog</title>
+    <updated>2023-03-27T09:00:00Z</updated>
+    <link rel="self" href="https://blog.example.com/atom.xml"/>
+    <link rel="alternate" href="https://blog.example.com"/>
+    <entry>
+        <id>urn:example:post-1</id>
+        <title>First post</title>
+        <updated>2023-03-27T09:00:00Z</updated>
+        <link rel="alternate" href="https://blog.example.com/first"/>
+        <summary>Some words.</summary>
+    </entry>
+</feed>"#;
+
+    fn feed_client() -> FeedClient {
+        FeedClient::new(Duration::from_millis(200)).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn fetch_reads_rss_feeds() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rss.xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(RSS))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(
+                &format!("{}/rss.xml", mock_server.uri()),
+                &CacheValidators::default(),
+            )
+            .await
+            .unwrap();
+
+        let feed = match outcome {
+            FetchedFeed::Updated { feed, .. } => feed,
+            FetchedFeed::NotModified => panic!("The feed was not read."),
+        };
+        assert_eq!(feed.title, "The blog");
+        assert_eq!(feed.link.as_deref(), Some("https://blog.example.com"));
+        assert_eq!(feed.items.len(), 2);
+        assert_eq!(feed.items[0].key, "post-2");
+        assert_eq!(feed.items[0].summary.as_deref(), Some("More words."));
+        // Without a guid the link identifies the item, and stands in for its title
+        assert_eq!(feed.items[1].key, "https://blog.example.com/first");
+        assert_eq!(feed.items[1].title, "https://blog.example.com/first");
+    }
+
+    #[actix_web::test]
+    async fn fetch_reads_atom_feeds() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string(ATOM))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &CacheValidators::default())
+            .await
+            .unwrap();
+
+        let feed = match outcome {
+            FetchedFeed::Updated { feed, .. } => feed,
+            FetchedFeed::NotModified => panic!("The feed was not read."),
+        };
+        assert_eq!(feed.link.as_deref(), Some("https://blog.example.com"));
+        assert_eq!(feed.items[0].key, "urn:example:post-1");
+        assert_eq!(feed.items[0].title, "First post");
+        assert_eq!(
+            feed.items[0].link.as_deref(),
+            Some("https://blog.example.com/first")
+        );
+    }
+
+    #[actix_web::test]
+    async fn fetch_sends_the_validators_of_the_last_fetch() {
+        let mock_server = MockServer::start().await;
+        Mock::given(header("If-None-Match", "\"v1\""))
+            .and(header_exists("If-Modified-Since"))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let validators = CacheValidators {
+            etag: Some("\"v1\"".into()),
+            last_modified: Some("Mon, 27 Mar 2023 09:00:00 GMT".into()),
+        };
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &validators)
+            .await
+            .unwrap();
+
+        assert_matches!(outcome, FetchedFeed::NotModified);
+    }
+
+    #[actix_web::test]
+    async fn fetch_returns_the_new_validators() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v2\"")
+                    .set_body_string(RSS),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &CacheValidators::default())
+            .await
+            .unwrap();
+
+        match outcome {
+            FetchedFeed::Updated { validators, .. } => {
+                assert_eq!(validators.etag.as_deref(), Some("\"v2\""));
+                assert_eq!(validators.last_modified, None);
+            }
+            FetchedFeed::NotModified => panic!("The feed was not read."),
+        }
+    }
+
+    #[actix_web::test]
+    async fn fetch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &CacheValidators::default())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[actix_web::test]
+    async fn fetch_fails_if_the_body_is_not_a_feed() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html></html>"))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &CacheValidators::default())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[actix_web::test]
+    async fn fetch_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(RSS)
+                    .set_delay(Duration::from_secs(180)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let outcome = feed_client()
+            .fetch(&mock_server.uri(), &CacheValidators::default())
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::configuration::{FeedSettings, Settings};
+use crate::feed_client::{CacheValidators, FeedClient, FeedError, FeedItem, FetchedFeed};
+use crate::routes::{insert_issue, IssueError};
+use crate::startup::get_connection_pool;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashSet;
+use std::time::Duration;
+use tera::{Context, Tera};
+use uuid::Uuid;
+
+const DIGEST_TEMPLATE: &str = "digest";
+
+#[derive(Debug)]
+pub enum IngestionError {
+    Feed(FeedError),
+    Template(tera::Error),
+    Issue(IssueError),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for IngestionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestionError::Feed(e) => write!(f, "{}", e),
+            IngestionError::Template(e) => write!(f, "Failed to render the digest: {}", e),
+            IngestionError::Issue(e) => write!(f, "Failed to create the digest: {}", e),
+            IngestionError::Database(e) => write!(f, "Failed to record feed items: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IngestionError {}
+
+impl From<FeedError> for IngestionError {
+    fn from(e: FeedError) -> Self {
+        IngestionError::Feed(e)
+    }
+}
+
+impl From<tera::Error> for IngestionError {
+    fn from(e: tera::Error) -> Self {
+        IngestionError::Template(e)
+    }
+}
+
+impl From<IssueError> for IngestionError {
+    fn from(e: IssueError) -> Self {
+        IngestionError::Issue(e)
+    }
+}
+
+impl From<sqlx::Error> for IngestionError {
+    fn from(e: sqlx::Error) -> Self {
+        tracing::error!("Failed to execute query: {:?}", e);
+        IngestionError::Database(e)
+    }
+}
+
+/// Background task that polls external feeds and turns the items published
+/// since the last poll into a digest issue.
+#[derive(Clone)]
+pub struct FeedIngester {
+    pool: PgPool,
+    client: FeedClient,
+    digest: Tera,
+    feeds: Vec<FeedSettings>,
+    poll_interval: Duration,
+    claim_for: Duration,
+}
+
+impl FeedIngester {
+    pub fn build(configuration: &Settings) -> Result<Self, std::io::Error> {
+        let settings = &configuration.feed_ingestion;
+        let mut digest = Tera::default();
+        digest
+            .add_template_file(&settings.digest_template, Some(DIGEST_TEMPLATE))
+            .map_err(std::io::Error::other)?;
+        Ok(Self {
+            pool: get_connection_pool(&configuration.database),
+            client: settings.client().map_err(std::io::Error::other)?,
+            digest,
+            feeds: settings.feeds.clone(),
+            poll_interval: settings.poll_interval(),
+            claim_for: settings.claim_for(),
+        })
+    }
+
+    pub async fn run_until_stopped(self) {
+        loop {
+            self.tick().await;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Poll every configured feed once.
+    pub async fn tick(&self) {
+        for feed in &self.feeds {
+            if let Err(e) =
+                ingest_feed(&self.pool, &self.client, &self.digest, feed, self.claim_for).await
+            {
+                tracing::error!("Failed to ingest the feed at {}: {}", feed.url, e);
+            }
+        }
+    }
+}
+
+/// Fetch a feed and create an issue from the items we haven't seen before.
+/// Returns the id of that issue, if one was created.
+///
+/// The first successful fetch only records the items already in the feed, so
+/// adding a feed doesn't mail out its whole back catalogue. The feed is
+/// claimed for `claim_for` in a short transaction before the fetch, and the
+/// items are recorded in another once it is done, so replicas never turn the
+/// same items into two issues and no lock is held while waiting on the
+/// network.
+#[tracing::instrument(
+    name = "Ingesting a feed",
+    skip(pool, client, digest, feed),
+    fields(url = %feed.url)
+)]
+pub async fn ingest_feed(
+    pool: &PgPool,
+    client: &FeedClient,
+    digest: &Tera,
+    feed: &FeedSettings,
+    claim_for: Duration,
+) -> Result<Option<Uuid>, IngestionError> {
+    let state = match claim_feed(pool, &feed.url, claim_for).await? {
+        Some(state) => state,
+        // Another replica is polling it right now
+        None => return Ok(None),
+    };
+    let first_poll = state.last_polled_at.is_none();
+    let validators = CacheValidators {
+        etag: state.etag,
+        last_modified: state.last_modified,
+    };
+
+    let fetched = match client.fetch(&feed.url, &validators).await {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            // Let the next poll, on any replica, try again
+            release_feed(pool, &feed.url).await?;
+            return Err(e.into());
+        }
+    };
+    let (fetched, validators) = match fetched {
+        FetchedFeed::NotModified => (None, validators),
+        FetchedFeed::Updated { feed, validators } => (Some(feed), validators),
+    };
+
+    let mut transaction = pool.begin().await?;
+    let issue_id = match fetched {
+        Some(fetched) => {
+            let new_items = record_new_items(&mut transaction, &feed.url, fetched.items).await?;
+            if first_poll || new_items.is_empty() {
+                None
+            } else {
+                let content = render_digest(digest, &fetched.title, &fetched.link, &new_items)?;
+                let title = format!("New on {}", fetched.title);
+                let issue =
+                    insert_issue(&mut transaction, &title, &content, &feed.lists, None).await?;
+                link_items(&mut transaction, &feed.url, &new_items, issue.id).await?;
+                if let Some(minutes) = feed.schedule_after_minutes {
+                    schedule_digest(&mut transaction, issue.id, minutes).await?;
+                }
+                tracing::info!(
+                    "Created issue {} from {} new items",
+                    issue.id,
+                    new_items.len()
+                );
+                Some(issue.id)
+            }
+        }
+        None => None,
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE ingested_feeds
+        SET etag = $2, last_modified = $3, last_polled_at = now(), claimed_until = NULL
+        WHERE url = $1
+        "#,
+        feed.url,
+        validators.etag,
+        validators.last_modified
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(issue_id)
+}
+
+struct FeedState {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    last_polled_at: Option<DateTime<Utc>>,
+}
+
+/// Claim a feed for `claim_for`, unless another replica holds a claim on it.
+/// A claim left behind by a replica that died lapses on its own.
+async fn claim_feed(
+    pool: &PgPool,
+    url: &str,
+    claim_for: Duration,
+) -> Result<Option<FeedState>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "INSERT INTO ingested_feeds (url) VALUES ($1) ON CONFLICT DO NOTHING",
+        url
+    )
+    .execute(&mut transaction)
+    .await?;
+    let claimed_until = Utc::now()
+        + ChronoDuration::from_std(claim_for).unwrap_or_else(|_| ChronoDuration::minutes(10));
+    let state = sqlx::query_as!(
+        FeedState,
+        r#"
+        UPDATE ingested_feeds
+        SET claimed_until = $2
+        WHERE url = $1 AND (claimed_until IS NULL OR claimed_until < now())
+        RETURNING etag, last_modified, last_polled_at
+        "#,
+        url,
+        claimed_until
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(state)
+}
+
+async fn release_feed(pool: &PgPool, url: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE ingested_feeds SET claimed_until = NULL WHERE url = $1",
+        url
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remember the items of a feed, returning the ones we hadn't seen yet in
+/// the order the feed lists them.
+async fn record_new_items(
+    transaction: &mut Transaction<'_, Postgres>,
+    feed_url: &str,
+    items: Vec<FeedItem>,
+) -> Result<Vec<FeedItem>, sqlx::Error> {
+    let mut keys = HashSet::new();
+    let items: Vec<FeedItem> = items
+        .into_iter()
+        .filter(|item| keys.insert(item.key.clone()))
+        .collect();
+    let item_keys: Vec<String> = items.iter().map(|item| item.key.clone()).collect();
+    let inserted: HashSet<String> = sqlx::query_scalar!(
+        r#"
+        INSERT INTO ingested_feed_items (feed_url, item_key, seen_at)
+        SELECT $1, item_key, now() FROM UNNEST($2::text[]) AS item_key
+        ON CONFLICT DO NOTHING
+        RETURNING item_key
+        "#,
+        feed_url,
+        &item_keys
+    )
+    .fetch_all(transaction)
+    .await?
+    .into_iter()
+    .collect();
+    Ok(items
+        .into_iter()
+        .filter(|item| inserted.contains(&item.key))
+        .collect())
+}
+
+async fn link_items(
+    transaction: &mut Transaction<'_, Postgres>,
+    feed_url: &str,
+    items: &[FeedItem],
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let item_keys: Vec<String> = items.iter().map(|item| item.key.clone()).collect();
+    sqlx::query!(
+        r#"
+        UPDATE ingested_feed_items
+        SET issue_id = $3
+        WHERE feed_url = $1 AND item_key = ANY($2)
+        "#,
+        feed_url,
+        &item_keys,
+        issue_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+async fn schedule_digest(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    minutes: u32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_at = $2, schedule_time_zone = 'UTC'
+        WHERE id = $1
+        "#,
+        issue_id,
+        Utc::now() + ChronoDuration::minutes(minutes.into())
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+fn render_digest(
+    digest: &Tera,
+    feed_title: &str,
+    feed_link: &Option<String>,
+    items: &[FeedItem],
+) -> Result<String, tera::Error> {
+    let mut context = Context::new();
+    context.insert("feed_title", feed_title);
+    context.insert("feed_link", feed_link);
+    context.insert("items", items);
+    digest.render(DIGEST_TEMPLATE, &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::feed_client::FeedItem;
+    use crate::feed_ingestion::{render_digest, DIGEST_TEMPLATE};
+    use tera::Tera;
+
+    fn digest() -> Tera {
+        let mut tera = Tera::default();
+        tera.add_template_file("templates/feeds/digest.md", Some(DIGEST_TEMPLATE))
+            .unwrap();
+        tera
+    }
+
+    #[test]
+    fn the_digest_links_every_new_item() {
+        let items = vec![
+            FeedItem {
+                key: "post-2".into(),
+                title: "Second post".into(),
+                link: Some("https://blog.example.com/second".into()),
+                summary: Some("More words.".into()),
+            },
+            FeedItem {
+                key: "post-1".into(),
+                title: "First post".into(),
+                link: None,
+                summary: None,
+            },
+        ];
+
+        let content = render_digest(
+            &digest(),
+            "The blog",
+            &Some("https://blog.example.com".into()),
+            &items,
+        )
+        .unwrap();
+
+        assert_eq!(
+            content,
+            "New on [The blog](https://blog.example.com):\n\
+             \n## [Second post](https://blog.example.com/second)\n\
+             \nMore words.\n\
+             \n## First post\n"
+        );
+    }
+}
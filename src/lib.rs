@@ -1,6 +1,19 @@
+pub mod activity;
+pub mod authentication;
+pub mod cli;
 pub mod configuration;
+pub mod delivery_worker;
 pub mod domain;
 pub mod email_client;
+pub mod email_html;
+pub mod email_templates;
+pub mod feed_client;
+pub mod feed_ingestion;
+pub mod markdown;
+pub mod migration;
 pub mod routes;
+pub mod scheduler;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod tracking;
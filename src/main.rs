@@ -1,14 +1,43 @@
+use clap::Parser;
+use email_newsletter::cli::{run_subscribers_command, send_test_email, Cli, Command};
 use email_newsletter::configuration::get_configuration;
-use email_newsletter::startup::Application;
+use email_newsletter::migration::run_migrations;
+use email_newsletter::startup::{get_connection_pool, Application};
 use email_newsletter::telemetry::{get_subscriber, set_subscriber};
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let subscriber = get_subscriber("email_newsletter".into(), "info".into(), std::io::stdout);
-    set_subscriber(subscriber);
+async fn main() -> anyhow::Result<()> {
+    let Cli { command, format } = Cli::parse();
+
+    // Keep stdout clean for the records printed by the admin subcommands
+    match command {
+        None | Some(Command::Serve) => set_subscriber(get_subscriber(
+            "email_newsletter".into(),
+            "info".into(),
+            std::io::stdout,
+        )),
+        Some(_) => set_subscriber(get_subscriber(
+            "email_newsletter".into(),
+            "info".into(),
+            std::io::stderr,
+        )),
+    }
 
     let configuration = get_configuration().expect("Failed to get configuration.");
-    let server = Application::build(configuration).await?;
-    server.run_until_stopped().await?;
+    match command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let server = Application::build(configuration).await?;
+            server.run_until_stopped().await?;
+        }
+        Command::Migrate => {
+            let connection_pool = get_connection_pool(&configuration.database);
+            run_migrations(&connection_pool).await?;
+        }
+        Command::Subscribers { command } => {
+            let connection_pool = get_connection_pool(&configuration.database);
+            run_subscribers_command(command, format, &connection_pool).await?;
+        }
+        Command::SendTestEmail { address } => send_test_email(address, configuration).await?,
+    }
     Ok(())
 }
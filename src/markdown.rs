@@ -0,0 +1,58 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::HashSet;
+
+/// Render an editor's Markdown into HTML that is safe to put in an email.
+///
+/// Raw HTML in the Markdown is allowed, but anything that isn't formatting is
+/// stripped: scripts, event handlers, and links using schemes other than
+/// `http`, `https` and `mailto`.
+pub fn render_markdown(markdown: &str) -> String {
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    );
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+
+    Builder::default()
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("noopener noreferrer"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let html =
+            render_markdown("# Issue 1\n\nSome *emphasis* and [a link](https://example.com).");
+        assert!(html.contains("<h1>Issue 1</h1>"));
+        assert!(html.contains("<em>emphasis</em>"));
+        assert!(
+            html.contains(r#"<a href="https://example.com" rel="noopener noreferrer">a link</a>"#)
+        );
+    }
+
+    #[test]
+    fn scripts_are_removed() {
+        let html = render_markdown("Hello<script>alert('hi')</script>");
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert"));
+    }
+
+    #[test]
+    fn event_handlers_are_removed() {
+        let html = render_markdown(r#"<img src="https://example.com/a.png" onerror="alert(1)">"#);
+        assert!(!html.contains("onerror"));
+    }
+
+    #[test]
+    fn unsafe_link_schemes_are_removed() {
+        let html = render_markdown("[click](javascript:alert(1))");
+        assert!(!html.contains("javascript:"));
+    }
+}
@@ -0,0 +1,12 @@
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::PgPool;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// `Migrator::run` holds a Postgres advisory lock (keyed on the database name)
+// for the whole run, so replicas starting together apply migrations one at a
+// time and the later ones find nothing left to do
+#[tracing::instrument(name = "Running database migrations", skip(pool))]
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
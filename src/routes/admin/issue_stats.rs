@@ -0,0 +1,169 @@
+use crate::authentication::Admin;
+use crate::domain::EmailEventKind;
+use crate::routes::{IssueError, HOURLY_STATS_HOURS};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Links listed in a report, most clicked first
+const TOP_LINKS: i64 = 10;
+
+#[derive(serde::Serialize)]
+pub struct IssueStats {
+    sent: i64,
+    /// Sent to subscribers whose email did not bounce for good
+    delivered: i64,
+    opens: i64,
+    unique_opens: i64,
+    clicks: i64,
+    unique_clicks: i64,
+    unsubscribes: i64,
+    bounces: i64,
+    /// Temporary failures, which don't count against `delivered`
+    soft_bounces: i64,
+    complaints: i64,
+    top_links: Vec<LinkStats>,
+    /// One entry per hour since publication, for the first 48 hours
+    hourly: Vec<HourlyStats>,
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkStats {
+    url: String,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct HourlyStats {
+    hour: i64,
+    opens: i64,
+    clicks: i64,
+}
+
+struct KindStats {
+    kind: String,
+    total: i64,
+    unique_subscribers: i64,
+}
+
+struct HourlyTotal {
+    kind: String,
+    hour: i16,
+    total: i64,
+}
+
+/// Report how an issue did. Everything comes from the running totals kept as
+/// events are recorded, so this stays cheap however many events there are.
+#[tracing::instrument(name = "Reporting on a newsletter issue", skip(_admin, pool))]
+pub async fn get_issue_stats(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match issue_stats(&pool, *id).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn issue_stats(pool: &PgPool, issue_id: Uuid) -> Result<IssueStats, IssueError> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM newsletter_issues WHERE id = $1) AS "exists!""#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    if !exists {
+        return Err(IssueError::NotFound);
+    }
+
+    let totals = sqlx::query_as!(
+        KindStats,
+        r#"
+        SELECT kind, SUM(total)::BIGINT AS "total!",
+            SUM(unique_subscribers)::BIGINT AS "unique_subscribers!"
+        FROM issue_stats
+        WHERE issue_id = $1
+        GROUP BY kind
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let of_kind = |kind: EmailEventKind| {
+        totals
+            .iter()
+            .find(|totals| totals.kind == kind.as_str())
+            .map_or((0, 0), |totals| (totals.total, totals.unique_subscribers))
+    };
+    let (sent, _) = of_kind(EmailEventKind::Sent);
+    let (opens, unique_opens) = of_kind(EmailEventKind::Open);
+    let (clicks, unique_clicks) = of_kind(EmailEventKind::Click);
+    let (_, unsubscribes) = of_kind(EmailEventKind::Unsubscribe);
+    let (_, bounces) = of_kind(EmailEventKind::Bounce);
+    let (_, soft_bounces) = of_kind(EmailEventKind::SoftBounce);
+    let (_, complaints) = of_kind(EmailEventKind::Complaint);
+
+    let top_links = sqlx::query_as!(
+        LinkStats,
+        r#"
+        SELECT url, SUM(total)::BIGINT AS "clicks!",
+            SUM(unique_subscribers)::BIGINT AS "unique_clicks!"
+        FROM issue_link_stats
+        WHERE issue_id = $1
+        GROUP BY url
+        ORDER BY 2 DESC, url
+        LIMIT $2
+        "#,
+        issue_id,
+        TOP_LINKS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let hourly_totals = sqlx::query_as!(
+        HourlyTotal,
+        r#"
+        SELECT kind, hour, SUM(total)::BIGINT AS "total!"
+        FROM issue_hourly_stats
+        WHERE issue_id = $1
+        GROUP BY kind, hour
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut hourly: Vec<HourlyStats> = (0..HOURLY_STATS_HOURS)
+        .map(|hour| HourlyStats {
+            hour,
+            opens: 0,
+            clicks: 0,
+        })
+        .collect();
+    for total in hourly_totals {
+        let Some(bucket) = hourly.get_mut(total.hour as usize) else {
+            continue;
+        };
+        match EmailEventKind::parse(total.kind) {
+            Ok(EmailEventKind::Open) => bucket.opens = total.total,
+            Ok(EmailEventKind::Click) => bucket.clicks = total.total,
+            _ => {}
+        }
+    }
+
+    Ok(IssueStats {
+        sent,
+        delivered: (sent - bounces).max(0),
+        opens,
+        unique_opens,
+        clicks,
+        unique_clicks,
+        unsubscribes,
+        bounces,
+        soft_bounces,
+        complaints,
+        top_links,
+        hourly,
+    })
+}
@@ -0,0 +1,882 @@
+use crate::authentication::Admin;
+use crate::delivery_worker::enqueue_delivery_tasks;
+use crate::domain::SendTime;
+use crate::markdown::render_markdown;
+use crate::routes::{resolve_lists, ListError, NewsletterBody, PublishReport};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use similar::TextDiff;
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, serde::Serialize)]
+pub struct Issue {
+    pub id: Uuid,
+    pub title: String,
+    /// Markdown source of the latest revision
+    pub content: String,
+    pub revision: i32,
+    /// `draft`, `scheduled` or `published`
+    pub status: String,
+    /// Slugs of the mailing lists the issue goes out to
+    pub lists: Vec<String>,
+    /// Name of the segment that narrows down who on those lists gets it
+    pub segment: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub schedule_time_zone: Option<String>,
+    /// Set when each subscriber gets the issue at this time in their own zone
+    pub local_send_time: Option<NaiveDateTime>,
+    pub published_at: Option<DateTime<Utc>>,
+    /// Revision that was sent; the draft may have been edited since
+    pub published_revision: Option<i32>,
+    pub published_title: Option<String>,
+    pub published_html: Option<String>,
+    pub slug: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueSummary {
+    id: Uuid,
+    title: String,
+    revision: i32,
+    status: String,
+    updated_at: DateTime<Utc>,
+    scheduled_at: Option<DateTime<Utc>>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueRevision {
+    revision: i32,
+    title: String,
+    content: String,
+    created_at: DateTime<Utc>,
+}
+
+/// The exact title and HTML body that went out when an issue was published.
+#[derive(Debug)]
+pub struct IssueSnapshot {
+    pub id: Uuid,
+    pub title: String,
+    pub html: String,
+    /// Where the issue lives in the public archive
+    pub slug: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueUpdate {
+    title: String,
+    content: String,
+    /// Revision the edit was based on. When given and the issue has moved on
+    /// since, the edit is rejected instead of silently overwriting it.
+    base_revision: Option<i32>,
+    /// Replaces the lists the issue goes out to; left alone when absent
+    lists: Option<Vec<String>>,
+    /// Replaces the segment of the issue when present, `null` removing it
+    #[serde(default, deserialize_with = "present")]
+    segment: Option<Option<String>>,
+}
+
+/// Tell a field set to `null` apart from a missing one.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleBody {
+    /// Wall-clock time in `time_zone`, e.g. `2023-03-21T09:00`
+    send_at: String,
+    /// IANA time zone name, e.g. `America/Sao_Paulo`
+    time_zone: String,
+    /// Deliver at `send_at` in each subscriber's own time zone instead, falling
+    /// back to `time_zone` for subscribers whose zone is unknown
+    #[serde(default)]
+    in_subscriber_time_zone: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DiffParameters {
+    from: i32,
+    /// Defaults to the latest revision
+    to: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum IssueError {
+    NotFound,
+    AlreadyPublished,
+    NotScheduled,
+    StaleRevision { current: i32 },
+    UnknownList(String),
+    UnknownSegment(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IssueError::NotFound => write!(f, "The newsletter issue does not exist."),
+            IssueError::AlreadyPublished => {
+                write!(f, "The newsletter issue has already been published.")
+            }
+            IssueError::NotScheduled => write!(f, "The newsletter issue is not scheduled."),
+            IssueError::StaleRevision { current } => write!(
+                f,
+                "The newsletter issue was edited since; its latest revision is {}.",
+                current
+            ),
+            IssueError::UnknownList(slug) => write!(f, "There is no list named {}.", slug),
+            IssueError::UnknownSegment(name) => write!(f, "There is no segment named {}.", name),
+            IssueError::Database(e) => write!(f, "Failed to access newsletter issues: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IssueError {}
+
+impl From<sqlx::Error> for IssueError {
+    fn from(e: sqlx::Error) -> Self {
+        IssueError::Database(e)
+    }
+}
+
+impl From<ListError> for IssueError {
+    fn from(e: ListError) -> Self {
+        match e {
+            ListError::UnknownList(slug) => IssueError::UnknownList(slug),
+            ListError::Database(e) => IssueError::Database(e),
+        }
+    }
+}
+
+impl IssueError {
+    pub fn into_response(self) -> HttpResponse {
+        match self {
+            IssueError::UnknownList(_) | IssueError::UnknownSegment(_) => {
+                HttpResponse::BadRequest().body(self.to_string())
+            }
+            IssueError::NotFound => HttpResponse::NotFound().finish(),
+            IssueError::NotScheduled => HttpResponse::NotFound().body(self.to_string()),
+            IssueError::AlreadyPublished | IssueError::StaleRevision { .. } => {
+                HttpResponse::Conflict().body(self.to_string())
+            }
+            IssueError::Database(e) => {
+                tracing::error!("Failed to execute query: {:?}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Creating a newsletter draft",
+    skip(_admin, body, pool),
+    fields(title = %body.title)
+)]
+pub async fn create_issue_draft(
+    _admin: Admin,
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if body.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("The newsletter title is empty.");
+    }
+    match create_issue(
+        &pool,
+        &body.title,
+        &body.content,
+        &body.lists,
+        body.segment.as_deref(),
+    )
+    .await
+    {
+        Ok(issue) => HttpResponse::Created().json(issue),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[tracing::instrument(name = "Listing newsletter issues", skip(_admin, pool))]
+pub async fn list_issues(_admin: Admin, pool: web::Data<PgPool>) -> HttpResponse {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT id, title, revision, updated_at, scheduled_at, published_at,
+            CASE
+                WHEN published_at IS NOT NULL THEN 'published'
+                WHEN scheduled_at IS NOT NULL THEN 'scheduled'
+                ELSE 'draft'
+            END AS "status!"
+        FROM newsletter_issues
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+    match issues {
+        Ok(issues) => HttpResponse::Ok().json(issues),
+        Err(e) => IssueError::from(e).into_response(),
+    }
+}
+
+#[tracing::instrument(name = "Fetching a newsletter issue", skip(_admin, pool))]
+pub async fn get_issue(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match fetch_issue(&pool, *id).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Save an edit as a new revision. Published issues can still be edited; the
+/// snapshot that was sent is left untouched.
+#[tracing::instrument(
+    name = "Editing a newsletter issue",
+    skip(_admin, body, pool),
+    fields(title = %body.title)
+)]
+pub async fn update_issue(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    body: web::Json<IssueUpdate>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if body.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("The newsletter title is empty.");
+    }
+    match save_revision(&pool, *id, &body).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Only drafts can be deleted: published issues belong to the archive.
+#[tracing::instrument(name = "Deleting a newsletter draft", skip(_admin, pool))]
+pub async fn delete_issue(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let issue = match fetch_issue(&pool, *id).await {
+        Ok(issue) => issue,
+        Err(e) => return e.into_response(),
+    };
+    if issue.published_at.is_some() {
+        return IssueError::AlreadyPublished.into_response();
+    }
+    let deleted = sqlx::query!(
+        "DELETE FROM newsletter_issues WHERE id = $1 AND published_at IS NULL",
+        issue.id
+    )
+    .execute(pool.get_ref())
+    .await;
+    match deleted {
+        Ok(result) if result.rows_affected() == 1 => HttpResponse::NoContent().finish(),
+        // Published between the two queries
+        Ok(_) => IssueError::AlreadyPublished.into_response(),
+        Err(e) => IssueError::from(e).into_response(),
+    }
+}
+
+#[tracing::instrument(name = "Listing newsletter issue revisions", skip(_admin, pool))]
+pub async fn list_issue_revisions(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(e) = fetch_issue(&pool, *id).await {
+        return e.into_response();
+    }
+    let revisions = sqlx::query_as!(
+        IssueRevision,
+        r#"
+        SELECT revision, title, content, created_at
+        FROM newsletter_issue_revisions
+        WHERE issue_id = $1
+        ORDER BY revision DESC
+        "#,
+        *id
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+    match revisions {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(e) => IssueError::from(e).into_response(),
+    }
+}
+
+#[tracing::instrument(name = "Fetching a newsletter issue revision", skip(_admin, pool))]
+pub async fn get_issue_revision(
+    _admin: Admin,
+    path: web::Path<(Uuid, i32)>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let (id, revision) = path.into_inner();
+    match fetch_revision(&pool, id, revision).await {
+        Ok(revision) => HttpResponse::Ok().json(revision),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Unified diff between two revisions of an issue, title included.
+#[tracing::instrument(name = "Diffing newsletter issue revisions", skip(_admin, pool))]
+pub async fn diff_issue_revisions(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    parameters: web::Query<DiffParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let to = match parameters.to {
+        Some(to) => to,
+        None => match fetch_issue(&pool, *id).await {
+            Ok(issue) => issue.revision,
+            Err(e) => return e.into_response(),
+        },
+    };
+    let (old, new) = match (
+        fetch_revision(&pool, *id, parameters.from).await,
+        fetch_revision(&pool, *id, to).await,
+    ) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => return e.into_response(),
+    };
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(diff_revisions(&old, &new))
+}
+
+#[tracing::instrument(name = "Publishing a newsletter draft", skip(_admin, pool))]
+pub async fn publish_issue(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match publish_now(&pool, *id).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Queue an issue to be published at a given local time, or move it if it is
+/// already scheduled. The latest revision at that time is the one sent.
+///
+/// When sending in each subscriber's time zone, `scheduled_at` is the first
+/// wave: the moment the local time comes round in the easternmost zone.
+#[tracing::instrument(
+    name = "Scheduling a newsletter issue",
+    skip(_admin, body, pool),
+    fields(send_at = %body.send_at, time_zone = %body.time_zone)
+)]
+pub async fn schedule_issue(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    body: web::Json<ScheduleBody>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let send_time = match SendTime::parse(&body.send_at, &body.time_zone, Utc::now()) {
+        Ok(send_time) => send_time,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let schedule = match body.in_subscriber_time_zone {
+        true => Schedule {
+            scheduled_at: send_time.first_wave(),
+            local_send_time: Some(send_time.local_time()),
+            time_zone: send_time.time_zone(),
+        },
+        false => Schedule {
+            scheduled_at: send_time.at(),
+            local_send_time: None,
+            time_zone: send_time.time_zone(),
+        },
+    };
+    match set_schedule(&pool, *id, Some(schedule)).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[tracing::instrument(name = "Cancelling a scheduled newsletter issue", skip(_admin, pool))]
+pub async fn cancel_issue_schedule(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match set_schedule(&pool, *id, None).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Store a new draft together with its first revision, addressed to the
+/// lists named by `lists`, or to the default list, and narrowed down to the
+/// saved segment named `segment` if any.
+#[tracing::instrument(name = "Saving a new newsletter issue", skip(pool, content))]
+pub async fn create_issue(
+    pool: &PgPool,
+    title: &str,
+    content: &str,
+    lists: &[String],
+    segment: Option<&str>,
+) -> Result<Issue, IssueError> {
+    let mut transaction = pool.begin().await?;
+    let issue = insert_issue(&mut transaction, title, content, lists, segment).await?;
+    transaction.commit().await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(issue)
+}
+
+/// [`create_issue`] as part of a larger transaction.
+pub async fn insert_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    content: &str,
+    lists: &[String],
+    segment: Option<&str>,
+) -> Result<Issue, IssueError> {
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (id, title, content, revision, created_at, updated_at)
+        VALUES ($1, $2, $3, 1, $4, $4)
+        "#,
+        id,
+        title,
+        content,
+        now
+    )
+    .execute(&mut *transaction)
+    .await?;
+    insert_revision(transaction, id, 1, title, content, now).await?;
+    let lists = set_issue_lists(transaction, id, lists).await?;
+    let segment = set_issue_segment(transaction, id, segment).await?;
+    Ok(Issue {
+        id,
+        title: title.to_string(),
+        content: content.to_string(),
+        revision: 1,
+        status: "draft".into(),
+        lists,
+        segment,
+        created_at: now,
+        updated_at: now,
+        scheduled_at: None,
+        schedule_time_zone: None,
+        local_send_time: None,
+        published_at: None,
+        published_revision: None,
+        published_title: None,
+        published_html: None,
+        slug: None,
+    })
+}
+
+/// Publish an issue right away and queue it for the delivery worker, as the
+/// scheduler does with scheduled issues once they are due.
+#[tracing::instrument(name = "Publishing a newsletter issue now", skip(pool))]
+pub async fn publish_now(pool: &PgPool, id: Uuid) -> Result<PublishReport, IssueError> {
+    let mut transaction = pool.begin().await?;
+    snapshot_locked_issue(&mut transaction, id).await?;
+    let queued = enqueue_delivery_tasks(&mut transaction, id).await?;
+    transaction.commit().await?;
+    tracing::info!("Queued issue {} for {} subscribers", id, queued);
+    Ok(PublishReport { queued })
+}
+
+/// Freeze the latest revision of an issue as the published version, within
+/// a transaction the caller commits. The issue row stays locked until then,
+/// so it cannot be published twice.
+pub async fn snapshot_locked_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<IssueSnapshot, IssueError> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, content, published_at
+        FROM newsletter_issues
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(IssueError::NotFound)?;
+    if issue.published_at.is_some() {
+        return Err(IssueError::AlreadyPublished);
+    }
+    let html = render_markdown(&issue.content);
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = $2, published_revision = revision,
+            published_title = $3, published_html = $4
+        WHERE id = $1
+        "#,
+        id,
+        Utc::now(),
+        issue.title,
+        html
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(IssueSnapshot {
+        id,
+        html,
+        slug: claim_slug(transaction, id, &issue.title).await?,
+        title: issue.title,
+    })
+}
+
+/// Give an issue a slug for `title` that no other issue uses. An issue
+/// published concurrently may take the same slug first, in which case the
+/// update fails on the unique constraint and the next free one is tried.
+async fn claim_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    title: &str,
+) -> Result<String, sqlx::Error> {
+    loop {
+        let slug = unique_slug(transaction, title).await?;
+        let mut savepoint = transaction.begin().await?;
+        let claimed = sqlx::query!(
+            "UPDATE newsletter_issues SET slug = $2 WHERE id = $1",
+            id,
+            slug
+        )
+        .execute(&mut savepoint)
+        .await;
+        match claimed {
+            Ok(_) => {
+                savepoint.commit().await?;
+                return Ok(slug);
+            }
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+                savepoint.rollback().await?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// A slug for `title` that no published issue uses yet, numbered if needed.
+async fn unique_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+) -> Result<String, sqlx::Error> {
+    let base = slugify(title);
+    let taken: Vec<String> = sqlx::query_scalar!(
+        r#"SELECT slug AS "slug!" FROM newsletter_issues WHERE slug = $1 OR slug LIKE $1 || '-%'"#,
+        base
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let slug = std::iter::once(base.clone())
+        .chain((2..).map(|n| format!("{}-{}", base, n)))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("An unbounded iterator always yields a free slug");
+    Ok(slug)
+}
+
+/// Lowercase ASCII letters and digits separated by single dashes.
+fn slugify(title: &str) -> String {
+    let slug = title
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    match slug.is_empty() {
+        true => "issue".into(),
+        false => slug,
+    }
+}
+
+struct Schedule {
+    scheduled_at: DateTime<Utc>,
+    local_send_time: Option<NaiveDateTime>,
+    time_zone: &'static str,
+}
+
+async fn set_schedule(
+    pool: &PgPool,
+    id: Uuid,
+    schedule: Option<Schedule>,
+) -> Result<Issue, IssueError> {
+    let mut transaction = pool.begin().await?;
+    let issue = sqlx::query!(
+        "SELECT scheduled_at, published_at FROM newsletter_issues WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .ok_or(IssueError::NotFound)?;
+    if issue.published_at.is_some() {
+        return Err(IssueError::AlreadyPublished);
+    }
+    if schedule.is_none() && issue.scheduled_at.is_none() {
+        return Err(IssueError::NotScheduled);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_at = $2, schedule_time_zone = $3, local_send_time = $4
+        WHERE id = $1
+        "#,
+        id,
+        schedule.as_ref().map(|schedule| schedule.scheduled_at),
+        schedule.as_ref().map(|schedule| schedule.time_zone),
+        schedule
+            .as_ref()
+            .and_then(|schedule| schedule.local_send_time)
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    fetch_issue(pool, id).await
+}
+
+#[tracing::instrument(name = "Saving a newsletter issue revision", skip(pool, update))]
+async fn save_revision(pool: &PgPool, id: Uuid, update: &IssueUpdate) -> Result<Issue, IssueError> {
+    let mut transaction = pool.begin().await?;
+    let current = sqlx::query!(
+        "SELECT revision FROM newsletter_issues WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .ok_or(IssueError::NotFound)?
+    .revision;
+    if update.base_revision.is_some_and(|base| base != current) {
+        return Err(IssueError::StaleRevision { current });
+    }
+    let revision = current + 1;
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, content = $3, revision = $4, updated_at = $5
+        WHERE id = $1
+        "#,
+        id,
+        update.title,
+        update.content,
+        revision,
+        now
+    )
+    .execute(&mut transaction)
+    .await?;
+    insert_revision(
+        &mut transaction,
+        id,
+        revision,
+        &update.title,
+        &update.content,
+        now,
+    )
+    .await?;
+    if let Some(lists) = &update.lists {
+        set_issue_lists(&mut transaction, id, lists).await?;
+    }
+    if let Some(segment) = &update.segment {
+        set_issue_segment(&mut transaction, id, segment.as_deref()).await?;
+    }
+    transaction.commit().await?;
+    fetch_issue(pool, id).await
+}
+
+/// Address an issue to the lists named by `slugs`, replacing its current
+/// ones, and return their slugs.
+async fn set_issue_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    slugs: &[String],
+) -> Result<Vec<String>, IssueError> {
+    let (list_ids, slugs): (Vec<Uuid>, Vec<String>) =
+        resolve_lists(transaction, slugs).await?.into_iter().unzip();
+    sqlx::query!(
+        "DELETE FROM newsletter_issue_lists WHERE issue_id = $1",
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (issue_id, list_id)
+        SELECT $1, * FROM UNNEST($2::uuid[])
+        "#,
+        issue_id,
+        &list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(slugs)
+}
+
+/// Narrow an issue down to the saved segment named `name`, or send it to
+/// its lists as a whole when there is none.
+async fn set_issue_segment(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    name: Option<&str>,
+) -> Result<Option<String>, IssueError> {
+    let segment_id = match name {
+        Some(name) => Some(
+            sqlx::query_scalar!("SELECT id FROM segments WHERE name = $1", name)
+                .fetch_optional(&mut *transaction)
+                .await?
+                .ok_or_else(|| IssueError::UnknownSegment(name.to_string()))?,
+        ),
+        None => None,
+    };
+    sqlx::query!(
+        "UPDATE newsletter_issues SET segment_id = $2 WHERE id = $1",
+        issue_id,
+        segment_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(name.map(str::to_string))
+}
+
+async fn insert_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    revision: i32,
+    title: &str,
+    content: &str,
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_revisions (issue_id, revision, title, content, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        issue_id,
+        revision,
+        title,
+        content,
+        created_at
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+async fn fetch_issue(pool: &PgPool, id: Uuid) -> Result<Issue, IssueError> {
+    sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT id, title, content, revision, created_at, updated_at,
+            scheduled_at, schedule_time_zone, local_send_time,
+            published_at, published_revision, published_title, published_html, slug,
+            ARRAY(
+                SELECT l.slug FROM newsletter_issue_lists il
+                JOIN lists l ON l.id = il.list_id
+                WHERE il.issue_id = newsletter_issues.id
+                ORDER BY l.slug
+            ) AS "lists!",
+            (SELECT name FROM segments WHERE id = newsletter_issues.segment_id) AS segment,
+            CASE
+                WHEN published_at IS NOT NULL THEN 'published'
+                WHEN scheduled_at IS NOT NULL THEN 'scheduled'
+                ELSE 'draft'
+            END AS "status!"
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(IssueError::NotFound)
+}
+
+async fn fetch_revision(
+    pool: &PgPool,
+    id: Uuid,
+    revision: i32,
+) -> Result<IssueRevision, IssueError> {
+    sqlx::query_as!(
+        IssueRevision,
+        r#"
+        SELECT revision, title, content, created_at
+        FROM newsletter_issue_revisions
+        WHERE issue_id = $1 AND revision = $2
+        "#,
+        id,
+        revision
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(IssueError::NotFound)
+}
+
+fn diff_revisions(old: &IssueRevision, new: &IssueRevision) -> String {
+    let old_text = format!("# {}\n\n{}\n", old.title, old.content);
+    let new_text = format!("# {}\n\n{}\n", new.title, new.content);
+    TextDiff::from_lines(&old_text, &new_text)
+        .unified_diff()
+        .header(
+            &format!("revision {}", old.revision),
+            &format!("revision {}", new.revision),
+        )
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_revisions, slugify, IssueRevision};
+    use chrono::Utc;
+
+    fn revision(revision: i32, title: &str, content: &str) -> IssueRevision {
+        IssueRevision {
+            revision,
+            title: title.into(),
+            content: content.into(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn diff_covers_title_and_content_changes() {
+        let old = revision(1, "Draft", "First line\nSecond line");
+        let new = revision(2, "Final", "First line\nSecond line, edited");
+
+        let diff = diff_revisions(&old, &new);
+
+        assert!(diff.starts_with("--- revision 1\n+++ revision 2\n"));
+        assert!(diff.contains("-# Draft\n+# Final\n"));
+        assert!(diff.contains("-Second line\n+Second line, edited\n"));
+        assert!(diff.contains(" First line\n"));
+    }
+
+    #[test]
+    fn identical_revisions_have_an_empty_diff() {
+        let old = revision(1, "Same", "Body");
+        let new = revision(2, "Same", "Body");
+
+        assert!(diff_revisions(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn slugs_keep_only_ascii_words() {
+        assert_eq!(slugify("Issue #12: What's new?"), "issue-12-what-s-new");
+        assert_eq!(slugify("  Café  au   lait "), "caf-au-lait");
+    }
+
+    #[test]
+    fn titles_without_words_get_a_placeholder_slug() {
+        assert_eq!(slugify("!!!"), "issue");
+    }
+}
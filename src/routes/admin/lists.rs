@@ -0,0 +1,158 @@
+use crate::authentication::Admin;
+use crate::domain::ListSlug;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The list everyone was on before there were several, and the one used when
+/// no list is given.
+pub const DEFAULT_LIST: &str = "default";
+
+#[derive(serde::Deserialize)]
+pub struct ListBody {
+    slug: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct MailingList {
+    id: Uuid,
+    slug: String,
+    name: String,
+    created_at: DateTime<Utc>,
+    /// Subscribers per status on this list
+    confirmed: i64,
+    pending_confirmation: i64,
+    unsubscribed: i64,
+}
+
+#[derive(Debug)]
+pub enum ListError {
+    UnknownList(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListError::UnknownList(slug) => write!(f, "There is no list named {}.", slug),
+            ListError::Database(e) => write!(f, "Failed to look up mailing lists: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ListError {}
+
+impl From<sqlx::Error> for ListError {
+    fn from(e: sqlx::Error) -> Self {
+        ListError::Database(e)
+    }
+}
+
+#[tracing::instrument(name = "Listing mailing lists", skip(_admin, pool))]
+pub async fn list_lists(_admin: Admin, pool: web::Data<PgPool>) -> HttpResponse {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT l.id, l.slug, l.name, l.created_at,
+            COUNT(*) FILTER (WHERE ls.status = 'confirmed') AS "confirmed!",
+            COUNT(*) FILTER (WHERE ls.status = 'pending_confirmation') AS "pending_confirmation!",
+            COUNT(*) FILTER (WHERE ls.status = 'unsubscribed') AS "unsubscribed!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.id
+        GROUP BY l.id
+        ORDER BY l.created_at, l.slug
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+    match lists {
+        Ok(lists) => HttpResponse::Ok().json(lists),
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Creating a mailing list",
+    skip(_admin, body, pool),
+    fields(slug = %body.slug)
+)]
+pub async fn create_list(
+    _admin: Admin,
+    body: web::Json<ListBody>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let slug = match ListSlug::parse(body.slug.clone()) {
+        Ok(slug) => slug,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    if body.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("The list name is empty.");
+    }
+    let created = sqlx::query_as!(
+        MailingList,
+        r#"
+        INSERT INTO lists (id, slug, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING id, slug, name, created_at,
+            0::BIGINT AS "confirmed!", 0::BIGINT AS "pending_confirmation!",
+            0::BIGINT AS "unsubscribed!"
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        body.name.trim(),
+        Utc::now()
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    match created {
+        Ok(Some(list)) => HttpResponse::Created().json(list),
+        Ok(None) => {
+            HttpResponse::Conflict().body(format!("A list named {} already exists.", slug.as_ref()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn find_list_id(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT id FROM lists WHERE slug = $1", slug)
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+/// The lists named by `slugs`, sorted and without repeats, falling back to
+/// the default list when there are none.
+pub async fn resolve_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    slugs: &[String],
+) -> Result<Vec<(Uuid, String)>, ListError> {
+    let mut slugs = match slugs.is_empty() {
+        true => vec![DEFAULT_LIST.to_string()],
+        false => slugs.to_vec(),
+    };
+    slugs.sort();
+    slugs.dedup();
+    let mut lists = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        match find_list_id(&mut *transaction, &slug).await? {
+            Some(id) => lists.push((id, slug)),
+            None => return Err(ListError::UnknownList(slug)),
+        }
+    }
+    Ok(lists)
+}
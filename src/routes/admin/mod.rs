@@ -0,0 +1,19 @@
+mod issue_stats;
+mod issues;
+mod lists;
+mod newsletters;
+mod segments;
+mod subscribers;
+mod subscribers_export;
+mod subscribers_import;
+mod templates;
+
+pub use issue_stats::*;
+pub use issues::*;
+pub use lists::*;
+pub use newsletters::*;
+pub use segments::*;
+pub use subscribers::*;
+pub use subscribers_export::*;
+pub use subscribers_import::*;
+pub use templates::*;
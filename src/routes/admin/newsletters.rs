@@ -0,0 +1,154 @@
+use crate::authentication::Admin;
+use crate::email_client::EmailClient;
+use crate::email_html::LintWarning;
+use crate::email_templates::{
+    EmailTemplates, RenderedEmail, TemplateContext, TemplateError, TemplateName,
+};
+use crate::markdown::render_markdown;
+use crate::routes::{create_issue, preferences_url, publish_now, unsubscribe_url};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterBody {
+    pub title: String,
+    /// Markdown source of the issue
+    pub content: String,
+    /// Slugs of the mailing lists to send to, the default list when empty
+    #[serde(default)]
+    pub lists: Vec<String>,
+    /// Name of a saved segment to narrow the lists down to
+    pub segment: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishReport {
+    /// Emails handed to the delivery worker, weekly digests included
+    pub queued: u64,
+}
+
+/// The links in the newsletter layout.
+pub struct NewsletterLinks {
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+    pub archive_url: String,
+}
+
+impl NewsletterLinks {
+    pub fn new(base_url: &str, subscription_token: &str, archive_url: String) -> Self {
+        Self {
+            unsubscribe_url: unsubscribe_url(base_url, subscription_token),
+            preferences_url: preferences_url(base_url, subscription_token),
+            archive_url,
+        }
+    }
+}
+
+/// Publish an issue in one go, without saving a draft first. The issue is
+/// still recorded, so it shows up in the history like any other.
+#[tracing::instrument(
+    name = "Publishing a newsletter issue",
+    skip(_admin, body, pool),
+    fields(title = %body.title)
+)]
+pub async fn publish_newsletter(
+    _admin: Admin,
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if body.title.trim().is_empty() {
+        return HttpResponse::BadRequest().body("The newsletter title is empty.");
+    }
+    let issue = match create_issue(
+        &pool,
+        &body.title,
+        &body.content,
+        &body.lists,
+        body.segment.as_deref(),
+    )
+    .await
+    {
+        Ok(issue) => issue,
+        Err(e) => return e.into_response(),
+    };
+    match publish_now(&pool, issue.id).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterPreview {
+    subject: String,
+    html: String,
+    text: String,
+    /// Constructs in the HTML that common email clients don't support
+    lint: Vec<LintWarning>,
+}
+
+/// Render an issue exactly as subscribers would see it, without sending it.
+#[tracing::instrument(
+    name = "Previewing a newsletter issue",
+    skip(_admin, body, pool, email_client, templates),
+    fields(title = %body.title)
+)]
+pub async fn preview_newsletter(
+    _admin: Admin,
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let content_html = render_markdown(&body.content);
+    match render_newsletter_email(
+        &templates,
+        &pool,
+        &body.title,
+        &content_html,
+        "Subscriber".into(),
+        NewsletterLinks {
+            unsubscribe_url: "#".into(),
+            preferences_url: "#".into(),
+            archive_url: "#".into(),
+        },
+    )
+    .await
+    {
+        Ok(email) => {
+            let prepared = email_client.prepare_html(&email.html);
+            HttpResponse::Ok().json(NewsletterPreview {
+                subject: email.subject,
+                html: prepared.html,
+                text: email.text,
+                lint: prepared.lint,
+            })
+        }
+        Err(e) => {
+            tracing::error!("Failed to render the newsletter: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Wrap an issue's sanitized HTML in the newsletter layout for one subscriber.
+pub async fn render_newsletter_email(
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    title: &str,
+    content_html: &str,
+    subscriber_name: String,
+    links: NewsletterLinks,
+) -> Result<RenderedEmail, TemplateError> {
+    let context = TemplateContext {
+        subscriber_name,
+        unsubscribe_url: links.unsubscribe_url,
+        preferences_url: links.preferences_url,
+        archive_url: links.archive_url,
+        title: title.to_string(),
+        content: content_html.to_string(),
+        ..Default::default()
+    };
+    templates
+        .render(pool, TemplateName::Newsletter, &context)
+        .await
+}
@@ -0,0 +1,269 @@
+use crate::authentication::Admin;
+use crate::configuration::SubscriberFieldSettings;
+use crate::domain::{Condition, Segment, TextField};
+use crate::routes::{resolve_lists, ListError};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct SegmentBody {
+    name: String,
+    definition: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SavedSegment {
+    id: Uuid,
+    name: String,
+    definition: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SegmentPreviewBody {
+    definition: String,
+    /// Slugs of the lists to count in, the default list when empty
+    #[serde(default)]
+    lists: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SegmentPreview {
+    /// Confirmed subscribers of the lists who are in the segment
+    count: i64,
+}
+
+#[tracing::instrument(name = "Listing segments", skip(_admin, pool))]
+pub async fn list_segments(_admin: Admin, pool: web::Data<PgPool>) -> HttpResponse {
+    let segments = sqlx::query_as!(
+        SavedSegment,
+        "SELECT id, name, definition, created_at FROM segments ORDER BY name",
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+    match segments {
+        Ok(segments) => HttpResponse::Ok().json(segments),
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Saving a segment",
+    skip(_admin, body, pool, subscriber_fields),
+    fields(name = %body.name)
+)]
+pub async fn create_segment(
+    _admin: Admin,
+    body: web::Json<SegmentBody>,
+    pool: web::Data<PgPool>,
+    subscriber_fields: web::Data<SubscriberFieldSettings>,
+) -> HttpResponse {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("The segment name is empty.");
+    }
+    if let Err(e) = check_segment(&body.definition, &subscriber_fields) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let created = sqlx::query_as!(
+        SavedSegment,
+        r#"
+        INSERT INTO segments (id, name, definition, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id, name, definition, created_at
+        "#,
+        Uuid::new_v4(),
+        name,
+        body.definition.trim(),
+        Utc::now()
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    match created {
+        Ok(Some(segment)) => HttpResponse::Created().json(segment),
+        Ok(None) => {
+            HttpResponse::Conflict().body(format!("A segment named {} already exists.", name))
+        }
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Count who a segment would reach, without saving it.
+#[tracing::instrument(
+    name = "Previewing a segment",
+    skip(_admin, body, pool, subscriber_fields)
+)]
+pub async fn preview_segment(
+    _admin: Admin,
+    body: web::Json<SegmentPreviewBody>,
+    pool: web::Data<PgPool>,
+    subscriber_fields: web::Data<SubscriberFieldSettings>,
+) -> HttpResponse {
+    let segment = match check_segment(&body.definition, &subscriber_fields) {
+        Ok(segment) => segment,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match count_segment(&pool, &segment, &body.lists).await {
+        Ok(count) => HttpResponse::Ok().json(SegmentPreview { count }),
+        Err(ListError::UnknownList(slug)) => {
+            HttpResponse::BadRequest().body(ListError::UnknownList(slug).to_string())
+        }
+        Err(ListError::Database(e)) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Parse a segment, and make sure the attributes it looks at are declared
+/// and compared with values of their kind.
+fn check_segment(definition: &str, settings: &SubscriberFieldSettings) -> Result<Segment, String> {
+    let segment = Segment::parse(definition)?;
+    for (name, value) in segment.attribute_conditions() {
+        let rule = settings
+            .attributes
+            .iter()
+            .find(|rule| rule.name == name)
+            .ok_or_else(|| format!("Unknown attribute: {}.", name))?;
+        if !rule.kind.accepts(value) {
+            return Err(format!(
+                "attr.{} is compared with {}, expected {}.",
+                name,
+                value,
+                rule.kind.expected()
+            ));
+        }
+    }
+    Ok(segment)
+}
+
+async fn count_segment(
+    pool: &PgPool,
+    segment: &Segment,
+    lists: &[String],
+) -> Result<i64, ListError> {
+    let mut transaction = pool.begin().await?;
+    let list_ids: Vec<Uuid> = resolve_lists(&mut transaction, lists)
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    let mut query = QueryBuilder::new(
+        r#"
+        SELECT COUNT(DISTINCT s.id)
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.status = 'confirmed' AND ls.list_id = ANY("#,
+    );
+    query.push_bind(list_ids).push(") AND ");
+    push_segment(&mut query, segment);
+    let (count,): (i64,) = query.build_query_as().fetch_one(&mut transaction).await?;
+    transaction.commit().await?;
+    Ok(count)
+}
+
+/// The segment an issue targets, or `None` when the issue goes to its lists
+/// as a whole.
+pub async fn issue_segment(
+    connection: &mut PgConnection,
+    issue_id: Uuid,
+) -> Result<Option<Segment>, sqlx::Error> {
+    let definition = sqlx::query_scalar!(
+        r#"
+        SELECT s.definition
+        FROM newsletter_issues i
+        JOIN segments s ON s.id = i.segment_id
+        WHERE i.id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(connection)
+    .await?;
+    // Definitions were checked when they were saved
+    definition
+        .map(|definition| Segment::parse(&definition).map_err(|e| sqlx::Error::Decode(e.into())))
+        .transpose()
+}
+
+/// Append a segment as a condition on the `subscriptions` row aliased `s`.
+/// Columns come from a fixed set and every value is bound, so no part of the
+/// definition ever ends up in the SQL itself.
+pub fn push_segment(query: &mut QueryBuilder<'_, Postgres>, segment: &Segment) {
+    match segment {
+        Segment::And(left, right) | Segment::Or(left, right) => {
+            let operator = match segment {
+                Segment::And(..) => " AND ",
+                _ => " OR ",
+            };
+            query.push("(");
+            push_segment(query, left);
+            query.push(operator);
+            push_segment(query, right);
+            query.push(")");
+        }
+        Segment::Not(segment) => {
+            query.push("(NOT ");
+            push_segment(query, segment);
+            query.push(")");
+        }
+        Segment::Condition(condition) => {
+            // Comparisons with a missing value are false rather than unknown,
+            // so that NOT matches them
+            query.push("COALESCE(");
+            push_condition(query, condition);
+            query.push(", FALSE)");
+        }
+    }
+}
+
+fn push_condition(query: &mut QueryBuilder<'_, Postgres>, condition: &Condition) {
+    match condition {
+        Condition::Tag(tag) => {
+            query
+                .push_bind(tag.as_ref().to_string())
+                .push(" = ANY(s.tags)");
+        }
+        Condition::SubscribedAt(comparison, instant) => {
+            query
+                .push("s.subscribed_at ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(*instant);
+        }
+        Condition::Text(field, comparison, text) => {
+            let column = match field {
+                TextField::Email => "s.email",
+                TextField::Name => "s.name",
+                TextField::TimeZone => "s.time_zone",
+            };
+            query
+                .push(column)
+                .push(" ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(text.clone());
+        }
+        Condition::Attribute(name, comparison, value) => {
+            // Segments are checked to compare an attribute with a value of its
+            // kind, but stored values may predate the declaration. Those don't
+            // raise an error: jsonb orders values of different types by type,
+            // so `=` is false for them while `<` and `>` may hold either way
+            query
+                .push("s.attributes -> ")
+                .push_bind(name.clone())
+                .push(" ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(value.clone());
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::authentication::Admin;
+use crate::configuration::SubscriberFieldSettings;
+use crate::domain::{SubscriberAttributes, SubscriberTag};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Entries shown on a subscriber's timeline, the most recent ones
+const TIMELINE_LIMIT: i64 = 500;
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    time_zone: Option<String>,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    attributes: Value,
+    frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    tracking_opt_out: bool,
+    lists: Vec<ListMembership>,
+    /// What happened to the subscriber, oldest first
+    timeline: Vec<ActivityEntry>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListMembership {
+    list: String,
+    status: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ActivityEntry {
+    kind: String,
+    occurred_at: DateTime<Utc>,
+    /// The list it was about, if any
+    list: Option<String>,
+    issue_id: Option<Uuid>,
+    issue_title: Option<String>,
+    /// e.g. what the email provider answered
+    detail: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberUpdate {
+    /// Replaces the subscriber's tags; left alone when absent
+    tags: Option<Vec<String>>,
+    /// Merged into the subscriber's attributes, where `null` removes one
+    #[serde(default)]
+    attributes: Map<String, Value>,
+}
+
+#[derive(Debug)]
+pub enum SubscriberError {
+    NotFound,
+    Invalid(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for SubscriberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubscriberError::NotFound => write!(f, "The subscriber does not exist."),
+            SubscriberError::Invalid(e) => write!(f, "{}", e),
+            SubscriberError::Database(e) => write!(f, "Failed to access subscribers: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SubscriberError {}
+
+impl From<sqlx::Error> for SubscriberError {
+    fn from(e: sqlx::Error) -> Self {
+        SubscriberError::Database(e)
+    }
+}
+
+impl SubscriberError {
+    pub fn into_response(self) -> HttpResponse {
+        match self {
+            SubscriberError::NotFound => HttpResponse::NotFound().finish(),
+            SubscriberError::Invalid(_) => HttpResponse::BadRequest().body(self.to_string()),
+            SubscriberError::Database(e) => {
+                tracing::error!("Failed to execute query: {:?}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+#[tracing::instrument(name = "Getting a subscriber", skip(_admin, pool))]
+pub async fn get_subscriber(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match fetch_subscriber(&pool, *id).await {
+        Ok(subscriber) => HttpResponse::Ok().json(subscriber),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Set the tags and custom attributes of a subscriber. Attributes are checked
+/// against the rules in the configuration, the same way as on signup.
+#[tracing::instrument(
+    name = "Updating a subscriber",
+    skip(_admin, body, pool, subscriber_fields)
+)]
+pub async fn update_subscriber(
+    _admin: Admin,
+    id: web::Path<Uuid>,
+    body: web::Json<SubscriberUpdate>,
+    pool: web::Data<PgPool>,
+    subscriber_fields: web::Data<SubscriberFieldSettings>,
+) -> HttpResponse {
+    match save_subscriber_update(&pool, *id, body.into_inner(), &subscriber_fields).await {
+        Ok(subscriber) => HttpResponse::Ok().json(subscriber),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn save_subscriber_update(
+    pool: &PgPool,
+    id: Uuid,
+    update: SubscriberUpdate,
+    settings: &SubscriberFieldSettings,
+) -> Result<Subscriber, SubscriberError> {
+    let tags = update
+        .tags
+        .map(SubscriberTag::parse_all)
+        .transpose()
+        .map_err(SubscriberError::Invalid)?
+        .map(|tags| {
+            tags.iter()
+                .map(|tag| tag.as_ref().to_string())
+                .collect::<Vec<_>>()
+        });
+    let (removed, set): (Vec<_>, Vec<_>) = update
+        .attributes
+        .into_iter()
+        .partition(|(_, value)| value.is_null());
+    let removed: Vec<String> = removed.into_iter().map(|(name, _)| name).collect();
+    let set = SubscriberAttributes::parse(set.into_iter().collect(), &settings.attributes)
+        .map_err(SubscriberError::Invalid)?;
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET tags = COALESCE($2, tags),
+            attributes = (attributes || $3) - $4::text[]
+        WHERE id = $1
+        "#,
+        id,
+        tags.as_deref(),
+        set.into_value(),
+        &removed
+    )
+    .execute(pool)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(SubscriberError::NotFound);
+    }
+    fetch_subscriber(pool, id).await
+}
+
+#[tracing::instrument(name = "Fetching a subscriber", skip(pool))]
+async fn fetch_subscriber(pool: &PgPool, id: Uuid) -> Result<Subscriber, SubscriberError> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email, name, time_zone, subscribed_at, tags, attributes, frequency, paused_until,
+            tracking_opt_out
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(SubscriberError::NotFound)?;
+    let lists = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.slug AS list, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        id
+    )
+    .fetch_all(pool)
+    .await?;
+    let timeline = sqlx::query_as!(
+        ActivityEntry,
+        r#"
+        SELECT kind AS "kind!", occurred_at AS "occurred_at!", list AS "list?",
+            issue_id AS "issue_id?", issue_title AS "issue_title?", detail AS "detail?"
+        FROM (
+            SELECT a.kind, a.occurred_at, l.slug AS list, a.issue_id,
+                COALESCE(i.published_title, i.title) AS issue_title, a.detail
+            FROM subscriber_timeline a
+            LEFT JOIN lists l ON l.id = a.list_id
+            LEFT JOIN newsletter_issues i ON i.id = a.issue_id
+            WHERE a.subscriber_id = $1
+            ORDER BY a.occurred_at DESC
+            LIMIT $2
+        ) recent
+        ORDER BY occurred_at
+        "#,
+        id,
+        TIMELINE_LIMIT
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Subscriber {
+        id,
+        email: subscriber.email,
+        name: subscriber.name,
+        time_zone: subscriber.time_zone,
+        subscribed_at: subscriber.subscribed_at,
+        tags: subscriber.tags,
+        attributes: subscriber.attributes,
+        frequency: subscriber.frequency,
+        paused_until: subscriber.paused_until,
+        tracking_opt_out: subscriber.tracking_opt_out,
+        lists,
+        timeline,
+    })
+}
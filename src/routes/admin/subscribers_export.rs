@@ -0,0 +1,219 @@
+use crate::authentication::Admin;
+use crate::domain::{SubscriberRecord, SubscriptionStatus};
+use crate::routes::{find_list_id, DEFAULT_LIST};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use futures_util::stream;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    status: Option<String>,
+    /// Slug of the list to export, the default list when absent
+    list: Option<String>,
+}
+
+enum ExportState {
+    Start {
+        pool: PgPool,
+        list_id: Uuid,
+        status: Option<SubscriptionStatus>,
+    },
+    Fetching(Box<Transaction<'static, Postgres>>),
+    Done,
+}
+
+#[tracing::instrument(name = "Exporting subscribers", skip(_admin, pool, parameters))]
+pub async fn export_subscribers(
+    _admin: Admin,
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let ExportParameters {
+        format,
+        status,
+        list,
+    } = parameters.into_inner();
+    let status = match status.map(SubscriptionStatus::parse).transpose() {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let list = list.unwrap_or_else(|| DEFAULT_LIST.into());
+    let list_id = match find_list_id(pool.get_ref(), &list).await {
+        Ok(Some(list_id)) => list_id,
+        Ok(None) => {
+            return HttpResponse::BadRequest().body(format!("There is no list named {}.", list))
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let state = ExportState::Start {
+        pool: pool.get_ref().clone(),
+        list_id,
+        status,
+    };
+    let body = stream::try_unfold(state, move |state| next_chunk(state, format));
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"subscribers.{}\"",
+                format.extension()
+            ),
+        ))
+        .streaming(Box::pin(body))
+}
+
+/// Produce the next chunk of the export, one cursor page at a time, so memory
+/// use does not depend on the size of the list.
+async fn next_chunk(
+    state: ExportState,
+    format: ExportFormat,
+) -> Result<Option<(Bytes, ExportState)>, sqlx::Error> {
+    match state {
+        ExportState::Start {
+            pool,
+            list_id,
+            status,
+        } => {
+            let mut transaction = pool.begin().await?;
+            // Cursors can't take bind parameters, but list ids are UUIDs and
+            // statuses come from a closed set of literals
+            let filter = match status {
+                Some(status) => format!("AND ls.status = '{}'", status.as_str()),
+                None => String::new(),
+            };
+            transaction
+                .execute(
+                    format!(
+                        r#"
+                        DECLARE subscribers_export NO SCROLL CURSOR FOR
+                        SELECT s.id, s.email, s.name, ls.status, ls.subscribed_at
+                        FROM list_subscriptions ls
+                        JOIN subscriptions s ON s.id = ls.subscriber_id
+                        WHERE ls.list_id = '{}' {}
+                        ORDER BY ls.subscribed_at, s.id
+                        "#,
+                        list_id, filter
+                    )
+                    .as_str(),
+                )
+                .await?;
+            let header = match format {
+                ExportFormat::Csv => Bytes::from_static(b"id,email,name,status,subscribed_at\n"),
+                ExportFormat::Jsonl => Bytes::new(),
+            };
+            Ok(Some((header, ExportState::Fetching(Box::new(transaction)))))
+        }
+        ExportState::Fetching(mut transaction) => {
+            let records: Vec<SubscriberRecord> = sqlx::query_as(&format!(
+                "FETCH {} FROM subscribers_export",
+                EXPORT_PAGE_SIZE
+            ))
+            .fetch_all(&mut *transaction)
+            .await?;
+            if records.is_empty() {
+                transaction.commit().await?;
+                return Ok(None);
+            }
+            let next_state = match records.len() < EXPORT_PAGE_SIZE {
+                true => {
+                    transaction.commit().await?;
+                    ExportState::Done
+                }
+                false => ExportState::Fetching(transaction),
+            };
+            Ok(Some((serialize_page(&records, format), next_state)))
+        }
+        ExportState::Done => Ok(None),
+    }
+}
+
+fn serialize_page(records: &[SubscriberRecord], format: ExportFormat) -> Bytes {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            for record in records {
+                let record = SubscriberRecord {
+                    id: record.id,
+                    email: neutralize_formula(&record.email),
+                    name: neutralize_formula(&record.name),
+                    status: record.status.clone(),
+                    subscribed_at: record.subscribed_at,
+                };
+                writer
+                    .serialize(record)
+                    .expect("Failed to serialize subscriber as CSV.");
+            }
+            Bytes::from(writer.into_inner().expect("Failed to flush CSV writer."))
+        }
+        ExportFormat::Jsonl => {
+            let mut buffer = Vec::new();
+            for record in records {
+                serde_json::to_writer(&mut buffer, record)
+                    .expect("Failed to serialize subscriber as JSON.");
+                buffer.push(b'\n');
+            }
+            Bytes::from(buffer)
+        }
+    }
+}
+
+/// Prefix cells that a spreadsheet would evaluate as a formula with `'`, so
+/// that opening an export can't run whatever a subscriber put in their name.
+fn neutralize_formula(cell: &str) -> String {
+    match cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        true => format!("'{}", cell),
+        false => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::neutralize_formula;
+
+    #[test]
+    fn cells_starting_like_a_formula_are_prefixed() {
+        for cell in ["=1+2", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(neutralize_formula(cell), format!("'{}", cell));
+        }
+    }
+
+    #[test]
+    fn other_cells_are_left_alone() {
+        assert_eq!(neutralize_formula("Ursula Le-Guin"), "Ursula Le-Guin");
+    }
+}
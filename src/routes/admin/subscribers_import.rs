@@ -0,0 +1,495 @@
+use crate::authentication::Admin;
+use crate::domain::{ActivityKind, SubscriberEmail, SubscriberName};
+use crate::routes::{find_list_id, generate_subscription_token, DEFAULT_LIST};
+use crate::suppression::{email_hash, SuppressionReason};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use csv_async::{AsyncReaderBuilder, StringRecord};
+use futures_util::{future, stream, StreamExt};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use tokio::io::AsyncRead;
+use tokio::sync::mpsc;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+const IMPORT_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Accepted,
+    Duplicate,
+    Rejected,
+}
+
+impl ImportOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportOutcome::Accepted => "accepted",
+            ImportOutcome::Duplicate => "duplicate",
+            ImportOutcome::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ImportRow {
+    /// Line of the CSV file the row was read from, the header being line 1
+    pub row: u64,
+    pub email: String,
+    pub outcome: ImportOutcome,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportReport {
+    pub accepted: usize,
+    pub duplicate: usize,
+    pub rejected: usize,
+    /// Existing subscribers whose name was replaced by the one in the file
+    pub names_updated: usize,
+    pub rows: Vec<ImportRow>,
+}
+
+impl ImportReport {
+    fn push(&mut self, row: u64, email: String, outcome: ImportOutcome, reason: Option<String>) {
+        match outcome {
+            ImportOutcome::Accepted => self.accepted += 1,
+            ImportOutcome::Duplicate => self.duplicate += 1,
+            ImportOutcome::Rejected => self.rejected += 1,
+        }
+        self.rows.push(ImportRow {
+            row,
+            email,
+            outcome,
+            reason,
+        });
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file itself is unusable, e.g. it is not CSV or a required column is missing
+    InvalidFile(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::InvalidFile(reason) => write!(f, "Invalid CSV file: {}", reason),
+            ImportError::Database(e) => write!(f, "Failed to import subscribers: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(e: sqlx::Error) -> Self {
+        ImportError::Database(e)
+    }
+}
+
+impl From<csv_async::Error> for ImportError {
+    fn from(e: csv_async::Error) -> Self {
+        ImportError::InvalidFile(e.to_string())
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ImportParameters {
+    /// Slug of the list to import into, the default list when absent
+    list: Option<String>,
+    /// Replace the name of existing subscribers with the one in the file
+    #[serde(default)]
+    overwrite_names: bool,
+}
+
+#[tracing::instrument(name = "Importing subscribers from CSV", skip(_admin, body, pool))]
+pub async fn import_subscribers_csv(
+    _admin: Admin,
+    parameters: web::Query<ImportParameters>,
+    mut body: web::Payload,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let list = parameters.list.as_deref().unwrap_or(DEFAULT_LIST);
+    let list_id = match find_list_id(pool.get_ref(), list).await {
+        Ok(Some(list_id)) => list_id,
+        Ok(None) => {
+            return HttpResponse::BadRequest().body(format!("There is no list named {}.", list))
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // The request payload can't leave the worker thread, so chunks are handed
+    // over to the CSV reader through a channel. A payload error reaches the
+    // reader as an I/O error, which aborts the import before anything is committed
+    let (sender, receiver) = mpsc::channel(16);
+    let forward_body = async move {
+        while let Some(chunk) = body.next().await {
+            let failed = chunk.is_err();
+            let chunk =
+                chunk.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+            if sender.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    };
+    let chunks = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let reader = StreamReader::new(Box::pin(chunks));
+
+    let (_, outcome) = future::join(
+        forward_body,
+        import_subscribers(&pool, list_id, parameters.overwrite_names, reader),
+    )
+    .await;
+    match outcome {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(ImportError::InvalidFile(reason)) => HttpResponse::BadRequest().body(reason),
+        Err(ImportError::Database(e)) => {
+            tracing::error!("Failed to import subscribers: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+struct ValidRow {
+    row: u64,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct Columns {
+    email: usize,
+    name: usize,
+    subscribed_at: Option<usize>,
+}
+
+impl Columns {
+    fn from_headers(headers: &StringRecord) -> Result<Self, ImportError> {
+        let position = |column: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(column))
+        };
+        let required = |column: &str| {
+            position(column)
+                .ok_or_else(|| ImportError::InvalidFile(format!("missing `{}` column", column)))
+        };
+        Ok(Columns {
+            email: required("email")?,
+            name: required("name")?,
+            subscribed_at: position("subscribed_at"),
+        })
+    }
+}
+
+/// Validate every row of the CSV read from `reader` and add the valid ones to
+/// a list as confirmed subscribers.
+///
+/// All batches are written in a single transaction, so a database failure
+/// leaves the subscriber list untouched. Subscribers already on the list keep
+/// their status and consent timestamp there, and their name unless
+/// `overwrite_names` is set.
+pub async fn import_subscribers<R>(
+    pool: &PgPool,
+    list_id: Uuid,
+    overwrite_names: bool,
+    reader: R,
+) -> Result<ImportReport, ImportError>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut csv_reader = AsyncReaderBuilder::new()
+        .flexible(true)
+        .create_reader(reader);
+    let columns = Columns::from_headers(csv_reader.headers().await?)?;
+
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut transaction = pool.begin().await?;
+
+    let mut records = csv_reader.records();
+    while let Some(record) = records.next().await {
+        let record = record?;
+        let row = record.position().map(|p| p.line()).unwrap_or_default();
+        let field = |index: usize| record.get(index).unwrap_or_default().trim().to_string();
+        let email = field(columns.email);
+
+        let valid_row = match parse_row(
+            row,
+            email.clone(),
+            field(columns.name),
+            columns.subscribed_at.map(field),
+        ) {
+            Ok(valid_row) => valid_row,
+            Err(reason) => {
+                report.push(row, email, ImportOutcome::Rejected, Some(reason));
+                continue;
+            }
+        };
+        if !seen.insert(valid_row.email.to_lowercase()) {
+            report.push(
+                row,
+                email,
+                ImportOutcome::Duplicate,
+                Some("Email appears earlier in the file.".into()),
+            );
+            continue;
+        }
+
+        batch.push(valid_row);
+        if batch.len() == IMPORT_BATCH_SIZE {
+            upsert_batch(
+                &mut transaction,
+                list_id,
+                overwrite_names,
+                &mut batch,
+                &mut report,
+            )
+            .await?;
+        }
+    }
+    upsert_batch(
+        &mut transaction,
+        list_id,
+        overwrite_names,
+        &mut batch,
+        &mut report,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    report.rows.sort_by_key(|r| r.row);
+    Ok(report)
+}
+
+fn parse_row(
+    row: u64,
+    email: String,
+    name: String,
+    subscribed_at: Option<String>,
+) -> Result<ValidRow, String> {
+    let email = SubscriberEmail::parse(email)?;
+    let name = SubscriberName::parse(name)?;
+    let subscribed_at = match subscribed_at.filter(|s| !s.is_empty()) {
+        Some(timestamp) => parse_consent_timestamp(&timestamp)?,
+        None => Utc::now(),
+    };
+    Ok(ValidRow {
+        row,
+        email: email.as_ref().to_string(),
+        name: name.as_ref().to_string(),
+        subscribed_at,
+    })
+}
+
+/// Accept RFC 3339 timestamps as well as the `YYYY-MM-DD HH:MM:SS` UTC
+/// timestamps found in Mailchimp exports.
+fn parse_consent_timestamp(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc())
+        })
+        .map_err(|_| format!("Invalid subscribed_at timestamp: {}", timestamp))
+}
+
+async fn upsert_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    overwrite_names: bool,
+    batch: &mut Vec<ValidRow>,
+    report: &mut ImportReport,
+) -> Result<(), sqlx::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    // Suppressed addresses must not come back through an import
+    let hashes: Vec<String> = batch.iter().map(|r| email_hash(&r.email)).collect();
+    let suppressed: HashMap<String, String> = sqlx::query!(
+        "SELECT email_hash, reason FROM suppressions WHERE email_hash = ANY($1)",
+        &hashes
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| (r.email_hash, r.reason))
+    .collect();
+    if !suppressed.is_empty() {
+        let (rejected, kept) = batch
+            .drain(..)
+            .partition::<Vec<_>, _>(|r| suppressed.contains_key(&email_hash(&r.email)));
+        for row in rejected {
+            let reason = SuppressionReason::parse(suppressed[&email_hash(&row.email)].clone());
+            let message = match reason {
+                Ok(SuppressionReason::Unsubscribe) => "Address unsubscribed from every list.",
+                Ok(SuppressionReason::Bounce) => "Address bounced.",
+                Ok(SuppressionReason::Complaint) => "Address reported our email as spam.",
+                Ok(SuppressionReason::Erasure) => "Address was erased at the subscriber's request.",
+                Err(_) => "Address is suppressed.",
+            };
+            report.push(
+                row.row,
+                row.email,
+                ImportOutcome::Rejected,
+                Some(message.into()),
+            );
+        }
+        *batch = kept;
+        if batch.is_empty() {
+            return Ok(());
+        }
+    }
+
+    // Rows for an existing subscriber are written under the address already
+    // stored, whatever its case, so that they hit the conflict on `email`
+    let lowercased: Vec<String> = batch.iter().map(|r| r.email.to_lowercase()).collect();
+    let existing: HashMap<String, (String, String)> = sqlx::query!(
+        "SELECT email, name FROM subscriptions WHERE lower(email) = ANY($1)",
+        &lowercased
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| (r.email.to_lowercase(), (r.email, r.name)))
+    .collect();
+
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|r| match existing.get(&r.email.to_lowercase()) {
+            Some((stored, _)) => stored.clone(),
+            None => r.email.clone(),
+        })
+        .collect();
+    let names: Vec<String> = batch.iter().map(|r| r.name.clone()).collect();
+    let timestamps: Vec<DateTime<Utc>> = batch.iter().map(|r| r.subscribed_at).collect();
+
+    // Only subscribers who weren't on the list yet come back from the second insert
+    let inserted: HashMap<String, Uuid> = sqlx::query!(
+        r#"
+        WITH batch AS (
+            SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[])
+                AS batch(id, email, name, subscribed_at)
+        ), subscribers AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at)
+            SELECT id, email, name, subscribed_at FROM batch
+            ON CONFLICT (email) DO UPDATE
+                SET name = CASE WHEN $6 THEN EXCLUDED.name ELSE subscriptions.name END
+            RETURNING id, email
+        ), joined AS (
+            INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+            SELECT $5, subscribers.id, 'confirmed', batch.subscribed_at
+            FROM subscribers JOIN batch ON batch.email = subscribers.email
+            ON CONFLICT (list_id, subscriber_id) DO NOTHING
+            RETURNING subscriber_id
+        )
+        SELECT subscribers.id, subscribers.email FROM subscribers
+        JOIN joined ON joined.subscriber_id = subscribers.id
+        "#,
+        &ids,
+        &emails,
+        &names,
+        &timestamps,
+        list_id,
+        overwrite_names
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| (r.email, r.id))
+    .collect();
+    let subscriber_ids: Vec<Uuid> = inserted.values().copied().collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_activity (id, subscriber_id, kind, occurred_at, list_id, detail)
+        SELECT gen_random_uuid(), subscriber_id, $3, now(), $2, 'Imported'
+        FROM UNNEST($1::uuid[]) AS subscriber_id
+        "#,
+        &subscriber_ids,
+        list_id,
+        ActivityKind::SignedUp.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Imported subscribers never got a token from the signup form
+    let tokens: Vec<String> = subscriber_ids
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        SELECT token, subscriber_id, $3 FROM UNNEST($1::text[], $2::uuid[]) AS t(token, subscriber_id)
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        "#,
+        &tokens,
+        &subscriber_ids,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    for (row, email) in batch.drain(..).zip(emails) {
+        let name_updated = overwrite_names
+            && existing
+                .get(&email.to_lowercase())
+                .is_some_and(|(_, name)| *name != row.name);
+        if name_updated {
+            report.names_updated += 1;
+        }
+        if inserted.contains_key(&email) {
+            report.push(row.row, row.email, ImportOutcome::Accepted, None);
+        } else {
+            let reason = if name_updated {
+                "Already subscribed, name updated."
+            } else {
+                "Already subscribed."
+            };
+            report.push(
+                row.row,
+                row.email,
+                ImportOutcome::Duplicate,
+                Some(reason.into()),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_consent_timestamp;
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn rfc3339_timestamps_are_accepted() {
+        assert_ok_eq!(
+            parse_consent_timestamp("2022-05-01T10:30:00-03:00"),
+            Utc.with_ymd_and_hms(2022, 5, 1, 13, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn mailchimp_timestamps_are_accepted_as_utc() {
+        assert_ok_eq!(
+            parse_consent_timestamp("2022-05-01 10:30:00"),
+            Utc.with_ymd_and_hms(2022, 5, 1, 10, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn garbage_timestamps_are_rejected() {
+        assert_err!(parse_consent_timestamp("yesterday"));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::authentication::Admin;
+use crate::email_templates::{EmailTemplates, TemplateError, TemplateName};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct TemplateBody {
+    subject: String,
+    html_body: String,
+    text_body: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Overriding an email template",
+    skip(_admin, body, pool, templates)
+)]
+pub async fn put_email_template(
+    _admin: Admin,
+    name: web::Path<String>,
+    body: web::Json<TemplateBody>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let name = match TemplateName::parse(name.into_inner()) {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::NotFound().body(e),
+    };
+    match templates
+        .save_override(
+            &pool,
+            name,
+            &body.subject,
+            &body.html_body,
+            body.text_body.as_deref(),
+        )
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(TemplateError::Render(e)) => HttpResponse::BadRequest().body(e.to_string()),
+        Err(TemplateError::Database(e)) => {
+            tracing::error!("Failed to save email template: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Removing an email template override",
+    skip(_admin, pool, templates)
+)]
+pub async fn delete_email_template(
+    _admin: Admin,
+    name: web::Path<String>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let name = match TemplateName::parse(name.into_inner()) {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::NotFound().body(e),
+    };
+    match templates.remove_override(&pool, name).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            tracing::error!("Failed to remove email template: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
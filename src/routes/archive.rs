@@ -0,0 +1,154 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tera::escape_html;
+
+const ARCHIVE_PAGE_SIZE: i64 = 20;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+struct ArchiveEntry {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Public link to a published issue.
+pub fn archive_url(base_url: &str, slug: &str) -> String {
+    format!("{}/archive/{}", base_url, slug)
+}
+
+/// Published issues, newest first. Drafts have no slug and are never listed.
+#[tracing::instrument(name = "Showing the newsletter archive", skip(pool))]
+pub async fn archive_index(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let page = parameters.page.unwrap_or(1);
+    if page < 1 {
+        return HttpResponse::BadRequest().body("Pages are numbered from 1.");
+    }
+    let offset = match (page - 1).checked_mul(ARCHIVE_PAGE_SIZE) {
+        Some(offset) => offset,
+        None => return HttpResponse::BadRequest().body("There is no such page."),
+    };
+    // One extra row tells us whether there is an older page
+    let mut entries = match sqlx::query_as!(
+        ArchiveEntry,
+        r#"
+        SELECT slug AS "slug!", published_title AS "title!", published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        ARCHIVE_PAGE_SIZE + 1,
+        offset
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if entries.is_empty() && page > 1 {
+        return HttpResponse::NotFound().finish();
+    }
+    let has_older = entries.len() as i64 > ARCHIVE_PAGE_SIZE;
+    entries.truncate(ARCHIVE_PAGE_SIZE as usize);
+
+    let items: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "\n        <li><a href=\"/archive/{}\">{}</a> <time datetime=\"{}\">{}</time></li>",
+                entry.slug,
+                escape_html(&entry.title),
+                entry.published_at.to_rfc3339(),
+                entry.published_at.format("%Y-%m-%d")
+            )
+        })
+        .collect();
+    let mut navigation = Vec::new();
+    if page > 1 {
+        navigation.push(format!(
+            r#"<a href="/archive?page={}" rel="prev">Newer issues</a>"#,
+            page - 1
+        ));
+    }
+    if has_older {
+        navigation.push(format!(
+            r#"<a href="/archive?page={}" rel="next">Older issues</a>"#,
+            page + 1
+        ));
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Newsletter archive</title></head>
+<body>
+    <h1>Newsletter archive</h1>
+    <ul>{}
+    </ul>
+    <nav>{}</nav>
+</body>
+</html>"#,
+            items,
+            navigation.join(" ")
+        ))
+}
+
+/// One published issue, rendered from the snapshot taken when it was sent.
+#[tracing::instrument(name = "Showing an archived issue", skip(pool))]
+pub async fn archive_issue(slug: web::Path<String>, pool: web::Data<PgPool>) -> HttpResponse {
+    let issue = match sqlx::query!(
+        r#"
+        SELECT published_title AS "title!", published_html AS "html!",
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE slug = $1 AND published_at IS NOT NULL
+        "#,
+        slug.as_str()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    {
+        Ok(Some(issue)) => issue,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(e) => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let title = escape_html(&issue.title);
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+    <article>
+        <h1>{title}</h1>
+        <p><time datetime="{}">{}</time></p>
+        {}
+    </article>
+    <p><a href="/archive">All issues</a></p>
+</body>
+</html>"#,
+            issue.published_at.to_rfc3339(),
+            issue.published_at.format("%Y-%m-%d"),
+            issue.html
+        ))
+}
@@ -0,0 +1,283 @@
+use crate::domain::EmailEventKind;
+use crate::tracking::{Tracker, TRANSPARENT_GIF};
+use actix_web::http::header::{CACHE_CONTROL, LOCATION, USER_AGENT};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Longest user agent kept with an event
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// How many hours after publication events are also counted hour by hour
+pub const HOURLY_STATS_HOURS: i64 = 48;
+
+/// Rows each running total of an issue is split over
+const STATS_SHARDS: u128 = 16;
+
+/// Record that a subscriber opened an issue. The pixel is served whatever the
+/// token, so a mangled link doesn't show up as a broken image.
+#[tracing::instrument(name = "Tracking an email open", skip(token, request, pool, tracker))]
+pub async fn track_open(
+    token: web::Path<String>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+) -> HttpResponse {
+    match tracker.verify(&token) {
+        Some(_) if !tracker.tracks_opens() => {}
+        Some((issue_id, subscriber_id)) => {
+            let user_agent = user_agent(&request);
+            // Losing an event is better than a broken image
+            let _ = store_event(
+                &pool,
+                issue_id,
+                subscriber_id,
+                EmailEventKind::Open,
+                None,
+                user_agent,
+            )
+            .await;
+        }
+        None => tracing::warn!("Ignoring an open with an invalid token"),
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .body(TRANSPARENT_GIF.as_slice())
+}
+
+/// Record that a subscriber followed a link, then send them on to it.
+///
+/// The link comes out of the signed token rather than the database, and
+/// tokens that don't verify get a 404: anything else would make this an open
+/// redirect.
+#[tracing::instrument(name = "Tracking a link click", skip(token, request, pool, tracker))]
+pub async fn track_click(
+    token: web::Path<String>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+) -> HttpResponse {
+    let (issue_id, subscriber_id, url) = match tracker.verify_click(&token) {
+        Some(click) => click,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    if tracker.tracks_clicks() {
+        let user_agent = user_agent(&request);
+        // Losing an event is better than a dead link
+        let _ = store_event(
+            &pool,
+            issue_id,
+            subscriber_id,
+            EmailEventKind::Click,
+            Some(&url),
+            user_agent,
+        )
+        .await;
+    }
+    HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .finish()
+}
+
+fn user_agent(request: &HttpRequest) -> Option<&str> {
+    request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+}
+
+/// Store an event in its own transaction.
+pub async fn store_event(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EmailEventKind,
+    url: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    record_event(
+        &mut transaction,
+        issue_id,
+        subscriber_id,
+        kind,
+        url,
+        user_agent,
+    )
+    .await?;
+    transaction.commit().await
+}
+
+/// Store an event and add it to the issue's running totals. Opens and clicks
+/// are dropped for subscribers who opted out of tracking, and every event is
+/// dropped if the issue or the subscriber no longer exists.
+#[tracing::instrument(name = "Recording an email event", skip(transaction, url, user_agent))]
+pub async fn record_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EmailEventKind,
+    url: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let occurred_at = Utc::now();
+    let user_agent = user_agent.map(|user_agent| {
+        user_agent
+            .chars()
+            .take(MAX_USER_AGENT_LENGTH)
+            .collect::<String>()
+    });
+    let published_at = sqlx::query_scalar!(
+        r#"
+        INSERT INTO email_events
+            (id, issue_id, subscriber_id, kind, occurred_at, url, user_agent)
+        SELECT $1, i.id, s.id, $4, $5, $6, $7
+        FROM newsletter_issues i, subscriptions s
+        WHERE i.id = $2 AND s.id = $3 AND (NOT $8::bool OR NOT s.tracking_opt_out)
+        RETURNING (SELECT published_at FROM newsletter_issues WHERE id = $2)
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        subscriber_id,
+        kind.as_str(),
+        occurred_at,
+        url,
+        user_agent,
+        kind.is_engagement()
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let published_at = match published_at {
+        Some(published_at) => published_at,
+        None => return Ok(()),
+    };
+    let shard = stats_shard(subscriber_id);
+    let is_first = is_first_event(transaction, issue_id, subscriber_id, kind, "").await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_stats (issue_id, kind, shard, total, unique_subscribers)
+        VALUES ($1, $2, $3, 1, $4)
+        ON CONFLICT (issue_id, kind, shard) DO UPDATE SET
+            total = issue_stats.total + 1,
+            unique_subscribers = issue_stats.unique_subscribers + EXCLUDED.unique_subscribers
+        "#,
+        issue_id,
+        kind.as_str(),
+        shard,
+        i64::from(is_first)
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let elapsed = published_at.map(|published_at| occurred_at - published_at);
+    if let Some(elapsed) = elapsed
+        .filter(|elapsed| *elapsed >= Duration::zero() && elapsed.num_hours() < HOURLY_STATS_HOURS)
+    {
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_hourly_stats (issue_id, kind, hour, shard, total)
+            VALUES ($1, $2, $3, $4, 1)
+            ON CONFLICT (issue_id, kind, hour, shard) DO UPDATE SET
+                total = issue_hourly_stats.total + 1
+            "#,
+            issue_id,
+            kind.as_str(),
+            elapsed.num_hours() as i16,
+            shard
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    if let Some(url) = url {
+        let is_first = is_first_event(transaction, issue_id, subscriber_id, kind, url).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_link_stats (issue_id, url, shard, total, unique_subscribers)
+            VALUES ($1, $2, $3, 1, $4)
+            ON CONFLICT (issue_id, url, shard) DO UPDATE SET
+                total = issue_link_stats.total + 1,
+                unique_subscribers =
+                    issue_link_stats.unique_subscribers + EXCLUDED.unique_subscribers
+            "#,
+            issue_id,
+            url,
+            shard,
+            i64::from(is_first)
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The running totals row of an issue an event adds to. Spreading subscribers
+/// over several rows keeps a burst of opens from serialising on one of them.
+fn stats_shard(subscriber_id: Uuid) -> i16 {
+    (subscriber_id.as_u128() % STATS_SHARDS) as i16
+}
+
+/// Whether this is the first time the subscriber did this with the issue,
+/// or with the given link of it.
+async fn is_first_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EmailEventKind,
+    url: &str,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO issue_event_subscribers (issue_id, subscriber_id, kind, url)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        subscriber_id,
+        kind.as_str(),
+        url
+    )
+    .execute(transaction)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
+
+/// Put an event that isn't tied to an issue, like an unsubscribe or a bounce,
+/// down to the last issue the subscriber was sent, if any. Returns that issue.
+#[tracing::instrument(name = "Recording an event for the last issue", skip(transaction))]
+pub async fn record_last_issue_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kind: EmailEventKind,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let issue_id = last_issue_sent(transaction, subscriber_id).await?;
+    if let Some(issue_id) = issue_id {
+        record_event(transaction, issue_id, subscriber_id, kind, None, None).await?;
+    }
+    Ok(issue_id)
+}
+
+/// The issue a subscriber was sent most recently.
+pub async fn last_issue_sent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT issue_id FROM email_events
+        WHERE subscriber_id = $1 AND kind = 'sent'
+        ORDER BY occurred_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(transaction)
+    .await
+}
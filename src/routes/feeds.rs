@@ -0,0 +1,252 @@
+use crate::routes::archive_url;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::header::{
+    EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, CACHE_CONTROL, ETAG, LAST_MODIFIED,
+};
+use actix_web::{web, HttpResponse};
+use atom_syndication::{ContentBuilder, EntryBuilder, FeedBuilder, LinkBuilder};
+use chrono::{DateTime, Utc};
+use rss::{ChannelBuilder, GuidBuilder, ItemBuilder};
+use sqlx::PgPool;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const FEED_TITLE: &str = "Newsletter";
+const FEED_ENTRIES: i64 = 20;
+
+struct FeedEntry {
+    id: Uuid,
+    slug: String,
+    title: String,
+    html: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Identifies what a feed currently contains. Published issues are immutable
+/// and can't be deleted, so their count and the latest publication time
+/// change whenever the feed does.
+struct FeedVersion {
+    etag: EntityTag,
+    last_modified: DateTime<Utc>,
+}
+
+impl FeedVersion {
+    fn is_fresh(
+        &self,
+        if_none_match: Option<web::Header<IfNoneMatch>>,
+        if_modified_since: Option<web::Header<IfModifiedSince>>,
+    ) -> bool {
+        // The extractor yields an empty list when the header is missing
+        let if_none_match = if_none_match.map(web::Header::into_inner).filter(
+            |if_none_match| !matches!(if_none_match, IfNoneMatch::Items(etags) if etags.is_empty()),
+        );
+        // If-Modified-Since is ignored when If-None-Match is present
+        match (if_none_match, if_modified_since) {
+            (Some(IfNoneMatch::Any), _) => true,
+            (Some(IfNoneMatch::Items(etags)), _) => {
+                etags.iter().any(|etag| etag.weak_eq(&self.etag))
+            }
+            (None, Some(if_modified_since)) => {
+                // HTTP dates have whole-second precision
+                let since = SystemTime::from(if_modified_since.into_inner().0);
+                self.last_modified.timestamp() <= DateTime::<Utc>::from(since).timestamp()
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Serving the Atom feed",
+    skip(pool, base_url, if_none_match, if_modified_since)
+)]
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> HttpResponse {
+    serve_feed(
+        &pool,
+        if_none_match,
+        if_modified_since,
+        "application/atom+xml; charset=utf-8",
+        |version, entries| render_atom(&base_url.0, version, entries),
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Serving the RSS feed",
+    skip(pool, base_url, if_none_match, if_modified_since)
+)]
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> HttpResponse {
+    serve_feed(
+        &pool,
+        if_none_match,
+        if_modified_since,
+        "application/rss+xml; charset=utf-8",
+        |version, entries| render_rss(&base_url.0, version, entries),
+    )
+    .await
+}
+
+/// Answer conditional requests from the feed version alone, and only load
+/// the issues when the reader's copy is out of date.
+async fn serve_feed(
+    pool: &PgPool,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+    content_type: &str,
+    render: impl FnOnce(&FeedVersion, Vec<FeedEntry>) -> String,
+) -> HttpResponse {
+    let version = match get_feed_version(pool).await {
+        Ok(version) => version,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let last_modified = HttpDate::from(SystemTime::from(version.last_modified));
+    if version.is_fresh(if_none_match, if_modified_since) {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, version.etag.to_string()))
+            .insert_header((LAST_MODIFIED, last_modified.to_string()))
+            .finish();
+    }
+
+    let entries = match get_feed_entries(pool).await {
+        Ok(entries) => entries,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((ETAG, version.etag.to_string()))
+        .insert_header((LAST_MODIFIED, last_modified.to_string()))
+        .insert_header((CACHE_CONTROL, "public, max-age=300"))
+        .body(render(&version, entries))
+}
+
+fn render_atom(base_url: &str, version: &FeedVersion, entries: Vec<FeedEntry>) -> String {
+    let entries = entries
+        .into_iter()
+        .map(|entry| {
+            EntryBuilder::default()
+                .id(format!("urn:uuid:{}", entry.id))
+                .title(entry.title)
+                .updated(entry.published_at)
+                .published(Some(entry.published_at.into()))
+                .link(
+                    LinkBuilder::default()
+                        .href(archive_url(base_url, &entry.slug))
+                        .rel("alternate")
+                        .mime_type(Some("text/html".into()))
+                        .build(),
+                )
+                .content(Some(
+                    ContentBuilder::default()
+                        .value(Some(entry.html))
+                        .content_type(Some("html".into()))
+                        .build(),
+                ))
+                .build()
+        })
+        .collect::<Vec<_>>();
+    FeedBuilder::default()
+        .id(format!("{}/feed.xml", base_url))
+        .title(FEED_TITLE)
+        .updated(version.last_modified)
+        .links(vec![
+            LinkBuilder::default()
+                .href(format!("{}/feed.xml", base_url))
+                .rel("self")
+                .mime_type(Some("application/atom+xml".into()))
+                .build(),
+            LinkBuilder::default()
+                .href(format!("{}/archive", base_url))
+                .rel("alternate")
+                .mime_type(Some("text/html".into()))
+                .build(),
+        ])
+        .entries(entries)
+        .build()
+        .to_string()
+}
+
+fn render_rss(base_url: &str, version: &FeedVersion, entries: Vec<FeedEntry>) -> String {
+    let items = entries
+        .into_iter()
+        .map(|entry| {
+            ItemBuilder::default()
+                .guid(Some(
+                    GuidBuilder::default()
+                        .value(format!("urn:uuid:{}", entry.id))
+                        .permalink(false)
+                        .build(),
+                ))
+                .title(Some(entry.title))
+                .link(Some(archive_url(base_url, &entry.slug)))
+                .pub_date(Some(entry.published_at.to_rfc2822()))
+                .description(Some(entry.html))
+                .build()
+        })
+        .collect::<Vec<_>>();
+    ChannelBuilder::default()
+        .title(FEED_TITLE)
+        .link(format!("{}/archive", base_url))
+        .description("Published issues of the newsletter")
+        .last_build_date(Some(version.last_modified.to_rfc2822()))
+        .items(items)
+        .build()
+        .to_string()
+}
+
+#[tracing::instrument(name = "Get feed version", skip(pool))]
+async fn get_feed_version(pool: &PgPool) -> Result<FeedVersion, sqlx::Error> {
+    let state = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!", MAX(published_at) AS last_published_at
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let last_modified = state.last_published_at.unwrap_or(DateTime::UNIX_EPOCH);
+    Ok(FeedVersion {
+        etag: EntityTag::new_strong(format!(
+            "{}-{}",
+            state.count,
+            last_modified.timestamp_micros()
+        )),
+        last_modified,
+    })
+}
+
+#[tracing::instrument(name = "Get feed entries", skip(pool))]
+async fn get_feed_entries(pool: &PgPool) -> Result<Vec<FeedEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        FeedEntry,
+        r#"
+        SELECT id, slug AS "slug!", published_title AS "title!", published_html AS "html!",
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        FEED_ENTRIES
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
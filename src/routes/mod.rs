@@ -1,5 +1,23 @@
+mod admin;
+mod archive;
+mod email_events;
+mod feeds;
 mod health_check;
+mod preferences;
+mod privacy;
 mod subscriptions;
+mod subscriptions_confirm;
+mod unsubscribe;
+mod webhooks;
 
+pub use admin::*;
+pub use archive::*;
+pub use email_events::*;
+pub use feeds::*;
 pub use health_check::*;
+pub use preferences::*;
+pub use privacy::*;
 pub use subscriptions::*;
+pub use subscriptions_confirm::*;
+pub use unsubscribe::*;
+pub use webhooks::*;
@@ -0,0 +1,389 @@
+use super::{load_preferences, preferences_page, record_change};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{EmailTemplates, TemplateContext, TemplateName};
+use crate::routes::{generate_subscription_token, get_subscriber_from_token, send_templated_email};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression::{lift_suppression, suppression_reason};
+use actix_web::http::header::ContentType;
+use actix_web::web::Form;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use tera::escape_html;
+use uuid::Uuid;
+
+/// How long the link sent to the new address stays valid.
+const EMAIL_CHANGE_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeFormData {
+    subscription_token: String,
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeParameters {
+    token: String,
+}
+
+struct EmailChange {
+    subscriber_id: Uuid,
+    old_email: String,
+    new_email: String,
+}
+
+#[derive(Debug)]
+pub enum EmailChangeError {
+    /// Another subscriber already has the new address
+    AddressTaken,
+    /// The new address bounced or complained, so it can't be mailed
+    AddressSuppressed,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for EmailChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmailChangeError::AddressTaken => write!(
+                f,
+                "This address is already subscribed. Unsubscribe it before moving your subscription to it."
+            ),
+            EmailChangeError::AddressSuppressed => {
+                write!(f, "We can't send email to this address.")
+            }
+            EmailChangeError::Database(e) => write!(f, "Failed to change the address: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmailChangeError {}
+
+impl From<sqlx::Error> for EmailChangeError {
+    fn from(e: sqlx::Error) -> Self {
+        EmailChangeError::Database(e)
+    }
+}
+
+impl EmailChangeError {
+    pub fn into_response(self) -> HttpResponse {
+        match self {
+            EmailChangeError::AddressTaken => HttpResponse::Conflict().body(self.to_string()),
+            EmailChangeError::AddressSuppressed => {
+                HttpResponse::BadRequest().body(self.to_string())
+            }
+            EmailChangeError::Database(e) => {
+                tracing::error!("Failed to execute query: {:?}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+/// Send a verification link to the address a subscriber wants to move to.
+///
+/// Nothing changes until the link is followed. Whether the new address is
+/// already subscribed only comes out then, once its owner has proven who
+/// they are.
+#[tracing::instrument(
+    name = "Requesting an email change",
+    skip(form, pool, email_client, templates, base_url)
+)]
+pub async fn request_email_change(
+    form: Form<EmailChangeFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let subscriber = match get_subscriber_from_token(&pool, &form.subscription_token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let new_email = match SubscriberEmail::parse(form.email) {
+        Ok(new_email) => new_email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    // Compared the way the UNIQUE constraint on `subscriptions.email` does
+    if new_email.as_ref() == subscriber.email {
+        return HttpResponse::BadRequest().body("This is already your address.");
+    }
+    match suppression_reason(pool.get_ref(), new_email.as_ref()).await {
+        Ok(Some(reason)) if reason.is_permanent() => {
+            return EmailChangeError::AddressSuppressed.into_response()
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let token = generate_subscription_token();
+    if store_email_change_request(&pool, subscriber.id, &new_email, &token)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let notice = format!(
+        "We sent a link to {} to confirm the change.",
+        new_email.as_ref()
+    );
+    if let Err(e) = send_verification_email(
+        &email_client,
+        &templates,
+        &pool,
+        new_email,
+        &base_url.0,
+        &token,
+    )
+    .await
+    {
+        tracing::error!("Failed to send email change verification: {:?}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match load_preferences(&pool, subscriber.id).await {
+        Ok((preferences, lists)) => preferences_page(
+            &form.subscription_token,
+            &preferences,
+            &lists,
+            Some(&notice),
+        ),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Where the verification link lands. Opening it only asks for confirmation,
+/// as mail scanners follow links on their own.
+#[tracing::instrument(name = "Email change confirmation form", skip(parameters, pool))]
+pub async fn confirm_email_change_form(
+    parameters: web::Query<EmailChangeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let new_email = match pending_email_change(&pool, &parameters.token).await {
+        Ok(Some(new_email)) => new_email,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Confirm your new address</title></head>
+<body>
+    <p>Your newsletter will be sent to {}.</p>
+    <form action="/preferences/email/confirm?token={}" method="post">
+        <button type="submit">Confirm</button>
+    </form>
+</body>
+</html>"#,
+            escape_html(&new_email),
+            escape_html(&parameters.token)
+        ))
+}
+
+/// Swap in the verified address, then let the old one know it was replaced.
+#[tracing::instrument(
+    name = "Confirming an email change",
+    skip(parameters, pool, email_client, templates)
+)]
+pub async fn confirm_email_change(
+    parameters: web::Query<EmailChangeParameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let change = match change_email(&pool, &parameters.token).await {
+        Ok(Some(change)) => change,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(e) => return e.into_response(),
+    };
+    tracing::info!("Changed the address of subscriber {}", change.subscriber_id);
+
+    // The address is already changed, a lost notice is not worth failing over
+    if let Err(e) = send_change_notice(&email_client, &templates, &pool, &change).await {
+        tracing::warn!("Failed to notify the old address: {:?}", e);
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[tracing::instrument(name = "Store email change request", skip(pool, new_email, token))]
+async fn store_email_change_request(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests
+            (email_change_token, subscriber_id, new_email, requested_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        token,
+        subscriber_id,
+        new_email.as_ref(),
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// The address a pending, unexpired change moves to.
+#[tracing::instrument(name = "Get pending email change", skip(pool, token))]
+async fn pending_email_change(pool: &PgPool, token: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT new_email FROM email_change_requests
+        WHERE email_change_token = $1 AND requested_at > $2
+        "#,
+        token,
+        expires_before()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+fn expires_before() -> DateTime<Utc> {
+    Utc::now() - Duration::hours(EMAIL_CHANGE_TOKEN_TTL_HOURS)
+}
+
+/// Apply a pending change and log it. Other pending changes of the same
+/// subscriber are dropped, so only the latest verified address sticks.
+#[tracing::instrument(name = "Change subscriber email", skip(pool, token))]
+async fn change_email(pool: &PgPool, token: &str) -> Result<Option<EmailChange>, EmailChangeError> {
+    let mut transaction = pool.begin().await?;
+    let change = sqlx::query_as!(
+        EmailChange,
+        r#"
+        SELECT r.subscriber_id, s.email AS old_email, r.new_email
+        FROM email_change_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.email_change_token = $1 AND r.requested_at > $2
+        FOR UPDATE
+        "#,
+        token,
+        expires_before()
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let change = match change {
+        Some(change) => change,
+        None => return Ok(None),
+    };
+
+    // Addresses differing only in case reach the same mailbox, even though
+    // the UNIQUE constraint tells them apart
+    let taken = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM subscriptions WHERE lower(email) = lower($1) AND id <> $2
+        ) AS "taken!"
+        "#,
+        change.new_email,
+        change.subscriber_id
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    if taken {
+        return Err(EmailChangeError::AddressTaken);
+    }
+    // The address may have bounced or complained since the link was sent
+    match suppression_reason(&mut transaction, &change.new_email).await? {
+        Some(reason) if reason.is_permanent() => return Err(EmailChangeError::AddressSuppressed),
+        // Confirming the change opts the new address in, as a signup would
+        _ => lift_suppression(&mut transaction, &change.new_email).await?,
+    }
+
+    let updated = sqlx::query!(
+        "UPDATE subscriptions SET email = $2 WHERE id = $1",
+        change.subscriber_id,
+        change.new_email
+    )
+    .execute(&mut transaction)
+    .await;
+    match updated {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+            return Err(EmailChangeError::AddressTaken)
+        }
+        Err(e) => return Err(e.into()),
+    }
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        change.subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    record_change(
+        &mut transaction,
+        change.subscriber_id,
+        "email",
+        Some(&change.old_email),
+        Some(&change.new_email),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(Some(change))
+}
+
+#[tracing::instrument(
+    name = "Send email change verification",
+    skip(email_client, templates, pool, recipient, base_url, token)
+)]
+async fn send_verification_email(
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    recipient: SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let context = TemplateContext {
+        confirmation_link: format!("{}/preferences/email/confirm?token={}", base_url, token),
+        ..Default::default()
+    };
+    send_templated_email(
+        email_client,
+        templates,
+        pool,
+        recipient.as_ref().to_string(),
+        TemplateName::EmailChangeVerification,
+        &context,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Send email change notice", skip_all)]
+async fn send_change_notice(
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    change: &EmailChange,
+) -> Result<(), anyhow::Error> {
+    let context = TemplateContext {
+        new_email: change.new_email.clone(),
+        ..Default::default()
+    };
+    send_templated_email(
+        email_client,
+        templates,
+        pool,
+        change.old_email.clone(),
+        TemplateName::EmailChangeNotice,
+        &context,
+    )
+    .await
+}
@@ -0,0 +1,452 @@
+mod email_change;
+
+pub use email_change::*;
+
+use crate::activity::record_activity;
+use crate::domain::{ActivityKind, DeliveryFrequency, EmailEventKind, SubscriberName};
+use crate::routes::{
+    create_subscription_token, get_subscriber_from_token, record_last_issue_event, unsubscribe_url,
+    ListError, Parameters,
+};
+use crate::suppression::{
+    lift_suppression, suppress_subscriber, SuppressionReason, SuppressionSource,
+};
+use actix_web::http::header::ContentType;
+use actix_web::web::Form;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use tera::escape_html;
+use uuid::Uuid;
+
+/// Longest a subscriber can pause delivery for, in weeks
+const MAX_PAUSE_WEEKS: i64 = 52;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    subscription_token: String,
+    name: String,
+    frequency: String,
+    /// Weeks to pause delivery for: `0` resumes it, blank leaves it as it is
+    #[serde(default)]
+    pause_weeks: String,
+    /// A `lists[<slug>]` checkbox for each list to be on, and a
+    /// `tracking_opt_out` one to turn off open tracking
+    #[serde(flatten)]
+    checkboxes: HashMap<String, String>,
+}
+
+struct PreferenceUpdate {
+    name: SubscriberName,
+    frequency: DeliveryFrequency,
+    pause_weeks: Option<i64>,
+    tracking_opt_out: bool,
+    lists: Vec<String>,
+}
+
+impl PreferencesFormData {
+    fn parse(self) -> Result<PreferenceUpdate, String> {
+        let pause_weeks = match self.pause_weeks.trim() {
+            "" => None,
+            weeks => match weeks.parse() {
+                Ok(weeks) if (0..=MAX_PAUSE_WEEKS).contains(&weeks) => Some(weeks),
+                _ => return Err(format!("Pauses last from 0 to {} weeks.", MAX_PAUSE_WEEKS)),
+            },
+        };
+        let tracking_opt_out = self.checkboxes.contains_key("tracking_opt_out");
+        let lists = self
+            .checkboxes
+            .into_keys()
+            .filter_map(|key| {
+                key.strip_prefix("lists[")
+                    .and_then(|key| key.strip_suffix(']'))
+                    .map(String::from)
+            })
+            .collect();
+        Ok(PreferenceUpdate {
+            name: SubscriberName::parse(self.name)?,
+            frequency: DeliveryFrequency::parse(self.frequency)?,
+            pause_weeks,
+            tracking_opt_out,
+            lists,
+        })
+    }
+}
+
+struct Preferences {
+    email: String,
+    name: String,
+    frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    tracking_opt_out: bool,
+}
+
+struct ListChoice {
+    id: Uuid,
+    slug: String,
+    name: String,
+    /// The subscriber's status on the list, if they were ever on it
+    status: Option<String>,
+}
+
+impl ListChoice {
+    fn is_joined(&self) -> bool {
+        matches!(
+            self.status.as_deref(),
+            Some("confirmed" | "pending_confirmation")
+        )
+    }
+}
+
+/// Let a subscriber manage their subscription from the link in their emails.
+#[tracing::instrument(name = "Showing subscriber preferences", skip(parameters, pool))]
+pub async fn preferences_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match load_preferences(&pool, subscriber.id).await {
+        Ok((preferences, lists)) => {
+            preferences_page(&parameters.subscription_token, &preferences, &lists, None)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Updating subscriber preferences", skip(form, pool))]
+pub async fn update_preferences(
+    form: Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let subscription_token = form.subscription_token.clone();
+    let subscriber = match get_subscriber_from_token(&pool, &subscription_token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let update = match form.parse() {
+        Ok(update) => update,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match save_preferences(&pool, subscriber.id, &update).await {
+        Ok(()) => {}
+        Err(ListError::UnknownList(slug)) => {
+            return HttpResponse::BadRequest().body(ListError::UnknownList(slug).to_string())
+        }
+        Err(ListError::Database(_)) => return HttpResponse::InternalServerError().finish(),
+    }
+    match load_preferences(&pool, subscriber.id).await {
+        Ok((preferences, lists)) => preferences_page(
+            &subscription_token,
+            &preferences,
+            &lists,
+            Some("Your preferences have been saved."),
+        ),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Loading subscriber preferences", skip(pool))]
+async fn load_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(Preferences, Vec<ListChoice>), sqlx::Error> {
+    let preferences = sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT email, name, frequency, paused_until, tracking_opt_out FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let lists = list_choices(pool, subscriber_id).await?;
+    Ok((preferences, lists))
+}
+
+async fn list_choices(
+    executor: impl sqlx::PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListChoice>, sqlx::Error> {
+    sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT l.id, l.slug, l.name, ls.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.id AND ls.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+/// Apply an update and record every change it makes in `preference_changes`.
+///
+/// Ticking a list confirms the subscriber on it straight away: the token they
+/// came in with already proves they own the address. For the same reason it
+/// lifts a suppression left by an earlier unsubscribe.
+#[tracing::instrument(name = "Saving subscriber preferences", skip(pool, update))]
+async fn save_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    update: &PreferenceUpdate,
+) -> Result<(), ListError> {
+    let mut transaction = pool.begin().await?;
+    let now = Utc::now();
+    let lists = list_choices(&mut transaction, subscriber_id).await?;
+    if let Some(slug) = update
+        .lists
+        .iter()
+        .find(|slug| !lists.iter().any(|list| &list.slug == *slug))
+    {
+        return Err(ListError::UnknownList(slug.clone()));
+    }
+
+    let current = sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT email, name, frequency, paused_until, tracking_opt_out FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let paused_until = match update.pause_weeks {
+        None => current.paused_until,
+        Some(0) => None,
+        Some(weeks) => Some(now + Duration::weeks(weeks)),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, frequency = $3, paused_until = $4, tracking_opt_out = $5
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        update.name.as_ref(),
+        update.frequency.as_str(),
+        paused_until,
+        update.tracking_opt_out
+    )
+    .execute(&mut transaction)
+    .await?;
+    if current.name != update.name.as_ref() {
+        let (old, new) = (current.name.as_str(), update.name.as_ref());
+        record_change(
+            &mut transaction,
+            subscriber_id,
+            "name",
+            Some(old),
+            Some(new),
+        )
+        .await?;
+    }
+    if current.frequency != update.frequency.as_str() {
+        let (old, new) = (current.frequency.as_str(), update.frequency.as_str());
+        record_change(
+            &mut transaction,
+            subscriber_id,
+            "frequency",
+            Some(old),
+            Some(new),
+        )
+        .await?;
+    }
+    if current.paused_until != paused_until {
+        record_change(
+            &mut transaction,
+            subscriber_id,
+            "paused_until",
+            current.paused_until.map(|t| t.to_rfc3339()).as_deref(),
+            paused_until.map(|t| t.to_rfc3339()).as_deref(),
+        )
+        .await?;
+    }
+    if current.tracking_opt_out != update.tracking_opt_out {
+        record_change(
+            &mut transaction,
+            subscriber_id,
+            "tracking_opt_out",
+            Some(&current.tracking_opt_out.to_string()),
+            Some(&update.tracking_opt_out.to_string()),
+        )
+        .await?;
+    }
+
+    for list in &lists {
+        let status = match (list.is_joined(), update.lists.contains(&list.slug)) {
+            (false, true) => "confirmed",
+            (true, false) => "unsubscribed",
+            _ => continue,
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE SET status = EXCLUDED.status
+            "#,
+            list.id,
+            subscriber_id,
+            status,
+            now
+        )
+        .execute(&mut transaction)
+        .await?;
+        if status == "unsubscribed" {
+            let kind = EmailEventKind::Unsubscribe;
+            let issue_id = record_last_issue_event(&mut transaction, subscriber_id, kind).await?;
+            record_activity(
+                &mut transaction,
+                subscriber_id,
+                ActivityKind::Unsubscribe,
+                issue_id,
+                Some(list.id),
+                Some("Preference center"),
+            )
+            .await?;
+            let (reason, source) = (
+                SuppressionReason::Unsubscribe,
+                SuppressionSource::PreferenceCenter,
+            );
+            suppress_subscriber(&mut transaction, subscriber_id, reason, source).await?;
+        } else {
+            create_subscription_token(&mut transaction, subscriber_id, list.id).await?;
+            lift_suppression(&mut transaction, &current.email).await?;
+        }
+        record_change(
+            &mut transaction,
+            subscriber_id,
+            &format!("list:{}", list.slug),
+            list.status.as_deref(),
+            Some(status),
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn record_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO preference_changes
+            (id, subscriber_id, field, old_value, new_value, changed_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        field,
+        old_value,
+        new_value,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+fn preferences_page(
+    subscription_token: &str,
+    preferences: &Preferences,
+    lists: &[ListChoice],
+    notice: Option<&str>,
+) -> HttpResponse {
+    let checked = |is_checked: bool| if is_checked { " checked" } else { "" };
+    let notice = notice
+        .map(|notice| format!("\n    <p>{}</p>", escape_html(notice)))
+        .unwrap_or_default();
+    let lists: String = lists
+        .iter()
+        .map(|list| {
+            format!(
+                "\n            <label><input type=\"checkbox\" name=\"lists[{}]\"{}> {}</label>",
+                escape_html(&list.slug),
+                checked(list.is_joined()),
+                escape_html(&list.name)
+            )
+        })
+        .collect();
+    let paused = match preferences.paused_until {
+        Some(paused_until) if paused_until > Utc::now() => format!(
+            "Delivery is paused until {}. Enter 0 to resume it now. ",
+            paused_until.format("%Y-%m-%d")
+        ),
+        _ => String::new(),
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Your preferences</title></head>
+<body>{notice}
+    <form action="/preferences" method="post">
+        <input type="hidden" name="subscription_token" value="{token}">
+        <p><label>Name <input type="text" name="name" value="{name}"></label></p>
+        <fieldset>
+            <legend>Lists</legend>{lists}
+        </fieldset>
+        <fieldset>
+            <legend>How often</legend>
+            <label><input type="radio" name="frequency" value="every_issue"{every_issue}> Every issue</label>
+            <label><input type="radio" name="frequency" value="weekly_digest"{weekly_digest}> A weekly digest</label>
+        </fieldset>
+        <p>{paused}<label>Pause delivery for <input type="number" name="pause_weeks" min="0" max="{max_pause_weeks}"> weeks</label></p>
+        <p><label><input type="checkbox" name="tracking_opt_out"{tracking_opt_out}> Don't track when I open emails</label></p>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/preferences/email" method="post">
+        <input type="hidden" name="subscription_token" value="{token}">
+        <p><label>Email <input type="email" name="email" value="{email}"></label></p>
+        <button type="submit">Change address</button>
+    </form>
+    <p><a href="{unsubscribe_url}">Unsubscribe</a></p>
+</body>
+</html>"#,
+            token = escape_html(subscription_token),
+            email = escape_html(&preferences.email),
+            name = escape_html(&preferences.name),
+            every_issue = checked(preferences.frequency == "every_issue"),
+            weekly_digest = checked(preferences.frequency == "weekly_digest"),
+            max_pause_weeks = MAX_PAUSE_WEEKS,
+            tracking_opt_out = checked(preferences.tracking_opt_out),
+            unsubscribe_url = escape_html(&unsubscribe_url("", subscription_token)),
+        ))
+}
+
+/// Where subscribers manage their subscription, from any of their tokens.
+pub fn preferences_url(base_url: &str, subscription_token: &str) -> String {
+    format!(
+        "{}/preferences?subscription_token={}",
+        base_url, subscription_token
+    )
+}
@@ -0,0 +1,238 @@
+use crate::routes::privacy::{get_subscriber_id_from_token, PrivacyTokenParameters};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    subscription: SubscriptionData,
+    lists: Vec<ListMembership>,
+    subscription_tokens: Vec<String>,
+    privacy_requests: Vec<DateTime<Utc>>,
+    preference_changes: Vec<PreferenceChange>,
+    email_change_requests: Vec<EmailChangeRequest>,
+    email_events: Vec<EmailEvent>,
+    activity: Vec<Activity>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionData {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    time_zone: Option<String>,
+    tags: Vec<String>,
+    attributes: serde_json::Value,
+    frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    tracking_opt_out: bool,
+}
+
+#[derive(serde::Serialize)]
+struct ListMembership {
+    list: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct PreferenceChange {
+    field: String,
+    old_value: Option<String>,
+    new_value: Option<String>,
+    changed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailChangeRequest {
+    new_email: String,
+    requested_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct Activity {
+    kind: String,
+    occurred_at: DateTime<Utc>,
+    issue_id: Option<Uuid>,
+    detail: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailEvent {
+    issue_id: Uuid,
+    kind: String,
+    occurred_at: DateTime<Utc>,
+    url: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[tracing::instrument(name = "Exporting subscriber data", skip(parameters, pool))]
+pub async fn export_subscriber_data(
+    parameters: web::Query<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let subscriber_id = match get_subscriber_id_from_token(&parameters.token, &pool).await {
+        Ok(Some(subscriber_id)) => subscriber_id,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match collect_subscriber_data(subscriber_id, &pool).await {
+        Ok(data) => HttpResponse::Ok()
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"subscriber-data.json\"",
+            ))
+            .json(data),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Collect everything stored about a subscriber", skip(pool))]
+pub async fn collect_subscriber_data(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<SubscriberDataExport, sqlx::Error> {
+    let subscription = sqlx::query_as!(
+        SubscriptionData,
+        r#"
+        SELECT id, email, name, subscribed_at, time_zone, tags, attributes, frequency,
+            paused_until, tracking_opt_out
+        FROM subscriptions WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let lists = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.slug AS list, ls.status, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY ls.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let subscription_tokens = sqlx::query!(
+        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+
+    let privacy_requests = sqlx::query!(
+        r#"
+        SELECT requested_at FROM privacy_request_tokens
+        WHERE subscriber_id = $1
+        ORDER BY requested_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .into_iter()
+    .map(|r| r.requested_at)
+    .collect();
+
+    let preference_changes = sqlx::query_as!(
+        PreferenceChange,
+        r#"
+        SELECT field, old_value, new_value, changed_at FROM preference_changes
+        WHERE subscriber_id = $1
+        ORDER BY changed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let email_change_requests = sqlx::query_as!(
+        EmailChangeRequest,
+        r#"
+        SELECT new_email, requested_at FROM email_change_requests
+        WHERE subscriber_id = $1
+        ORDER BY requested_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let email_events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT issue_id, kind, occurred_at, url, user_agent FROM email_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let activity = sqlx::query_as!(
+        Activity,
+        r#"
+        SELECT kind, occurred_at, issue_id, detail FROM subscriber_activity
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(SubscriberDataExport {
+        subscription,
+        lists,
+        subscription_tokens,
+        privacy_requests,
+        preference_changes,
+        email_change_requests,
+        email_events,
+        activity,
+    })
+}
@@ -0,0 +1,90 @@
+use crate::routes::privacy::{get_subscriber_id_from_token, PrivacyTokenParameters};
+use crate::suppression::{suppress, SuppressionReason, SuppressionSource};
+use actix_web::http::header::ContentType;
+use actix_web::web::Form;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use tera::escape_html;
+use uuid::Uuid;
+
+/// Ask for confirmation before erasing, so a link prefetched by a mail
+/// scanner can't delete anything.
+#[tracing::instrument(name = "Showing erasure confirmation", skip(parameters, pool))]
+pub async fn erasure_form(
+    parameters: web::Query<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match get_subscriber_id_from_token(&parameters.token, &pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Erase your data</title></head>
+<body>
+    <p>This permanently deletes your subscription and everything we store about you.</p>
+    <form action="/privacy/erase" method="post">
+        <input type="hidden" name="token" value="{}">
+        <button type="submit">Erase my data</button>
+    </form>
+</body>
+</html>"#,
+            escape_html(&parameters.token)
+        ))
+}
+
+#[tracing::instrument(name = "Erasing subscriber data", skip(form, pool))]
+pub async fn erase_subscriber_data(
+    form: Form<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let subscriber_id = match get_subscriber_id_from_token(&form.token, &pool).await {
+        Ok(Some(subscriber_id)) => subscriber_id,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match erase_subscriber(subscriber_id, &pool).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Hard-delete a subscriber, keeping only the hash of their address.
+///
+/// Rows referencing the subscriber are removed by `ON DELETE CASCADE`.
+#[tracing::instrument(name = "Erase subscriber", skip(pool))]
+pub async fn erase_subscriber(subscriber_id: Uuid, pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let erased = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    if let Some(erased) = erased {
+        suppress(
+            &mut transaction,
+            &erased.email,
+            SuppressionReason::Erasure,
+            SuppressionSource::PrivacyRequest,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
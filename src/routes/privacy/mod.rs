@@ -0,0 +1,55 @@
+mod data_export;
+mod erasure;
+mod request;
+
+pub use data_export::*;
+pub use erasure::*;
+pub use request::*;
+
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long the links sent by email stay valid.
+const PRIVACY_REQUEST_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct PrivacyTokenParameters {
+    token: String,
+}
+
+fn generate_privacy_request_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+#[tracing::instrument(
+    name = "Get subscriber id from privacy request token",
+    skip(token, pool)
+)]
+async fn get_subscriber_id_from_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let expires_before = Utc::now() - Duration::hours(PRIVACY_REQUEST_TOKEN_TTL_HOURS);
+    let result = sqlx::query!(
+        r#"
+        SELECT subscriber_id FROM privacy_request_tokens
+        WHERE privacy_request_token = $1 AND requested_at > $2
+        "#,
+        token,
+        expires_before
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.subscriber_id))
+}
@@ -0,0 +1,138 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{EmailTemplates, TemplateContext, TemplateName};
+use crate::routes::privacy::generate_privacy_request_token;
+use crate::routes::send_templated_email;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::web::Form;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct PrivacyRequestFormData {
+    email: String,
+}
+
+/// Email a subscriber the links to download or erase their data.
+///
+/// Any valid address gets the same 200 straight away and the email is sent in
+/// the background, so neither the response nor its timing tells who is on
+/// the list.
+#[tracing::instrument(
+    name = "Requesting access to subscriber data",
+    skip(form, pool, email_client, templates, base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_privacy_links(
+    form: Form<PrivacyRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let email = match SubscriberEmail::parse(form.into_inner().email) {
+        Ok(email) => email,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    tokio::spawn(
+        async move {
+            if let Err(e) =
+                send_privacy_links(&pool, &email_client, &templates, &base_url.0, email).await
+            {
+                tracing::error!("Failed to send privacy links: {:?}", e);
+            }
+        }
+        .in_current_span(),
+    );
+    HttpResponse::Ok().finish()
+}
+
+async fn send_privacy_links(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    base_url: &str,
+    email: SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let subscriber_id = match get_subscriber_id_from_email(&email, pool).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(()),
+    };
+    let token = generate_privacy_request_token();
+    store_privacy_request_token(subscriber_id, &token, pool).await?;
+    send_privacy_links_email(email_client, templates, pool, email, base_url, &token).await
+}
+
+#[tracing::instrument(name = "Get subscriber id from email", skip(email, pool))]
+async fn get_subscriber_id_from_email(
+    email: &SubscriberEmail,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email = $1",
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Store privacy request token", skip(token, pool))]
+async fn store_privacy_request_token(
+    subscriber_id: Uuid,
+    token: &str,
+    pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO privacy_request_tokens (privacy_request_token, subscriber_id, requested_at)
+        VALUES ($1, $2, $3)
+        "#,
+        token,
+        subscriber_id,
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send privacy links email",
+    skip(email_client, templates, pool, recipient, base_url, token)
+)]
+async fn send_privacy_links_email(
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    recipient: SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let context = TemplateContext {
+        data_url: format!("{}/privacy/data?token={}", base_url, token),
+        erasure_url: format!("{}/privacy/erase?token={}", base_url, token),
+        ..Default::default()
+    };
+    send_templated_email(
+        email_client,
+        templates,
+        pool,
+        recipient.as_ref().to_string(),
+        TemplateName::PrivacyLinks,
+        &context,
+    )
+    .await
+}
@@ -1,70 +1,310 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::activity::{describe_send, record_activity};
+use crate::configuration::SubscriberFieldSettings;
+use crate::domain::{
+    ActivityKind, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName,
+    SubscriberTag, SubscriberTimeZone,
+};
+use crate::email_client::{EmailClient, ProviderResponse};
+use crate::email_templates::{EmailTemplates, TemplateContext, TemplateName};
+use crate::routes::{find_list_id, send_unless_suppressed, DEFAULT_LIST};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression::suppression_reason;
 use actix_web::web::Form;
 use actix_web::{web, HttpResponse};
 use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 use sqlx::types::uuid;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// IANA time zone, filled in by the signup page from the browser
+    time_zone: Option<String>,
+    /// Slug of the list to join, the default list when absent
+    list: Option<String>,
+    /// Hidden fields: comma-separated `tags`, and `attributes[<name>]` for
+    /// each custom attribute. Anything else is ignored.
+    #[serde(flatten)]
+    hidden_fields: HashMap<String, String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let subscriber_email = SubscriberEmail::parse(form.email)?;
-        let subscriber_name = SubscriberName::parse(form.name)?;
+impl FormData {
+    fn parse(self, settings: &SubscriberFieldSettings) -> Result<NewSubscriber, String> {
+        let subscriber_email = SubscriberEmail::parse(self.email)?;
+        let subscriber_name = SubscriberName::parse(self.name)?;
+        let time_zone = self
+            .time_zone
+            .filter(|time_zone| !time_zone.trim().is_empty())
+            .map(SubscriberTimeZone::parse)
+            .transpose()?;
+        let mut tags = Vec::new();
+        let mut attributes = Vec::new();
+        for (field, value) in self.hidden_fields {
+            if field == "tags" {
+                tags.extend(
+                    value
+                        .split(',')
+                        .filter(|tag| !tag.trim().is_empty())
+                        .map(str::to_string),
+                );
+            } else if let Some(name) = field
+                .strip_prefix("attributes[")
+                .and_then(|name| name.strip_suffix(']'))
+            {
+                attributes.push((name.to_string(), value));
+            }
+        }
+        let tags = SubscriberTag::parse_all(tags)?;
+        if let Some(tag) = tags
+            .iter()
+            .find(|tag| !settings.signup_form_tags.iter().any(|t| t == tag.as_ref()))
+        {
+            return Err(format!(
+                "The signup form can't set the tag {}.",
+                tag.as_ref()
+            ));
+        }
         Ok(NewSubscriber {
             email: subscriber_email,
             name: subscriber_name,
+            time_zone,
+            tags,
+            attributes: SubscriberAttributes::from_form(attributes, &settings.attributes)?,
         })
     }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool),
+    skip(form, pool, email_client, base_url, templates, subscriber_fields),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
-pub async fn subscribe(form: Form<FormData>, pool: web::Data<PgPool>) -> HttpResponse {
-    let new_subscriber = match form.into_inner().try_into() {
+pub async fn subscribe(
+    form: Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<EmailTemplates>,
+    subscriber_fields: web::Data<SubscriberFieldSettings>,
+) -> HttpResponse {
+    let mut form = form.into_inner();
+    let list = form
+        .list
+        .take()
+        .filter(|list| !list.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_LIST.into());
+    let new_subscriber = match form.parse(&subscriber_fields) {
         Ok(new_subscriber) => new_subscriber,
         Err(_) => return HttpResponse::BadRequest().finish(),
     };
+    let list_id = match find_list_id(pool.get_ref(), &list).await {
+        Ok(Some(list_id)) => list_id,
+        Ok(None) => {
+            return HttpResponse::BadRequest().body(format!("There is no list named {}.", list))
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Not even a confirmation email goes to an address that bounced or
+    // complained, and whoever filled in the form is not told why
+    match suppression_reason(pool.get_ref(), new_subscriber.email.as_ref()).await {
+        Ok(Some(reason)) if reason.is_permanent() => return HttpResponse::Ok().finish(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
 
-    let result = insert_subscriber(&new_subscriber, &pool);
-    match result.await {
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match join_list(&mut transaction, list_id, subscriber_id).await {
+        // Nothing to confirm, or an address we must not write to again, and
+        // nothing to tell whoever filled in the form either way
+        Ok(status) if status != "pending_confirmation" => {
+            return match transaction.commit().await {
+                Ok(_) => HttpResponse::Ok().finish(),
+                Err(_) => HttpResponse::InternalServerError().finish(),
+            };
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if record_activity(
+        &mut transaction,
+        subscriber_id,
+        ActivityKind::SignedUp,
+        None,
+        Some(list_id),
+        None,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let subscription_token = generate_subscription_token();
+    if store_token(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &subscription_token,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let outcome = send_confirmation_email(
+        &email_client,
+        &templates,
+        &pool,
+        new_subscriber,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await;
+    // Losing the entry is better than failing a signup whose email went out
+    let _ = record_activity(
+        pool.get_ref(),
+        subscriber_id,
+        ActivityKind::ConfirmationEmail,
+        None,
+        Some(list_id),
+        Some(&describe_send(&outcome)),
+    )
+    .await;
+    match outcome {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+/// Save a new subscriber, or find the existing one with the same email. Their
+/// name, time zone and attributes are left alone: anyone can fill in the form
+/// with someone else's address. New tags and attributes are added, though.
 #[tracing::instrument(
     name = "Saving new subscription in the database",
-    skip(new_subscriber, pool)
+    skip(new_subscriber, transaction)
 )]
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
-    pool: &PgPool,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Uuid, sqlx::Error> {
+    let tags: Vec<String> = new_subscriber
+        .tags
+        .iter()
+        .map(|tag| tag.as_ref().to_string())
+        .collect();
+    // The update also makes the existing row's id come back on a conflict
+    sqlx::query_scalar!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'confirmed')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, time_zone, tags, attributes)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (email) DO UPDATE
+        SET tags = ARRAY(
+                SELECT DISTINCT UNNEST(subscriptions.tags || EXCLUDED.tags) ORDER BY 1
+            ),
+            attributes = EXCLUDED.attributes || subscriptions.attributes
+        RETURNING id
         "#,
         Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
+        Utc::now(),
+        new_subscriber
+            .time_zone
+            .as_ref()
+            .map(|time_zone| time_zone.as_ref()),
+        &tags,
+        new_subscriber.attributes.clone().into_value()
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+/// Put a subscriber on a list pending confirmation, unless they already are
+/// on it. Returns their status on the list afterwards.
+#[tracing::instrument(name = "Adding a subscriber to a list", skip(transaction))]
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    // Those who left the list can come back, going through confirmation again
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', $3)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = EXCLUDED.status, subscribed_at = EXCLUDED.subscribed_at
+        WHERE list_subscriptions.status = 'unsubscribed'
+        "#,
+        list_id,
+        subscriber_id,
         Utc::now()
     )
-    .execute(pool)
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query_scalar!(
+        "SELECT status FROM list_subscriptions WHERE list_id = $1 AND subscriber_id = $2",
+        list_id,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+/// Store the token of a subscriber on a list, replacing the one sent for an
+/// earlier signup.
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(subscription_token, transaction)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE
+        SET subscription_token = EXCLUDED.subscription_token
+        "#,
+        subscription_token,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
@@ -72,3 +312,83 @@ pub async fn insert_subscriber(
     })?;
     Ok(())
 }
+
+/// Give a subscriber a token on a list unless they already have one, for
+/// those put on a list without going through the signup form.
+#[tracing::instrument(name = "Create subscription token", skip(transaction))]
+pub async fn create_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (subscriber_id, list_id) DO NOTHING
+        "#,
+        generate_subscription_token(),
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send a confirmation email to a new subscriber",
+    skip(
+        email_client,
+        templates,
+        pool,
+        new_subscriber,
+        base_url,
+        subscription_token
+    )
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    new_subscriber: NewSubscriber,
+    base_url: &str,
+    subscription_token: &str,
+) -> Result<Option<ProviderResponse>, anyhow::Error> {
+    let context = TemplateContext {
+        subscriber_name: new_subscriber.name.as_ref().to_string(),
+        confirmation_link: format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            base_url, subscription_token
+        ),
+        unsubscribe_url: unsubscribe_url(base_url, subscription_token),
+        ..Default::default()
+    };
+    let email = templates
+        .render(pool, TemplateName::Confirmation, &context)
+        .await?;
+    let response = send_unless_suppressed(email_client, pool, new_subscriber.email, &email)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to send confirmation email: {:?}", e);
+            e
+        })?;
+    Ok(response)
+}
+
+/// The subscription token doubles as the subscriber's unsubscribe token for
+/// the list it confirmed.
+pub fn unsubscribe_url(base_url: &str, subscription_token: &str) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        base_url, subscription_token
+    )
+}
+
+pub fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
@@ -0,0 +1,173 @@
+use crate::activity::record_activity;
+use crate::domain::{ActivityKind, SubscriberEmail};
+use crate::email_client::{EmailClient, ProviderResponse};
+use crate::email_templates::{EmailTemplates, RenderedEmail, TemplateContext, TemplateName};
+use crate::routes::{preferences_url, unsubscribe_url};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression::{lift_suppression, suppression_reason};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    pub subscription_token: String,
+}
+
+pub struct TokenSubscriber {
+    pub id: Uuid,
+    /// The list the token belongs to
+    pub list_id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// Status on that list
+    pub status: String,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, email_client, base_url, templates)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if subscriber.status != "pending_confirmation" {
+        return HttpResponse::Ok().finish();
+    }
+
+    if confirm_subscriber(&pool, &subscriber).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // The subscription is already confirmed, a lost welcome email is not worth failing over
+    let context = TemplateContext {
+        subscriber_name: subscriber.name,
+        unsubscribe_url: unsubscribe_url(&base_url.0, &parameters.subscription_token),
+        preferences_url: preferences_url(&base_url.0, &parameters.subscription_token),
+        ..Default::default()
+    };
+    if let Err(e) = send_templated_email(
+        &email_client,
+        &templates,
+        &pool,
+        subscriber.email,
+        TemplateName::Welcome,
+        &context,
+    )
+    .await
+    {
+        tracing::warn!("Failed to send welcome email: {:?}", e);
+    }
+    HttpResponse::Ok().finish()
+}
+
+/// Following the link proves the subscriber owns the address, so it lifts
+/// a suppression left by an earlier unsubscribe or erasure.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(pool, subscriber),
+    fields(subscriber_id = %subscriber.id, list_id = %subscriber.list_id)
+)]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber: &TokenSubscriber,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber.id,
+        subscriber.list_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    lift_suppression(&mut transaction, &subscriber.email).await?;
+    record_activity(
+        &mut transaction,
+        subscriber.id,
+        ActivityKind::Confirmed,
+        None,
+        Some(subscriber.list_id),
+        None,
+    )
+    .await?;
+    transaction.commit().await
+}
+
+#[tracing::instrument(name = "Get subscriber from token", skip(subscription_token, pool))]
+pub async fn get_subscriber_from_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<TokenSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenSubscriber,
+        r#"
+        SELECT s.id, t.list_id, s.email, s.name, ls.status
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        JOIN list_subscriptions ls ON ls.subscriber_id = t.subscriber_id AND ls.list_id = t.list_id
+        WHERE t.subscription_token = $1
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+/// Render one of the named templates and send it to `recipient`.
+pub async fn send_templated_email(
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    pool: &PgPool,
+    recipient: String,
+    template: TemplateName,
+    context: &TemplateContext,
+) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(recipient).map_err(anyhow::Error::msg)?;
+    let email = templates.render(pool, template, context).await?;
+    send_unless_suppressed(email_client, pool, recipient, &email).await?;
+    Ok(())
+}
+
+/// Send an email, unless the address bounced or complained. Every email but
+/// the newsletter goes through here, so no path mails such an address again.
+/// Returns `None` when nothing was sent.
+pub async fn send_unless_suppressed(
+    email_client: &EmailClient,
+    pool: &PgPool,
+    recipient: SubscriberEmail,
+    email: &RenderedEmail,
+) -> Result<Option<ProviderResponse>, anyhow::Error> {
+    if let Some(reason) = suppression_reason(pool, recipient.as_ref()).await? {
+        if reason.is_permanent() {
+            tracing::info!(
+                "Not sending to an address suppressed after a {}",
+                reason.as_str()
+            );
+            return Ok(None);
+        }
+    }
+    let response = email_client
+        .send_email(recipient, &email.subject, &email.html, &email.text)
+        .await?;
+    Ok(Some(response))
+}
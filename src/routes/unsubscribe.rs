@@ -0,0 +1,136 @@
+use crate::activity::record_activity;
+use crate::domain::{ActivityKind, EmailEventKind};
+use crate::email_client::EmailClient;
+use crate::email_templates::{EmailTemplates, TemplateContext, TemplateName};
+use crate::routes::{
+    get_subscriber_from_token, record_last_issue_event, send_templated_email, Parameters,
+};
+use crate::suppression::{suppress_subscriber, SuppressionReason, SuppressionSource};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use tera::escape_html;
+use uuid::Uuid;
+
+/// Where the unsubscribe link lands. Opening it only asks for confirmation,
+/// as mail scanners follow links on their own.
+#[tracing::instrument(name = "Unsubscribe confirmation page", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Unsubscribe</title></head>
+<body>
+    <p>You will no longer receive this newsletter.</p>
+    <form action="/subscriptions/unsubscribe?subscription_token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            escape_html(&parameters.subscription_token)
+        ))
+}
+
+/// The token travels in the query string, so both the confirmation page and
+/// RFC 8058 one-click unsubscribes from mail clients, which post
+/// `List-Unsubscribe=One-Click` to the link itself, land here.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, email_client, templates)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let subscriber = match get_subscriber_from_token(&pool, &parameters.subscription_token).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if subscriber.status == "unsubscribed" {
+        return HttpResponse::Ok().finish();
+    }
+
+    if mark_unsubscribed(&pool, subscriber.id, subscriber.list_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let context = TemplateContext {
+        subscriber_name: subscriber.name,
+        ..Default::default()
+    };
+    if let Err(e) = send_templated_email(
+        &email_client,
+        &templates,
+        &pool,
+        subscriber.email,
+        TemplateName::UnsubscribeReceipt,
+        &context,
+    )
+    .await
+    {
+        tracing::warn!("Failed to send unsubscribe receipt: {:?}", e);
+    }
+    HttpResponse::Ok().finish()
+}
+
+/// Leave the one list the token belongs to; other lists are unaffected. The
+/// address is only suppressed once it is on no list at all.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub async fn mark_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let issue_id =
+        record_last_issue_event(&mut transaction, subscriber_id, EmailEventKind::Unsubscribe)
+            .await?;
+    record_activity(
+        &mut transaction,
+        subscriber_id,
+        ActivityKind::Unsubscribe,
+        issue_id,
+        Some(list_id),
+        Some("Unsubscribe link"),
+    )
+    .await?;
+    suppress_subscriber(
+        &mut transaction,
+        subscriber_id,
+        SuppressionReason::Unsubscribe,
+        SuppressionSource::UnsubscribeLink,
+    )
+    .await?;
+    transaction.commit().await
+}
@@ -0,0 +1,317 @@
+use crate::activity::record_activity;
+use crate::authentication::EmailProvider;
+use crate::configuration::WebhookSettings;
+use crate::domain::{ActivityKind, EmailEventKind, SubscriptionStatus};
+use crate::routes::{last_issue_sent, record_last_issue_event};
+use crate::suppression::{suppress_subscriber, SuppressionReason, SuppressionSource};
+use actix_web::{web, HttpResponse};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// SparkPost bounce classes that mean the address will never accept mail.
+/// Every other class is a soft bounce.
+const HARD_BOUNCE_CLASSES: &[&str] = &["10", "30", "90"];
+
+/// One entry of a SparkPost webhook batch.
+#[derive(serde::Deserialize)]
+pub struct WebhookEvent {
+    msys: WebhookEventBody,
+}
+
+/// Only message events matter here; engagement is tracked by us, and the
+/// empty body SparkPost sends to test a webhook has none.
+#[derive(serde::Deserialize)]
+struct WebhookEventBody {
+    message_event: Option<MessageEvent>,
+}
+
+#[derive(serde::Deserialize)]
+struct MessageEvent {
+    /// Stays the same when the provider sends an event again
+    event_id: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    rcpt_to: String,
+    bounce_class: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProviderEvent {
+    HardBounce,
+    SoftBounce,
+    Complaint,
+    Delivery,
+}
+
+impl MessageEvent {
+    fn classify(&self) -> Option<ProviderEvent> {
+        match self.kind.as_str() {
+            "bounce" | "out_of_band" => match self.bounce_class.as_deref() {
+                Some(class) if HARD_BOUNCE_CLASSES.contains(&class) => {
+                    Some(ProviderEvent::HardBounce)
+                }
+                _ => Some(ProviderEvent::SoftBounce),
+            },
+            "spam_complaint" => Some(ProviderEvent::Complaint),
+            "delivery" => Some(ProviderEvent::Delivery),
+            _ => None,
+        }
+    }
+}
+
+/// Take in the delivery events the email provider posts about our emails.
+///
+/// Hard bounces and spam complaints take the address off every list for
+/// good. Soft bounces are counted, and treated as a hard bounce once they
+/// reach the limit without a delivery in between. Events already applied
+/// from an earlier delivery of the batch are skipped.
+#[tracing::instrument(
+    name = "Receiving email provider events",
+    skip(_provider, events, pool, settings),
+    fields(events = events.len())
+)]
+pub async fn receive_email_events(
+    _provider: EmailProvider,
+    events: web::Json<Vec<WebhookEvent>>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> HttpResponse {
+    for event in events.into_inner() {
+        let Some(message) = event.msys.message_event else {
+            continue;
+        };
+        let Some(provider_event) = message.classify() else {
+            continue;
+        };
+        // Failing the batch has the provider send it again
+        if let Err(e) =
+            apply_event(&pool, &message, provider_event, settings.soft_bounce_limit).await
+        {
+            tracing::error!("Failed to apply an email provider event: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[tracing::instrument(name = "Applying an email provider event", skip(pool, message))]
+async fn apply_event(
+    pool: &PgPool,
+    message: &MessageEvent,
+    event: ProviderEvent,
+    soft_bounce_limit: i32,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    if let Some(event_id) = &message.event_id {
+        let first_delivery = sqlx::query!(
+            r#"
+            INSERT INTO provider_events (event_id, received_at)
+            VALUES ($1, now())
+            ON CONFLICT DO NOTHING
+            "#,
+            event_id
+        )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected()
+            == 1;
+        if !first_delivery {
+            return Ok(());
+        }
+    }
+    // Every subscriber whose address differs only in case shares the mailbox
+    let subscriber_ids = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1) ORDER BY id FOR UPDATE",
+        message.rcpt_to
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    // Nobody when the address was since erased, or was never subscribed
+    for subscriber_id in subscriber_ids {
+        apply_to_subscriber(
+            &mut transaction,
+            subscriber_id,
+            message,
+            event,
+            soft_bounce_limit,
+        )
+        .await?;
+    }
+    transaction.commit().await
+}
+
+async fn apply_to_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    message: &MessageEvent,
+    event: ProviderEvent,
+    soft_bounce_limit: i32,
+) -> Result<(), sqlx::Error> {
+    let bounce_class = message.bounce_class.as_deref().unwrap_or("unknown");
+    let (activity, issue_id, detail) = match event {
+        ProviderEvent::HardBounce => {
+            block_subscriber(
+                &mut *transaction,
+                subscriber_id,
+                SubscriptionStatus::Bounced,
+            )
+            .await?;
+            let issue_id =
+                record_last_issue_event(&mut *transaction, subscriber_id, EmailEventKind::Bounce)
+                    .await?;
+            let detail = format!("Hard bounce, class {}", bounce_class);
+            (ActivityKind::Bounce, issue_id, detail)
+        }
+        ProviderEvent::SoftBounce => {
+            let soft_bounces = sqlx::query_scalar!(
+                r#"
+                UPDATE subscriptions SET soft_bounces = soft_bounces + 1
+                WHERE id = $1
+                RETURNING soft_bounces
+                "#,
+                subscriber_id
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+            if soft_bounces >= soft_bounce_limit {
+                tracing::info!(
+                    "Treating an address as bounced after {} soft bounces",
+                    soft_bounces
+                );
+                block_subscriber(
+                    &mut *transaction,
+                    subscriber_id,
+                    SubscriptionStatus::Bounced,
+                )
+                .await?;
+            }
+            let issue_id = record_last_issue_event(
+                &mut *transaction,
+                subscriber_id,
+                EmailEventKind::SoftBounce,
+            )
+            .await?;
+            let detail = format!(
+                "Soft bounce {} of {}, class {}",
+                soft_bounces, soft_bounce_limit, bounce_class
+            );
+            (ActivityKind::Bounce, issue_id, detail)
+        }
+        ProviderEvent::Complaint => {
+            block_subscriber(
+                &mut *transaction,
+                subscriber_id,
+                SubscriptionStatus::Complained,
+            )
+            .await?;
+            let issue_id = record_last_issue_event(
+                &mut *transaction,
+                subscriber_id,
+                EmailEventKind::Complaint,
+            )
+            .await?;
+            let detail = "Reported as spam".to_string();
+            (ActivityKind::Complaint, issue_id, detail)
+        }
+        ProviderEvent::Delivery => {
+            sqlx::query!(
+                "UPDATE subscriptions SET soft_bounces = 0 WHERE id = $1",
+                subscriber_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+            let issue_id = last_issue_sent(&mut *transaction, subscriber_id).await?;
+            let detail = "Handed over to the recipient's mail server".to_string();
+            (ActivityKind::Delivered, issue_id, detail)
+        }
+    };
+    record_activity(
+        &mut *transaction,
+        subscriber_id,
+        activity,
+        issue_id,
+        None,
+        Some(&detail),
+    )
+    .await
+}
+
+/// Take a subscriber off every list they are on or about to be on, and keep
+/// their address from being mailed should it be added again.
+async fn block_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    let reason = match status {
+        SubscriptionStatus::Complained => SuppressionReason::Complaint,
+        _ => SuppressionReason::Bounce,
+    };
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = $2
+        WHERE subscriber_id = $1 AND status IN ('confirmed', 'pending_confirmation')
+        "#,
+        subscriber_id,
+        status.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    suppress_subscriber(
+        transaction,
+        subscriber_id,
+        reason,
+        SuppressionSource::Webhook,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageEvent, ProviderEvent};
+    use claims::{assert_none, assert_some_eq};
+
+    fn event(kind: &str, bounce_class: Option<&str>) -> MessageEvent {
+        MessageEvent {
+            event_id: None,
+            kind: kind.into(),
+            rcpt_to: "ursula_le_guin@gmail.com".into(),
+            bounce_class: bounce_class.map(String::from),
+        }
+    }
+
+    #[test]
+    fn invalid_recipients_are_hard_bounces() {
+        assert_some_eq!(
+            event("bounce", Some("10")).classify(),
+            ProviderEvent::HardBounce
+        );
+        assert_some_eq!(
+            event("out_of_band", Some("30")).classify(),
+            ProviderEvent::HardBounce
+        );
+    }
+
+    #[test]
+    fn other_bounces_are_soft() {
+        assert_some_eq!(
+            event("bounce", Some("22")).classify(),
+            ProviderEvent::SoftBounce
+        );
+        assert_some_eq!(event("bounce", None).classify(), ProviderEvent::SoftBounce);
+    }
+
+    #[test]
+    fn spam_complaints_are_recognised() {
+        assert_some_eq!(
+            event("spam_complaint", None).classify(),
+            ProviderEvent::Complaint
+        );
+    }
+
+    #[test]
+    fn engagement_events_are_ignored() {
+        assert_none!(event("open", None).classify());
+        assert_none!(event("click", None).classify());
+    }
+}
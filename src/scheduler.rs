@@ -0,0 +1,108 @@
+use crate::configuration::Settings;
+use crate::delivery_worker::{
+    enqueue_delivery_tasks, enqueue_local_delivery_tasks, try_execute_task, ExecutionOutcome,
+};
+use crate::email_client::EmailClient;
+use crate::email_templates::EmailTemplates;
+use crate::routes::{snapshot_locked_issue, IssueError};
+use crate::startup::get_connection_pool;
+use crate::tracking::Tracker;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Background task that publishes scheduled issues once they are due and
+/// sends the emails they queue up.
+#[derive(Clone)]
+pub struct Scheduler {
+    pool: PgPool,
+    email_client: EmailClient,
+    templates: EmailTemplates,
+    tracker: Tracker,
+    base_url: String,
+    poll_interval: Duration,
+}
+
+impl Scheduler {
+    /// `templates` are shared with the HTTP server when both run in the same
+    /// process, so template edits reach the scheduler at once.
+    pub fn build(configuration: &Settings, templates: EmailTemplates) -> Self {
+        Self {
+            pool: get_connection_pool(&configuration.database),
+            email_client: configuration
+                .email_client
+                .clone()
+                .client(&configuration.application.base_url),
+            templates,
+            tracker: configuration.tracking.clone().tracker(),
+            base_url: configuration.application.base_url.clone(),
+            poll_interval: configuration.scheduler.poll_interval(),
+        }
+    }
+
+    pub async fn run_until_stopped(self) {
+        loop {
+            self.tick().await;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Promote the issues that are due, then drain the delivery queue.
+    pub async fn tick(&self) {
+        if let Err(e) = promote_due_issues(&self.pool).await {
+            tracing::error!("Failed to promote scheduled issues: {:?}", e);
+        }
+        loop {
+            match try_execute_task(
+                &self.pool,
+                &self.email_client,
+                &self.templates,
+                &self.tracker,
+                &self.base_url,
+            )
+            .await
+            {
+                Ok(ExecutionOutcome::TaskCompleted) => continue,
+                Ok(ExecutionOutcome::EmptyQueue) => break,
+                // Already logged by the task; try again on the next tick
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+/// Publish every scheduled issue whose time has come and queue it for its
+/// recipients, in one transaction. Issues sent in each subscriber's time zone
+/// are queued in waves, one per zone, each due at the local send time.
+///
+/// Due issues are claimed with `FOR UPDATE SKIP LOCKED` and marked as published
+/// before the transaction commits, so when several replicas tick at once each
+/// issue is promoted by exactly one of them.
+#[tracing::instrument(name = "Promoting due newsletter issues", skip(pool))]
+pub async fn promote_due_issues(pool: &PgPool) -> Result<usize, IssueError> {
+    let mut transaction = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+        SELECT id, local_send_time, schedule_time_zone
+        FROM newsletter_issues
+        WHERE scheduled_at <= now() AND published_at IS NULL
+        ORDER BY scheduled_at
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for issue in &due {
+        snapshot_locked_issue(&mut transaction, issue.id).await?;
+        let queued = match (issue.local_send_time, issue.schedule_time_zone.as_deref()) {
+            (Some(local_send_time), Some(time_zone)) => {
+                let fallback = time_zone.parse().unwrap_or(chrono_tz::UTC);
+                enqueue_local_delivery_tasks(&mut transaction, issue.id, local_send_time, fallback)
+                    .await?
+            }
+            _ => enqueue_delivery_tasks(&mut transaction, issue.id).await?,
+        };
+        tracing::info!("Queued issue {} for {} subscribers", issue.id, queued);
+    }
+    transaction.commit().await?;
+    Ok(due.len())
+}
@@ -1,6 +1,13 @@
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::configuration::{
+    AdminSettings, DatabaseSettings, Settings, SubscriberFieldSettings, WebhookSettings,
+};
 use crate::email_client::EmailClient;
+use crate::email_templates::EmailTemplates;
+use crate::feed_ingestion::FeedIngester;
+use crate::migration::run_migrations;
 use crate::routes::*;
+use crate::scheduler::Scheduler;
+use crate::tracking::Tracker;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
 use sqlx::postgres::PgPoolOptions;
@@ -11,25 +18,40 @@ use tracing_actix_web::TracingLogger;
 pub struct Application {
     server: Server,
     port: u16,
+    scheduler: Option<Scheduler>,
+    feed_ingester: Option<FeedIngester>,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
         // Create database pool
         let connection_pool = get_connection_pool(&configuration.database);
+        if configuration.application.run_migrations_on_startup {
+            run_migrations(&connection_pool)
+                .await
+                .map_err(std::io::Error::other)?;
+        }
+
+        // Load email templates
+        let email_templates =
+            EmailTemplates::from_directory(configuration.application.templates_directory.as_ref())
+                .map_err(std::io::Error::other)?;
+
+        // Publish scheduled issues and deliver queued emails in the background
+        let scheduler = match configuration.scheduler.enabled {
+            true => Some(Scheduler::build(&configuration, email_templates.clone())),
+            false => None,
+        };
+        // Turn new items of external feeds into issues
+        let feed_ingester = match configuration.feed_ingestion.enabled {
+            true => Some(FeedIngester::build(&configuration)?),
+            false => None,
+        };
 
         // Set email client
-        let sender_email = configuration
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("Failed to parse sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
+            .client(&configuration.application.base_url);
 
         // Connect to address:port
         let address = format!(
@@ -46,9 +68,24 @@ impl Application {
             port
         );
 
-        let server = run(listener, connection_pool, email_client)?;
+        let server = run(
+            listener,
+            connection_pool,
+            email_client,
+            configuration.admin,
+            configuration.application.base_url,
+            email_templates,
+            configuration.subscriber_fields,
+            configuration.tracking.tracker(),
+            configuration.webhooks,
+        )?;
 
-        Ok(Self { server, port })
+        Ok(Self {
+            server,
+            port,
+            scheduler,
+            feed_ingester,
+        })
     }
 
     pub fn port(&self) -> u16 {
@@ -56,7 +93,17 @@ impl Application {
     }
 
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let scheduler = self
+            .scheduler
+            .map(|scheduler| tokio::spawn(scheduler.run_until_stopped()));
+        let feed_ingester = self
+            .feed_ingester
+            .map(|feed_ingester| tokio::spawn(feed_ingester.run_until_stopped()));
+        let result = self.server.await;
+        for task in scheduler.into_iter().chain(feed_ingester) {
+            task.abort();
+        }
+        result
     }
 }
 
@@ -67,19 +114,125 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
+/// Public URL of the application, used to build the links we send by email.
+pub struct ApplicationBaseUrl(pub String);
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    admin_settings: AdminSettings,
+    base_url: String,
+    email_templates: EmailTemplates,
+    subscriber_fields: SubscriberFieldSettings,
+    tracker: Tracker,
+    webhook_settings: WebhookSettings,
 ) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
+    let email_client = web::Data::new(email_client);
+    let admin_settings = web::Data::new(admin_settings);
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let email_templates = web::Data::new(email_templates);
+    let subscriber_fields = web::Data::new(subscriber_fields);
+    let tracker = web::Data::new(tracker);
+    let webhook_settings = web::Data::new(webhook_settings);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .route("/archive", web::get().to(archive_index))
+            .route("/archive/{slug}", web::get().to(archive_issue))
+            .route("/feed.xml", web::get().to(atom_feed))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route(
+                "/webhooks/email-events",
+                web::post().to(receive_email_events),
+            )
+            .route("/rss.xml", web::get().to(rss_feed))
             .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(update_preferences))
+            .route("/preferences/email", web::post().to(request_email_change))
+            .route(
+                "/preferences/email/confirm",
+                web::get().to(confirm_email_change_form),
+            )
+            .route(
+                "/preferences/email/confirm",
+                web::post().to(confirm_email_change),
+            )
+            .route("/admin/lists", web::get().to(list_lists))
+            .route("/admin/lists", web::post().to(create_list))
+            .route("/admin/segments", web::get().to(list_segments))
+            .route("/admin/segments", web::post().to(create_segment))
+            .route("/admin/segments/preview", web::post().to(preview_segment))
+            .route(
+                "/admin/subscribers/import",
+                web::post().to(import_subscribers_csv),
+            )
+            .route(
+                "/admin/subscribers/export",
+                web::get().to(export_subscribers),
+            )
+            .route("/admin/subscribers/{id}", web::get().to(get_subscriber))
+            .route(
+                "/admin/subscribers/{id}",
+                web::patch().to(update_subscriber),
+            )
+            .route("/admin/newsletters", web::post().to(publish_newsletter))
+            .route(
+                "/admin/newsletters/preview",
+                web::post().to(preview_newsletter),
+            )
+            .route("/admin/issues", web::post().to(create_issue_draft))
+            .route("/admin/issues", web::get().to(list_issues))
+            .route("/admin/issues/{id}", web::get().to(get_issue))
+            .route("/admin/issues/{id}", web::put().to(update_issue))
+            .route("/admin/issues/{id}", web::delete().to(delete_issue))
+            .route(
+                "/admin/issues/{id}/revisions",
+                web::get().to(list_issue_revisions),
+            )
+            .route(
+                "/admin/issues/{id}/revisions/{revision}",
+                web::get().to(get_issue_revision),
+            )
+            .route(
+                "/admin/issues/{id}/diff",
+                web::get().to(diff_issue_revisions),
+            )
+            .route("/admin/issues/{id}/publish", web::post().to(publish_issue))
+            .route("/admin/issues/{id}/stats", web::get().to(get_issue_stats))
+            .route("/admin/issues/{id}/schedule", web::put().to(schedule_issue))
+            .route(
+                "/admin/issues/{id}/schedule",
+                web::delete().to(cancel_issue_schedule),
+            )
+            .route("/admin/templates/{name}", web::put().to(put_email_template))
+            .route(
+                "/admin/templates/{name}",
+                web::delete().to(delete_email_template),
+            )
+            .route("/privacy/requests", web::post().to(request_privacy_links))
+            .route("/privacy/data", web::get().to(export_subscriber_data))
+            .route("/privacy/erase", web::get().to(erasure_form))
+            .route("/privacy/erase", web::post().to(erase_subscriber_data))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(admin_settings.clone())
+            .app_data(base_url.clone())
+            .app_data(email_templates.clone())
+            .app_data(subscriber_fields.clone())
+            .app_data(tracker.clone())
+            .app_data(webhook_settings.clone())
     })
     .listen(listener)?
     .run();
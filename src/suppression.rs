@@ -0,0 +1,216 @@
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Why an address is on the suppression list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    /// The subscriber left every list they were on
+    Unsubscribe,
+    Bounce,
+    Complaint,
+    /// The subscriber had their data erased
+    Erasure,
+}
+
+impl SuppressionReason {
+    pub fn parse(reason: String) -> Result<Self, String> {
+        match reason.as_str() {
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            "bounce" => Ok(Self::Bounce),
+            "complaint" => Ok(Self::Complaint),
+            "erasure" => Ok(Self::Erasure),
+            other => Err(format!("Invalid suppression reason: {}", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unsubscribe => "unsubscribe",
+            Self::Bounce => "bounce",
+            Self::Complaint => "complaint",
+            Self::Erasure => "erasure",
+        }
+    }
+
+    /// Reasons that even the owner of the address confirming a new
+    /// subscription doesn't lift.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::Bounce | Self::Complaint)
+    }
+}
+
+impl AsRef<str> for SuppressionReason {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Where a suppression came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionSource {
+    UnsubscribeLink,
+    PreferenceCenter,
+    /// An event posted by the email provider
+    Webhook,
+    PrivacyRequest,
+}
+
+impl SuppressionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnsubscribeLink => "unsubscribe_link",
+            Self::PreferenceCenter => "preference_center",
+            Self::Webhook => "webhook",
+            Self::PrivacyRequest => "privacy_request",
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 of the normalised address, so a suppressed address can
+/// be recognised without keeping it around.
+pub fn email_hash(email: &str) -> String {
+    Sha256::digest(email.trim().to_lowercase().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Put an address on the suppression list. A bounce or complaint already on
+/// record is kept, so it can't be lifted by a later confirmation.
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: SuppressionReason,
+    source: SuppressionSource,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, reason, source, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (email_hash) DO UPDATE
+        SET reason = EXCLUDED.reason, source = EXCLUDED.source, created_at = EXCLUDED.created_at
+        WHERE suppressions.reason NOT IN ('bounce', 'complaint')
+        "#,
+        email_hash(email),
+        reason.as_str(),
+        source.as_str(),
+        Utc::now()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Suppress a subscriber's address. An unsubscribe only counts once they are
+/// on no list at all: leaving one list is not asking for no mail whatsoever.
+pub async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    reason: SuppressionReason,
+    source: SuppressionSource,
+) -> Result<(), sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email, EXISTS(
+            SELECT 1 FROM list_subscriptions
+            WHERE subscriber_id = $1 AND status IN ('confirmed', 'pending_confirmation')
+        ) AS "is_subscribed!"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    match subscriber {
+        Some(subscriber)
+            if reason != SuppressionReason::Unsubscribe || !subscriber.is_subscribed =>
+        {
+            suppress(&mut *transaction, &subscriber.email, reason, source).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Why an address must not be mailed, if it must not.
+pub async fn suppression_reason(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<SuppressionReason>, sqlx::Error> {
+    let reason = sqlx::query_scalar!(
+        "SELECT reason FROM suppressions WHERE email_hash = $1",
+        email_hash(email)
+    )
+    .fetch_optional(executor)
+    .await?;
+    reason
+        .map(|reason| SuppressionReason::parse(reason).map_err(|e| sqlx::Error::Decode(e.into())))
+        .transpose()
+}
+
+/// The owner of the address opted in again, which overrides an unsubscribe
+/// or erasure but not a bounce or complaint.
+pub async fn lift_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM suppressions
+        WHERE email_hash = $1 AND reason NOT IN ('bounce', 'complaint')
+        "#,
+        email_hash(email)
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{email_hash, SuppressionReason};
+    use claims::assert_err;
+
+    #[test]
+    fn hash_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash(" Ursula@Gmail.com "),
+            email_hash("ursula@gmail.com")
+        );
+    }
+
+    #[test]
+    fn hash_is_hex_encoded_sha256() {
+        assert_eq!(
+            email_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn reasons_round_trip_through_their_string_form() {
+        for reason in [
+            SuppressionReason::Unsubscribe,
+            SuppressionReason::Bounce,
+            SuppressionReason::Complaint,
+            SuppressionReason::Erasure,
+        ] {
+            assert_eq!(SuppressionReason::parse(reason.as_str().into()), Ok(reason));
+        }
+    }
+
+    #[test]
+    fn unknown_reasons_are_rejected() {
+        assert_err!(SuppressionReason::parse("spam".into()));
+    }
+
+    #[test]
+    fn only_bounces_and_complaints_are_permanent() {
+        assert!(SuppressionReason::Bounce.is_permanent());
+        assert!(SuppressionReason::Complaint.is_permanent());
+        assert!(!SuppressionReason::Unsubscribe.is_permanent());
+        assert!(!SuppressionReason::Erasure.is_permanent());
+    }
+}
@@ -0,0 +1,437 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Length of the HMAC-SHA256 signature ending every token
+const SIGNATURE_LENGTH: usize = 32;
+
+/// Signs the tracking links put in newsletter emails, so that events can only
+/// be recorded for emails we actually sent. Without a key nothing is tracked
+/// and every token is rejected.
+#[derive(Clone)]
+pub struct Tracker {
+    signing_key: Option<Secret<String>>,
+    open_tracking: bool,
+    click_tracking: bool,
+}
+
+impl Tracker {
+    pub fn new(
+        signing_key: Option<Secret<String>>,
+        open_tracking: bool,
+        click_tracking: bool,
+    ) -> Self {
+        Self {
+            signing_key,
+            open_tracking,
+            click_tracking,
+        }
+    }
+
+    pub fn tracks_opens(&self) -> bool {
+        self.open_tracking
+    }
+
+    pub fn tracks_clicks(&self) -> bool {
+        self.click_tracking
+    }
+
+    /// Rewrite the links of an email about one issue and add its open pixel,
+    /// for whichever kinds of tracking are on.
+    pub fn track_email(
+        &self,
+        html: &str,
+        base_url: &str,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> String {
+        let html = self.track_links(html, base_url, issue_id, subscriber_id);
+        match self.open_pixel_url(base_url, issue_id, subscriber_id) {
+            Some(pixel_url) => inject_open_pixel(&html, &pixel_url),
+            None => html,
+        }
+    }
+
+    /// Point every web link of an email at the click redirect, except the
+    /// ones to the subscriber's own unsubscribe and preferences pages.
+    pub fn track_links(
+        &self,
+        html: &str,
+        base_url: &str,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> String {
+        if !self.click_tracking {
+            return html.to_string();
+        }
+        let untracked = [
+            format!("{}/subscriptions/unsubscribe", base_url),
+            format!("{}/preferences", base_url),
+        ];
+        let base = Url::parse(base_url).ok();
+        let settings = RewriteStrSettings::new().append_element_content_handler(element!(
+            "a[href], area[href]",
+            |el| {
+                let Some(href) = el.get_attribute("href") else {
+                    return Ok(());
+                };
+                let href = unescape_attribute(href.trim());
+                // Relative links are made absolute when the email is sent, so
+                // they are resolved the same way here to be tracked too
+                let href = match (&base, Url::parse(&href)) {
+                    (_, Ok(_)) => href,
+                    (Some(base), Err(_)) if !href.is_empty() && !href.starts_with('#') => {
+                        match base.join(&href) {
+                            Ok(absolute) => absolute.to_string(),
+                            Err(_) => return Ok(()),
+                        }
+                    }
+                    _ => return Ok(()),
+                };
+                if untracked
+                    .iter()
+                    .any(|prefix| href.starts_with(prefix.as_str()))
+                {
+                    return Ok(());
+                }
+                if let Some(click_url) = self.click_url(base_url, issue_id, subscriber_id, &href) {
+                    el.set_attribute("href", &click_url)?;
+                }
+                Ok(())
+            }
+        ));
+        rewrite_str(html, settings).unwrap_or_else(|e| {
+            tracing::warn!("Failed to rewrite links for click tracking: {}", e);
+            html.to_string()
+        })
+    }
+
+    /// The redirect recording a click on `url`, for web links when click
+    /// tracking is on.
+    pub fn click_url(
+        &self,
+        base_url: &str,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+        url: &str,
+    ) -> Option<String> {
+        if !self.click_tracking || !is_web_url(url) {
+            return None;
+        }
+        Some(format!(
+            "{}/t/c/{}",
+            base_url,
+            self.sign_click(issue_id, subscriber_id, url)?
+        ))
+    }
+
+    /// The pixel recording when a subscriber opens an issue, if open tracking
+    /// is on.
+    pub fn open_pixel_url(
+        &self,
+        base_url: &str,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> Option<String> {
+        if !self.open_tracking {
+            return None;
+        }
+        Some(format!(
+            "{}/t/o/{}.gif",
+            base_url,
+            self.sign(issue_id, subscriber_id)?
+        ))
+    }
+
+    /// An opaque token naming the issue and the subscriber, if there is a key
+    /// to sign it with.
+    pub fn sign(&self, issue_id: Uuid, subscriber_id: Uuid) -> Option<String> {
+        self.seal(Purpose::Open, ids(issue_id, subscriber_id))
+    }
+
+    /// The issue and subscriber ids of a token made by `sign`.
+    pub fn verify(&self, token: &str) -> Option<(Uuid, Uuid)> {
+        let payload = self.unseal(Purpose::Open, token)?;
+        if payload.len() != 32 {
+            return None;
+        }
+        parse_ids(&payload)
+    }
+
+    /// A token carrying the link itself, so that following it needs no lookup.
+    pub fn sign_click(&self, issue_id: Uuid, subscriber_id: Uuid, url: &str) -> Option<String> {
+        let mut payload = ids(issue_id, subscriber_id);
+        payload.extend_from_slice(url.as_bytes());
+        self.seal(Purpose::Click, payload)
+    }
+
+    /// The issue and subscriber ids and the link of a token made by
+    /// `sign_click`.
+    pub fn verify_click(&self, token: &str) -> Option<(Uuid, Uuid, String)> {
+        let payload = self.unseal(Purpose::Click, token)?;
+        if payload.len() <= 32 {
+            return None;
+        }
+        let (issue_id, subscriber_id) = parse_ids(&payload[..32])?;
+        let url = String::from_utf8(payload[32..].to_vec()).ok()?;
+        is_web_url(&url).then_some((issue_id, subscriber_id, url))
+    }
+
+    fn seal(&self, purpose: Purpose, mut payload: Vec<u8>) -> Option<String> {
+        let signature = self.mac(purpose, &payload)?.finalize().into_bytes();
+        payload.extend_from_slice(&signature);
+        Some(URL_SAFE_NO_PAD.encode(payload))
+    }
+
+    fn unseal(&self, purpose: Purpose, token: &str) -> Option<Vec<u8>> {
+        let mut payload = URL_SAFE_NO_PAD.decode(token).ok()?;
+        let end = payload.len().checked_sub(SIGNATURE_LENGTH)?;
+        let signature = payload.split_off(end);
+        self.mac(purpose, &payload)?.verify_slice(&signature).ok()?;
+        Some(payload)
+    }
+
+    /// Each kind of token is signed for its own purpose, so an open token
+    /// can't be passed off as a click one.
+    fn mac(&self, purpose: Purpose, message: &[u8]) -> Option<Hmac<Sha256>> {
+        let signing_key = self.signing_key.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(signing_key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(&[purpose as u8]);
+        mac.update(message);
+        Some(mac)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Purpose {
+    Open = 1,
+    Click = 2,
+}
+
+fn ids(issue_id: Uuid, subscriber_id: Uuid) -> Vec<u8> {
+    let mut ids = Vec::with_capacity(64);
+    ids.extend_from_slice(issue_id.as_bytes());
+    ids.extend_from_slice(subscriber_id.as_bytes());
+    ids
+}
+
+fn parse_ids(ids: &[u8]) -> Option<(Uuid, Uuid)> {
+    let issue_id = Uuid::from_slice(&ids[..16]).ok()?;
+    let subscriber_id = Uuid::from_slice(&ids[16..32]).ok()?;
+    Some((issue_id, subscriber_id))
+}
+
+/// Only absolute `http` and `https` links are tracked, so the redirect can't
+/// be pointed at anything else.
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Undo the character references that HTML escaping leaves in attribute
+/// values, e.g. `&amp;` between query parameters.
+fn unescape_attribute(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_reference(&rest[1..end])?, end + 1)));
+        match reference {
+            Some((character, length)) => {
+                unescaped.push(character);
+                rest = &rest[length..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn decode_reference(reference: &str) -> Option<char> {
+    match reference {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = reference.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Add an invisible image at the end of an HTML body.
+pub fn inject_open_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:block;border:0;">"#,
+        pixel_url
+    );
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], pixel, &html[end..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+/// A transparent 1x1 GIF.
+pub const TRANSPARENT_GIF: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{inject_open_pixel, Tracker};
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    fn tracker(key: &str) -> Tracker {
+        Tracker::new(Some(key.to_string().into()), true, true)
+    }
+
+    #[test]
+    fn signed_tokens_round_trip() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = tracker("key").sign(issue_id, subscriber_id).unwrap();
+        assert_some_eq!(tracker("key").verify(&token), (issue_id, subscriber_id));
+    }
+
+    #[test]
+    fn tokens_signed_with_another_key_or_tampered_with_are_rejected() {
+        let token = tracker("key").sign(Uuid::new_v4(), Uuid::new_v4()).unwrap();
+        assert_none!(tracker("other-key").verify(&token));
+        let mut tampered = token.clone().into_bytes();
+        tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+        assert_none!(tracker("key").verify(&String::from_utf8(tampered).unwrap()));
+        assert_none!(tracker("key").verify("not-a-token"));
+        assert_none!(tracker("key").verify(""));
+    }
+
+    #[test]
+    fn there_is_no_pixel_when_open_tracking_is_off() {
+        let tracker = Tracker::new(Some("key".to_string().into()), false, true);
+        assert_none!(tracker.open_pixel_url("http://localhost", Uuid::new_v4(), Uuid::new_v4()));
+    }
+
+    #[test]
+    fn the_pixel_goes_at_the_end_of_the_body() {
+        assert_eq!(
+            inject_open_pixel("<html><BODY><p>Hi</p></BODY></html>", "/t.gif"),
+            "<html><BODY><p>Hi</p><img src=\"/t.gif\" width=\"1\" height=\"1\" alt=\"\" \
+            style=\"display:block;border:0;\"></BODY></html>"
+        );
+        assert!(inject_open_pixel("<p>Hi</p>", "/t.gif").starts_with("<p>Hi</p><img"));
+    }
+
+    #[test]
+    fn click_tokens_carry_the_link() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = tracker("key")
+            .sign_click(issue_id, subscriber_id, "https://example.com/?a=1")
+            .unwrap();
+        assert_some_eq!(
+            tracker("key").verify_click(&token),
+            (
+                issue_id,
+                subscriber_id,
+                "https://example.com/?a=1".to_string()
+            )
+        );
+        assert_none!(tracker("other-key").verify_click(&token));
+    }
+
+    #[test]
+    fn open_and_click_tokens_are_not_interchangeable() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let open = tracker("key").sign(issue_id, subscriber_id).unwrap();
+        let click = tracker("key")
+            .sign_click(issue_id, subscriber_id, "https://example.com")
+            .unwrap();
+        assert_none!(tracker("key").verify_click(&open));
+        assert_none!(tracker("key").verify(&click));
+    }
+
+    #[test]
+    fn only_web_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/post">Post</a>
+<a href="mailto:editor@example.com">Mail</a>
+<a href="http://localhost/preferences?subscription_token=abc">Preferences</a>
+<a href="http://localhost/subscriptions/unsubscribe?subscription_token=abc">Unsubscribe</a>"#;
+        let tracked =
+            tracker("key").track_links(html, "http://localhost", Uuid::new_v4(), Uuid::new_v4());
+        assert!(!tracked.contains("https://example.com/post"));
+        assert_eq!(tracked.matches("http://localhost/t/c/").count(), 1);
+        assert!(tracked.contains("mailto:editor@example.com"));
+        assert!(tracked.contains("http://localhost/preferences?subscription_token=abc"));
+        assert!(tracked.contains("http://localhost/subscriptions/unsubscribe?"));
+    }
+
+    #[test]
+    fn relative_links_are_tracked_against_the_base_url() {
+        let html = r##"<a href="/archive/older">Older</a>
+<a href="#top">Top</a>
+<a href="/preferences?subscription_token=abc">Preferences</a>"##;
+        let tracked =
+            tracker("key").track_links(html, "http://localhost", Uuid::new_v4(), Uuid::new_v4());
+        let start = tracked.find("/t/c/").unwrap() + "/t/c/".len();
+        let end = start + tracked[start..].find('"').unwrap();
+        let (_, _, url) = tracker("key").verify_click(&tracked[start..end]).unwrap();
+        assert_eq!(url, "http://localhost/archive/older");
+        assert!(tracked.contains(r##"href="#top""##));
+        assert!(tracked.contains(r#"href="/preferences?subscription_token=abc""#));
+    }
+
+    #[test]
+    fn escaped_links_are_tracked_as_they_will_be_followed() {
+        let html = r#"<a href="https:&#x2F;&#x2F;example.com&#x2F;?a=1&amp;b=2&c">Post</a>"#;
+        let tracked =
+            tracker("key").track_links(html, "http://localhost", Uuid::new_v4(), Uuid::new_v4());
+        let start = tracked.find("/t/c/").unwrap() + "/t/c/".len();
+        let end = start + tracked[start..].find('"').unwrap();
+        let (_, _, url) = tracker("key").verify_click(&tracked[start..end]).unwrap();
+        assert_eq!(url, "https://example.com/?a=1&b=2&c");
+    }
+
+    #[test]
+    fn links_are_left_alone_when_click_tracking_is_off() {
+        let tracker = Tracker::new(Some("key".to_string().into()), true, false);
+        let html = r#"<a href="https://example.com/post">Post</a>"#;
+        assert_eq!(
+            tracker.track_links(html, "http://localhost", Uuid::new_v4(), Uuid::new_v4()),
+            html
+        );
+    }
+
+    #[test]
+    fn nothing_is_tracked_or_verified_without_a_key() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = tracker("key").sign(issue_id, subscriber_id).unwrap();
+        let tracker = Tracker::new(None, true, true);
+        assert_none!(tracker.verify(&token));
+        assert_none!(tracker.open_pixel_url("http://localhost", issue_id, subscriber_id));
+        assert_none!(tracker.click_url(
+            "http://localhost",
+            issue_id,
+            subscriber_id,
+            "https://example.com"
+        ));
+    }
+}
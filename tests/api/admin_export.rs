@@ -0,0 +1,106 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn import_subscribers(test_app: &TestApp) {
+    let csv = "email,name,subscribed_at\n\
+               ursula@gmail.com,Ursula,2021-01-01 00:00:00\n\
+               octavia@gmail.com,Octavia,2022-01-01 00:00:00\n";
+    let response = test_app.post_subscribers_import("", csv.into()).await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn export_is_rejected_without_admin_credentials() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/subscribers/export", test_app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn export_defaults_to_csv_in_subscription_order() {
+    // Arrange
+    let test_app = spawn_app().await;
+    import_subscribers(&test_app).await;
+
+    // Act
+    let response = test_app.get_subscribers_export("").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("text/csv", response.headers()["Content-Type"]);
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "id,email,name,status,subscribed_at");
+    assert!(lines[1].contains(",ursula@gmail.com,Ursula,confirmed,2021-01-01T00:00:00Z"));
+    assert!(lines[2].contains(",octavia@gmail.com,Octavia,confirmed,"));
+}
+
+#[actix_web::test]
+async fn csv_cells_that_look_like_formulas_are_escaped() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let response = test_app
+        .post_subscribers_import("", "email,name\nursula@gmail.com,=HYPERLINK\n".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Act
+    let response = test_app.get_subscribers_export("").await;
+
+    // Assert
+    let body = response.text().await.unwrap();
+    assert!(body.contains(",ursula@gmail.com,'=HYPERLINK,confirmed,"));
+}
+
+#[actix_web::test]
+async fn export_as_jsonl_filters_by_status() {
+    // Arrange
+    let test_app = spawn_app().await;
+    import_subscribers(&test_app).await;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'pending_confirmation'
+        FROM subscriptions
+        WHERE subscriptions.id = list_subscriptions.subscriber_id
+            AND subscriptions.email = 'octavia@gmail.com'
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = test_app
+        .get_subscribers_export("format=jsonl&status=pending_confirmation")
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    let records: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["email"], "octavia@gmail.com");
+    assert_eq!(records[0]["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn export_with_an_unknown_status_is_a_bad_request() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_subscribers_export("status=gone").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
@@ -0,0 +1,188 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::path;
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn import_is_rejected_without_admin_credentials() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", test_app.address))
+        .body("email,name\nursula@gmail.com,Ursula")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="admin""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[actix_web::test]
+async fn import_reports_accepted_duplicate_and_rejected_rows() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=existing%40gmail.com".into())
+        .await;
+    let csv = "email,name,subscribed_at\n\
+               ursula@gmail.com,Ursula,2021-03-04 05:06:07\n\
+               existing@gmail.com,Le Guin,\n\
+               ursula@gmail.com,Ursula again,\n\
+               not-an-email,Someone,\n\
+               octavia@gmail.com,Octavia,last tuesday\n";
+
+    // Act
+    let response = test_app.post_subscribers_import("", csv.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["duplicate"], 2);
+    assert_eq!(report["rejected"], 2);
+    let outcomes: Vec<_> = report["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["row"].as_u64().unwrap(),
+                row["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (2, "accepted"),
+            (3, "duplicate"),
+            (4, "duplicate"),
+            (5, "rejected"),
+            (6, "rejected"),
+        ]
+    );
+
+    let imported = sqlx::query!(
+        "SELECT subscribed_at::TEXT AS subscribed_at FROM subscriptions WHERE email = 'ursula@gmail.com'"
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch imported subscription.");
+    assert_eq!(
+        imported.subscribed_at.as_deref(),
+        Some("2021-03-04 05:06:07+00")
+    );
+}
+
+#[actix_web::test]
+async fn addresses_differing_only_in_case_are_imported_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let csv = "email,name\n\
+               ursula@gmail.com,Ursula\n\
+               Ursula@Gmail.com,Ursula again\n";
+
+    // Act
+    let response = test_app.post_subscribers_import("", csv.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["duplicate"], 1);
+    let imported = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch imported subscriptions.");
+    assert_eq!(imported.len(), 1);
+}
+
+#[actix_web::test]
+async fn existing_names_are_kept_unless_overwrite_is_asked_for() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=existing%40gmail.com".into())
+        .await;
+    let csv = "email,name\nExisting@gmail.com,Ursula\n";
+    let stored_name = || async {
+        sqlx::query!("SELECT email, name FROM subscriptions")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .expect("Failed to fetch the subscription.")
+    };
+
+    // Act - Part 1 - Import without overwriting
+    let response = test_app.post_subscribers_import("", csv.into()).await;
+
+    // Assert - Part 1
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["duplicate"], 1);
+    assert_eq!(report["names_updated"], 0);
+    let subscription = stored_name().await;
+    assert_eq!(subscription.email, "existing@gmail.com");
+    assert_eq!(subscription.name, "le guin");
+
+    // Act - Part 2 - Import again, overwriting names
+    let response = test_app
+        .post_subscribers_import("overwrite_names=true", csv.into())
+        .await;
+
+    // Assert - Part 2
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["duplicate"], 1);
+    assert_eq!(report["names_updated"], 1);
+    assert_eq!(
+        report["rows"][0]["reason"],
+        "Already subscribed, name updated."
+    );
+    let subscription = stored_name().await;
+    assert_eq!(subscription.email, "existing@gmail.com");
+    assert_eq!(subscription.name, "Ursula");
+}
+
+#[actix_web::test]
+async fn imported_subscribers_get_a_subscription_token() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let csv = "email,name\nursula@gmail.com,Ursula\noctavia@gmail.com,Octavia\n";
+
+    // Act
+    let response = test_app.post_subscribers_import("", csv.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch subscription tokens.");
+    assert_eq!(tokens.len(), 2);
+}
+
+#[actix_web::test]
+async fn import_without_an_email_column_is_a_bad_request() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_subscribers_import("", "name\nUrsula\n".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
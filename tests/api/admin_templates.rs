@@ -0,0 +1,95 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn a_database_template_overrides_the_one_on_disk() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let template = serde_json::json!({
+        "subject": "Hey {{ subscriber_name }}",
+        "html_body": "<p>Confirm at <a href=\"{{ confirmation_link }}\">this link</a></p>",
+    });
+
+    // Act
+    let response = test_app.put_email_template("confirmation", &template).await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["content"]["subject"], "Hey le guin");
+    // No text body was given, so it is derived from the HTML
+    let text = email["content"]["text"].as_str().unwrap();
+    assert!(text.contains("Confirm at"));
+    assert!(!text.contains("<p>"));
+    assert!(text.contains("/subscriptions/confirm?subscription_token="));
+}
+
+#[actix_web::test]
+async fn a_template_that_does_not_render_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let template = serde_json::json!({
+        "subject": "Hello",
+        "html_body": "<p>{{ subscriber_name </p>",
+    });
+
+    // Act
+    let response = test_app.put_email_template("welcome", &template).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn unknown_templates_are_not_found() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let template = serde_json::json!({ "subject": "Hello", "html_body": "<p>Hi</p>" });
+
+    // Act
+    let response = test_app.put_email_template("farewell", &template).await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn template_edits_apply_to_the_next_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    // Renders, and so caches, the templates before the edit
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let template = serde_json::json!({
+        "subject": "Hey {{ subscriber_name }}",
+        "html_body": "<p>Confirm at <a href=\"{{ confirmation_link }}\">this link</a></p>",
+    });
+
+    // Act
+    let response = test_app.put_email_template("confirmation", &template).await;
+    test_app
+        .post_subscriptions("name=octavia&email=octavia_butler%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[1];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["content"]["subject"], "Hey octavia");
+}
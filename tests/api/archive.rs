@@ -0,0 +1,178 @@
+use crate::helpers::spawn_app;
+use futures_util::future::join_all;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn the_archive_lists_published_issues_but_never_drafts() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Secret draft", "content": "Not yet." });
+    test_app.post_issue(&draft).await;
+    test_app.publish_new_issue("First issue", "One").await;
+    test_app.publish_new_issue("Second issue", "Two").await;
+
+    // Act
+    let response = test_app.get_archive("").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<a href="/archive/first-issue">First issue</a>"#));
+    assert!(html.contains(r#"<a href="/archive/second-issue">Second issue</a>"#));
+    assert!(html.find("Second issue").unwrap() < html.find("First issue").unwrap());
+    assert!(!html.contains("Secret draft"));
+    assert_eq!(
+        404,
+        test_app
+            .get_archive("/secret-draft")
+            .await
+            .status()
+            .as_u16()
+    );
+}
+
+#[actix_web::test]
+async fn an_archived_issue_shows_the_snapshot_that_was_sent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let issue = test_app
+        .publish_new_issue("Launch <day>", "We **shipped**.")
+        .await;
+    let edit = serde_json::json!({ "title": "Edited", "content": "Rewritten afterwards." });
+    test_app
+        .put_issue(issue["id"].as_str().unwrap(), &edit)
+        .await;
+
+    // Act
+    let response = test_app.get_archive("/launch-day").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Launch &lt;day&gt;</h1>"));
+    assert!(html.contains("<p>We <strong>shipped</strong>.</p>"));
+    assert!(!html.contains("Rewritten afterwards."));
+}
+
+#[actix_web::test]
+async fn issues_with_the_same_title_get_distinct_slugs() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let first = test_app.publish_new_issue("Weekly", "One").await;
+    let second = test_app.publish_new_issue("Weekly", "Two").await;
+
+    // Assert
+    assert_eq!(first["slug"], "weekly");
+    assert_eq!(second["slug"], "weekly-2");
+    let html = test_app
+        .get_archive("/weekly-2")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p>Two</p>"));
+}
+
+#[actix_web::test]
+async fn issues_published_at_once_get_distinct_slugs() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Weekly", "content": "Body" });
+    let mut ids = Vec::new();
+    for _ in 0..4 {
+        let issue: serde_json::Value = test_app.post_issue(&draft).await.json().await.unwrap();
+        ids.push(issue["id"].as_str().unwrap().to_string());
+    }
+
+    // Act
+    let responses = join_all(ids.iter().map(|id| test_app.publish_issue(id))).await;
+
+    // Assert
+    for response in responses {
+        assert_eq!(200, response.status().as_u16());
+    }
+    let mut slugs = sqlx::query_scalar!(r#"SELECT slug AS "slug!" FROM newsletter_issues"#)
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    slugs.sort();
+    assert_eq!(slugs, ["weekly", "weekly-2", "weekly-3", "weekly-4"]);
+}
+
+#[actix_web::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let test_app = spawn_app().await;
+    for n in 1..=21 {
+        test_app
+            .publish_new_issue(&format!("Issue {}", n), "Body")
+            .await;
+    }
+
+    // Act
+    let first_page = test_app.get_archive("").await.text().await.unwrap();
+    let second_page = test_app.get_archive("?page=2").await.text().await.unwrap();
+    let third_page = test_app.get_archive("?page=3").await;
+
+    // Assert
+    assert!(first_page.contains(">Issue 21<"));
+    assert!(!first_page.contains(">Issue 1<"));
+    assert!(first_page.contains(r#"<a href="/archive?page=2" rel="next">"#));
+    assert!(!first_page.contains(r#"rel="prev""#));
+    assert!(second_page.contains(">Issue 1<"));
+    assert!(second_page.contains(r#"<a href="/archive?page=1" rel="prev">"#));
+    assert!(!second_page.contains(r#"rel="next""#));
+    assert_eq!(404, third_page.status().as_u16());
+}
+
+#[actix_web::test]
+async fn a_page_too_far_to_count_is_a_bad_request() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_archive(&format!("?page={}", i64::MAX)).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn newsletter_emails_link_to_the_archived_issue() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app.publish_new_issue("Issue #1", "Hello").await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = test_app.get_links(&email_request);
+    let archive_link = links
+        .iter()
+        .find(|link| link.path() == "/archive/issue-1")
+        .expect("No view in browser link in the email.");
+    let html = reqwest::get(archive_link.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<p>Hello</p>"));
+}
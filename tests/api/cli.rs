@@ -0,0 +1,123 @@
+use crate::helpers::spawn_app;
+use email_newsletter::cli::{
+    add_subscriber, confirm_subscriber, list_subscribers, remove_subscriber,
+};
+use email_newsletter::domain::{
+    NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+use email_newsletter::routes::{find_list_id, DEFAULT_LIST};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+fn new_subscriber(email: &str) -> NewSubscriber {
+    NewSubscriber {
+        email: SubscriberEmail::parse(email.into()).unwrap(),
+        name: SubscriberName::parse("le guin".into()).unwrap(),
+        time_zone: None,
+        tags: Vec::new(),
+        attributes: SubscriberAttributes::default(),
+    }
+}
+
+async fn default_list(pool: &PgPool) -> Uuid {
+    find_list_id(pool, DEFAULT_LIST).await.unwrap().unwrap()
+}
+
+#[actix_web::test]
+async fn added_subscribers_are_listed_by_status() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let list_id = default_list(&test_app.db_pool).await;
+    let pending = new_subscriber("ursula@gmail.com");
+    let confirmed = new_subscriber("le_guin@gmail.com");
+
+    // Act
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &pending,
+        SubscriptionStatus::PendingConfirmation,
+    )
+    .await
+    .unwrap();
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &confirmed,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let all = list_subscribers(&test_app.db_pool, list_id, None)
+        .await
+        .unwrap();
+    assert_eq!(all.len(), 2);
+    let pending_only = list_subscribers(
+        &test_app.db_pool,
+        list_id,
+        Some(SubscriptionStatus::PendingConfirmation),
+    )
+    .await
+    .unwrap();
+    assert_eq!(pending_only.len(), 1);
+    assert_eq!(pending_only[0].email, "ursula@gmail.com");
+}
+
+#[actix_web::test]
+async fn confirm_marks_a_pending_subscriber_as_confirmed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let list_id = default_list(&test_app.db_pool).await;
+    let subscriber = new_subscriber("ursula@gmail.com");
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &subscriber,
+        SubscriptionStatus::PendingConfirmation,
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let record = confirm_subscriber(&test_app.db_pool, list_id, &subscriber.email)
+        .await
+        .unwrap()
+        .expect("The subscriber was not found.");
+
+    // Assert
+    assert_eq!(record.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn remove_deletes_the_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let list_id = default_list(&test_app.db_pool).await;
+    let subscriber = new_subscriber("ursula@gmail.com");
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &subscriber,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let removed = remove_subscriber(&test_app.db_pool, &subscriber.email)
+        .await
+        .unwrap();
+    let removed_again = remove_subscriber(&test_app.db_pool, &subscriber.email)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(removed.is_some());
+    assert!(removed_again.is_none());
+    assert!(list_subscribers(&test_app.db_pool, list_id, None)
+        .await
+        .unwrap()
+        .is_empty());
+}
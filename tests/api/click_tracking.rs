@@ -0,0 +1,145 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+const POST_URL: &str = "https://example.com/post?id=1";
+
+/// Send an issue linking to the post at `href` to the confirmed subscriber
+/// and return the HTML they got.
+async fn send_issue(test_app: &TestApp, href: &str) -> String {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Issue #1",
+            "content": format!("Read [the post]({}).", href),
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let email = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email.body).unwrap();
+    email["content"]["html"].as_str().unwrap().to_string()
+}
+
+/// The path of the click redirect behind the link to the post.
+fn click_path(html: &str) -> String {
+    let link = html.find(">the post</a>").expect("No link to the post.");
+    let start = html[..link].rfind("/t/c/").expect("No tracked link.");
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+async fn follow(test_app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}{}", test_app.address, path))
+        .header("User-Agent", "Thunderbird/115.0")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_web::test]
+async fn links_in_newsletters_are_rewritten_to_the_click_redirect() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+
+    // Act
+    let html = send_issue(&test_app, POST_URL).await;
+
+    // Assert
+    assert!(!html.contains("https://example.com/post"));
+    assert!(html.contains("/t/c/"));
+    // The subscriber's own pages are linked to directly
+    assert!(html.contains("/preferences?subscription_token="));
+}
+
+#[actix_web::test]
+async fn following_a_tracked_link_records_a_click_and_redirects() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let html = send_issue(&test_app, POST_URL).await;
+
+    // Act
+    let response = follow(&test_app, &click_path(&html)).await;
+
+    // Assert
+    assert_eq!(302, response.status().as_u16());
+    assert_eq!(
+        response.headers()["location"],
+        "https://example.com/post?id=1"
+    );
+    let event = sqlx::query!("SELECT kind, url, user_agent FROM email_events WHERE kind = 'click'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "click");
+    assert_eq!(event.url.as_deref(), Some("https://example.com/post?id=1"));
+    assert_eq!(event.user_agent.as_deref(), Some("Thunderbird/115.0"));
+}
+
+#[actix_web::test]
+async fn relative_links_are_tracked_once_made_absolute() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let html = send_issue(&test_app, "/archive/older-issue").await;
+
+    // Act
+    let response = follow(&test_app, &click_path(&html)).await;
+
+    // Assert
+    assert_eq!(302, response.status().as_u16());
+    let location = response.headers()["location"].to_str().unwrap();
+    assert!(location.starts_with("http"));
+    assert!(location.ends_with("/archive/older-issue"));
+}
+
+#[actix_web::test]
+async fn tampered_links_are_not_followed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let html = send_issue(&test_app, POST_URL).await;
+    let path = click_path(&html);
+    let test_cases = vec![
+        (format!("{}x", path), "an extended token"),
+        (path.replacen("/t/c/", "/t/c/x", 1), "a shifted token"),
+        (
+            "/t/c/aHR0cHM6Ly9ldmlsLmV4YW1wbGU".to_string(),
+            "an unsigned link",
+        ),
+    ];
+
+    for (path, description) in test_cases {
+        // Act
+        let response = follow(&test_app, &path).await;
+
+        // Assert
+        assert_eq!(
+            404,
+            response.status().as_u16(),
+            "The API did not fail with 404 Not Found for {}.",
+            description
+        );
+    }
+    let events: i64 = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM email_events WHERE kind = 'click'"#
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(events, 0);
+}
@@ -0,0 +1,276 @@
+use crate::helpers::{spawn_app, TestApp};
+use email_newsletter::suppression::{suppress, SuppressionReason, SuppressionSource};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscription_token(test_app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn mock_email_server(test_app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+}
+
+/// The last email sent, with its recipient.
+async fn last_email(test_app: &TestApp) -> (String, wiremock::Request) {
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let recipient = body["recipients"][0]["address"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    (recipient, email_request)
+}
+
+async fn request_change(test_app: &TestApp, email: &str) -> reqwest::Response {
+    let token = subscription_token(test_app).await;
+    test_app
+        .post_email_change(format!(
+            "subscription_token={}&email={}",
+            token,
+            email.replace('@', "%40")
+        ))
+        .await
+}
+
+async fn confirm_change(link: reqwest::Url) -> reqwest::Response {
+    reqwest::Client::new().post(link).send().await.unwrap()
+}
+
+async fn saved_email(test_app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT email FROM subscriptions ORDER BY subscribed_at LIMIT 1")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn requesting_a_change_sends_a_link_to_the_new_address_only() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+
+    // Act
+    let response = request_change(&test_app, "ursula@example.com").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("ursula@example.com"));
+    let (recipient, email_request) = last_email(&test_app).await;
+    assert_eq!(recipient, "ursula@example.com");
+    let links = test_app.get_links(&email_request);
+    assert_eq!(links[0].path(), "/preferences/email/confirm");
+    assert_eq!(saved_email(&test_app).await, "ursula_le_guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn confirming_a_change_swaps_the_address_and_notifies_the_old_one() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    request_change(&test_app, "ursula@example.com").await;
+    let (_, email_request) = last_email(&test_app).await;
+    let confirmation_link = test_app.get_links(&email_request).remove(0);
+
+    // Act
+    let response = confirm_change(confirmation_link).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(saved_email(&test_app).await, "ursula@example.com");
+    // The subscriber keeps their lists and history
+    let status = sqlx::query_scalar!("SELECT status FROM list_subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+    let change = sqlx::query!("SELECT field, old_value, new_value FROM preference_changes")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(change.field, "email");
+    assert_eq!(
+        change.old_value.as_deref(),
+        Some("ursula_le_guin@gmail.com")
+    );
+    assert_eq!(change.new_value.as_deref(), Some("ursula@example.com"));
+    let (recipient, _) = last_email(&test_app).await;
+    assert_eq!(recipient, "ursula_le_guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn invalid_change_requests_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let test_cases = vec![
+        ("not-an-email", "an invalid address"),
+        ("ursula_le_guin@gmail.com", "the current address"),
+    ];
+
+    for (email, description) in test_cases {
+        // Act
+        let response = request_change(&test_app, email).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn unknown_tokens_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let requested = test_app
+        .post_email_change("subscription_token=nope&email=ursula%40example.com".into())
+        .await;
+    let link = reqwest::Url::parse(&format!(
+        "{}/preferences/email/confirm?token=nope",
+        test_app.address
+    ))
+    .unwrap();
+    let opened = reqwest::get(link.clone()).await.unwrap();
+    let confirmed = confirm_change(link).await;
+
+    // Assert
+    assert_eq!(401, requested.status().as_u16());
+    assert_eq!(401, opened.status().as_u16());
+    assert_eq!(401, confirmed.status().as_u16());
+}
+
+#[actix_web::test]
+async fn opening_the_verification_link_only_asks_for_confirmation() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    request_change(&test_app, "ursula@example.com").await;
+    let (_, email_request) = last_email(&test_app).await;
+    let confirmation_link = test_app.get_links(&email_request).remove(0);
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"method="post""#));
+    assert!(page.contains("ursula@example.com"));
+    assert_eq!(saved_email(&test_app).await, "ursula_le_guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn changing_only_the_case_of_the_address_is_allowed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    request_change(&test_app, "Ursula_Le_Guin@gmail.com").await;
+    let (_, email_request) = last_email(&test_app).await;
+    let confirmation_link = test_app.get_links(&email_request).remove(0);
+
+    // Act
+    let response = confirm_change(confirmation_link).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(saved_email(&test_app).await, "Ursula_Le_Guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn moving_to_an_address_that_is_already_subscribed_returns_a_409() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    request_change(&test_app, "ursula@example.com").await;
+    let (_, email_request) = last_email(&test_app).await;
+    let confirmation_link = test_app.get_links(&email_request).remove(0);
+    test_app
+        .post_subscriptions("name=someone&email=ursula%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = confirm_change(confirmation_link).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(saved_email(&test_app).await, "ursula_le_guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn moving_to_a_case_variant_of_another_subscribers_address_returns_a_409() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    test_app
+        .post_subscriptions("name=someone&email=ursula%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    request_change(&test_app, "Ursula@example.com").await;
+    let (_, email_request) = last_email(&test_app).await;
+    let confirmation_link = test_app.get_links(&email_request).remove(0);
+
+    // Act
+    let response = confirm_change(confirmation_link).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(saved_email(&test_app).await, "ursula_le_guin@gmail.com");
+}
+
+#[actix_web::test]
+async fn moving_to_a_suppressed_address_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    suppress(
+        &test_app.db_pool,
+        "ursula@example.com",
+        SuppressionReason::Complaint,
+        SuppressionSource::Webhook,
+    )
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = request_change(&test_app, "ursula@example.com").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(saved_email(&test_app).await, "ursula_le_guin@gmail.com");
+}
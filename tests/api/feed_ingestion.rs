@@ -0,0 +1,213 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn blog_rss(posts: &[(&str, &str)]) -> String {
+    let items: String = posts
+        .iter()
+        .map(|(guid, title)| {
+            format!(
+                "<item><guid>{guid}</guid><title>{title}</title>\
+                 <link>https://blog.example.com/{guid}</link>\
+                 <description>About {title}.</description></item>"
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel>
+<title>The blog</title><link>https://blog.example.com</link><description>Posts</description>
+{items}</channel></rss>"#
+    )
+}
+
+/// Serve `body` as the blog feed for one ingestion pass.
+async fn ingest_blog(test_app: &TestApp, body: String) {
+    let _mock_guard = Mock::given(method("GET"))
+        .and(path("/blog/rss.xml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .expect(1)
+        .mount_as_scoped(&test_app.feed_server)
+        .await;
+    test_app.run_feed_ingestion().await;
+}
+
+async fn list_issues(test_app: &TestApp) -> Vec<serde_json::Value> {
+    test_app
+        .get_admin("/admin/issues")
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn the_first_poll_does_not_turn_existing_items_into_an_issue() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    ingest_blog(&test_app, blog_rss(&[("post-1", "First post")])).await;
+
+    // Assert
+    assert!(list_issues(&test_app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn new_items_become_a_draft_digest() {
+    // Arrange
+    let test_app = spawn_app().await;
+    ingest_blog(&test_app, blog_rss(&[("post-1", "First post")])).await;
+
+    // Act
+    let posts = [
+        ("post-3", "Third post"),
+        ("post-2", "Second post"),
+        ("post-1", "First post"),
+    ];
+    ingest_blog(&test_app, blog_rss(&posts)).await;
+
+    // Assert
+    let issues = list_issues(&test_app).await;
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["status"], "draft");
+    let issue: serde_json::Value = test_app
+        .get_admin(&format!(
+            "/admin/issues/{}",
+            issues[0]["id"].as_str().unwrap()
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["title"], "New on The blog");
+    let content = issue["content"].as_str().unwrap();
+    assert!(content.contains("## [Third post](https://blog.example.com/post-3)"));
+    assert!(content.contains("About Second post."));
+    assert!(!content.contains("First post"));
+    assert!(content.find("Third post") < content.find("Second post"));
+}
+
+#[actix_web::test]
+async fn items_are_only_sent_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    ingest_blog(&test_app, blog_rss(&[("post-1", "First post")])).await;
+    let posts = [("post-2", "Second post"), ("post-1", "First post")];
+    ingest_blog(&test_app, blog_rss(&posts)).await;
+
+    // Act
+    ingest_blog(&test_app, blog_rss(&posts)).await;
+
+    // Assert
+    assert_eq!(list_issues(&test_app).await.len(), 1);
+}
+
+#[actix_web::test]
+async fn feeds_configured_for_it_get_their_digest_scheduled() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let atom = |entries: &[&str]| {
+        let entries: String = entries
+            .iter()
+            .map(|id| {
+                format!(
+                    "<entry><id>urn:news:{id}</id><title>News {id}</title>\
+                     <updated>2023-03-27T09:00:00Z</updated></entry>"
+                )
+            })
+            .collect();
+        format!(
+            r#"<?xml version="1.0"?><feed xmlns="http://www.w3.org/2005/Atom">
+<id>urn:news</id><title>News</title><updated>2023-03-27T09:00:00Z</updated>{entries}</feed>"#
+        )
+    };
+    for entries in [vec!["1"], vec!["2", "1"]] {
+        let _mock_guard = Mock::given(path("/news/atom.xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(atom(&entries)))
+            .mount_as_scoped(&test_app.feed_server)
+            .await;
+
+        // Act
+        test_app.run_feed_ingestion().await;
+    }
+
+    // Assert
+    let issues = list_issues(&test_app).await;
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["status"], "scheduled");
+    let scheduled_at: chrono::DateTime<chrono::Utc> =
+        serde_json::from_value(issues[0]["scheduled_at"].clone()).unwrap();
+    let delay = scheduled_at - chrono::Utc::now();
+    assert!(delay > chrono::Duration::minutes(55) && delay <= chrono::Duration::minutes(60));
+}
+
+#[actix_web::test]
+async fn unchanged_feeds_are_fetched_conditionally() {
+    // Arrange
+    let test_app = spawn_app().await;
+    {
+        let _mock_guard = Mock::given(path("/blog/rss.xml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(blog_rss(&[("post-1", "First post")])),
+            )
+            .expect(1)
+            .mount_as_scoped(&test_app.feed_server)
+            .await;
+        test_app.run_feed_ingestion().await;
+    }
+    Mock::given(path("/blog/rss.xml"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&test_app.feed_server)
+        .await;
+
+    // Act
+    test_app.run_feed_ingestion().await;
+
+    // Assert
+    assert!(list_issues(&test_app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn concurrent_polls_create_a_single_issue() {
+    // Arrange
+    let test_app = spawn_app().await;
+    ingest_blog(&test_app, blog_rss(&[("post-1", "First post")])).await;
+    let posts = [("post-2", "Second post"), ("post-1", "First post")];
+    Mock::given(path("/blog/rss.xml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(blog_rss(&posts)))
+        .mount(&test_app.feed_server)
+        .await;
+
+    // Act
+    // Two replicas polling at the same time
+    futures_util::join!(test_app.run_feed_ingestion(), test_app.run_feed_ingestion());
+
+    // Assert
+    assert_eq!(list_issues(&test_app).await.len(), 1);
+}
+
+#[actix_web::test]
+async fn a_failing_feed_is_retried_on_the_next_poll() {
+    // Arrange
+    let test_app = spawn_app().await;
+    ingest_blog(&test_app, blog_rss(&[("post-1", "First post")])).await;
+    let posts = [("post-2", "Second post"), ("post-1", "First post")];
+    {
+        let _mock_guard = Mock::given(path("/blog/rss.xml"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount_as_scoped(&test_app.feed_server)
+            .await;
+        test_app.run_feed_ingestion().await;
+    }
+
+    // Act
+    ingest_blog(&test_app, blog_rss(&posts)).await;
+
+    // Assert
+    assert_eq!(list_issues(&test_app).await.len(), 1);
+}
@@ -0,0 +1,131 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::Response;
+use std::str::FromStr;
+
+async fn get_feed(test_app: &TestApp, path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut request = reqwest::Client::new().get(format!("{}{}", test_app.address, path));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+#[actix_web::test]
+async fn the_atom_feed_has_the_full_content_of_published_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Secret draft", "content": "Not yet." });
+    test_app.post_issue(&draft).await;
+    let issue = test_app
+        .publish_new_issue("First issue", "We **shipped**.")
+        .await;
+
+    // Act
+    let response = get_feed(&test_app, "/feed.xml", &[]).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = atom_syndication::Feed::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(feed.entries().len(), 1);
+    let entry = &feed.entries()[0];
+    assert_eq!(
+        entry.id(),
+        format!("urn:uuid:{}", issue["id"].as_str().unwrap())
+    );
+    assert_eq!(entry.title().as_str(), "First issue");
+    assert_eq!(
+        entry.links()[0].href(),
+        "http://127.0.0.1/archive/first-issue"
+    );
+    assert_eq!(
+        entry.content().unwrap().value(),
+        Some("<p>We <strong>shipped</strong>.</p>\n")
+    );
+    assert_eq!(feed.updated(), entry.updated());
+}
+
+#[actix_web::test]
+async fn the_rss_feed_has_the_full_content_of_published_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Secret draft", "content": "Not yet." });
+    test_app.post_issue(&draft).await;
+    test_app.publish_new_issue("First issue", "One").await;
+    test_app.publish_new_issue("Second issue", "Two").await;
+
+    // Act
+    let response = get_feed(&test_app, "/rss.xml", &[]).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let channel = rss::Channel::from_str(&response.text().await.unwrap()).unwrap();
+    let titles: Vec<_> = channel
+        .items()
+        .iter()
+        .filter_map(|item| item.title())
+        .collect();
+    assert_eq!(titles, vec!["Second issue", "First issue"]);
+    let item = &channel.items()[0];
+    assert_eq!(item.description(), Some("<p>Two</p>"));
+    assert!(item.guid().unwrap().value().starts_with("urn:uuid:"));
+    assert!(item.pub_date().is_some());
+}
+
+#[actix_web::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.publish_new_issue("First issue", "One").await;
+    for path in ["/feed.xml", "/rss.xml"] {
+        let response = get_feed(&test_app, path, &[]).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        // Act
+        let by_etag = get_feed(&test_app, path, &[("If-None-Match", &etag)]).await;
+        let by_date = get_feed(&test_app, path, &[("If-Modified-Since", &last_modified)]).await;
+
+        // Assert
+        assert_eq!(304, by_etag.status().as_u16());
+        assert_eq!(304, by_date.status().as_u16());
+        assert_eq!(by_etag.headers()["ETag"], etag.as_str());
+    }
+}
+
+#[actix_web::test]
+async fn publishing_an_issue_changes_the_feed_version() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.publish_new_issue("First issue", "One").await;
+    let response = get_feed(&test_app, "/feed.xml", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+
+    // Act
+    test_app.publish_new_issue("Second issue", "Two").await;
+    let response = get_feed(&test_app, "/feed.xml", &[("If-None-Match", &etag)]).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+}
+
+#[actix_web::test]
+async fn feeds_without_issues_are_still_valid() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = get_feed(&test_app, "/feed.xml", &[]).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let feed = atom_syndication::Feed::from_str(&response.text().await.unwrap()).unwrap();
+    assert!(feed.entries().is_empty());
+}
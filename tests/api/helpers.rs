@@ -1,10 +1,18 @@
-use email_newsletter::configuration::{get_configuration, DatabaseSettings};
+use email_newsletter::configuration::{
+    get_configuration, DatabaseSettings, FeedSettings, WebhookSettings,
+};
+use email_newsletter::email_templates::EmailTemplates;
+use email_newsletter::feed_ingestion::FeedIngester;
+use email_newsletter::scheduler::Scheduler;
 use email_newsletter::startup::{get_connection_pool, Application};
 use email_newsletter::telemetry::set_subscriber;
 use once_cell::sync::Lazy;
 use reqwest::Response;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test".into();
@@ -31,7 +39,18 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub db_pool: PgPool,
+    pub email_server: MockServer,
+    pub admin_username: String,
+    pub admin_password: String,
+    /// Credentials the email provider calls our webhooks with
+    pub webhooks: WebhookSettings,
+    pub scheduler: Scheduler,
+    /// Serves the external feeds listed in the configuration, see
+    /// `TestApp::run_feed_ingestion`
+    pub feed_server: MockServer,
+    pub feed_ingester: FeedIngester,
 }
 
 impl TestApp {
@@ -44,26 +63,378 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+
+    pub async fn post_subscribers_import(&self, query: &str, csv: String) -> Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/subscribers/import?{}",
+                self.address, query
+            ))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .header("Content-Type", "text/csv")
+            .body(csv)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_list(&self, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/lists", self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// POST to `/admin/segments` or one of its children, e.g. `/preview`.
+    pub async fn post_segments(&self, path: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/segments{}", self.address, path))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_subscriber(&self, id: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/subscribers/{}", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences(&self, subscription_token: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!("{}/preferences", self.address))
+            .query(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Unsubscribe the way a mail client's one-click button does.
+    pub async fn post_unsubscribe(&self, subscription_token: &str) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/unsubscribe", self.address))
+            .query(&[("subscription_token", subscription_token)])
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences(&self, body: String) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/preferences", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_change(&self, body: String) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/preferences/email", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_events(&self, events: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email-events", self.address))
+            .basic_auth(
+                &self.webhooks.username,
+                Some(self.webhooks.password.expose_secret()),
+            )
+            .json(events)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_privacy_request(&self, body: String) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/privacy/requests", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Extract the links from the plain-text body of an email sent through the mock server,
+    /// pointed at the test application's port.
+    pub fn get_links(&self, email_request: &wiremock::Request) -> Vec<reqwest::Url> {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        body["content"]["text"]
+            .as_str()
+            .unwrap()
+            .split_whitespace()
+            .filter(|word| word.starts_with("http"))
+            .map(|link| {
+                let mut link = reqwest::Url::parse(link).unwrap();
+                assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+                link.set_port(Some(self.port)).unwrap();
+                link
+            })
+            .collect()
+    }
+
+    pub async fn put_email_template(&self, name: &str, template: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .put(format!("{}/admin/templates/{}", self.address, name))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(template)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Publish a newsletter, then run the scheduler so that the emails it
+    /// queued are out by the time this returns.
+    pub async fn post_newsletters(&self, body: &serde_json::Value) -> Response {
+        let response = reqwest::Client::new()
+            .post(format!("{}/admin/newsletters", self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.run_scheduler().await;
+        response
+    }
+
+    pub async fn post_newsletter_preview(&self, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/newsletters/preview", self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_issue(&self, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/issues", self.address))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_issue(&self, id: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .put(format!("{}/admin/issues/{}", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Publish a draft, then run the scheduler so that the emails it queued
+    /// are out by the time this returns.
+    pub async fn publish_issue(&self, id: &str) -> Response {
+        let response = reqwest::Client::new()
+            .post(format!("{}/admin/issues/{}/publish", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.run_scheduler().await;
+        response
+    }
+
+    pub async fn delete_issue(&self, id: &str) -> Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/issues/{}", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_issue_schedule(&self, id: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .put(format!("{}/admin/issues/{}/schedule", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_issue_schedule(&self, id: &str) -> Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/issues/{}/schedule", self.address, id))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Run one scheduler pass: publish due issues and drain the delivery queue.
+    pub async fn run_scheduler(&self) {
+        self.scheduler.tick().await
+    }
+
+    /// Poll the configured feeds once: `/blog/rss.xml` on the feed server
+    /// yields drafts, `/news/atom.xml` issues scheduled an hour out.
+    pub async fn run_feed_ingestion(&self) {
+        self.feed_ingester.tick().await
+    }
+
+    /// Create an issue, publish it, and return it as the admin API shows it.
+    pub async fn publish_new_issue(&self, title: &str, content: &str) -> serde_json::Value {
+        let draft = serde_json::json!({ "title": title, "content": content });
+        let issue: serde_json::Value = self.post_issue(&draft).await.json().await.unwrap();
+        let id = issue["id"].as_str().unwrap();
+        assert_eq!(200, self.publish_issue(id).await.status().as_u16());
+        self.get_admin(&format!("/admin/issues/{}", id))
+            .await
+            .json()
+            .await
+            .unwrap()
+    }
+
+    /// GET a page of the public archive, e.g. `""` or `"/some-slug"`.
+    pub async fn get_archive(&self, path: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!("{}/archive{}", self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// GET an admin endpoint, e.g. `/admin/issues`.
+    pub async fn get_admin(&self, path: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!("{}{}", self.address, path))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Subscribe through the signup form and return the confirmation link.
+    pub async fn create_unconfirmed_subscriber(&self) -> reqwest::Url {
+        let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+        let _mock_guard = Mock::given(path("/api/v1/transmissions"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body.into())
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let email_request = self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_links(&email_request).remove(0)
+    }
+
+    pub async fn create_confirmed_subscriber(&self) {
+        let confirmation_link = self.create_unconfirmed_subscriber().await;
+        let _mock_guard = Mock::given(path("/api/v1/transmissions"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Welcome email")
+            .mount_as_scoped(&self.email_server)
+            .await;
+        reqwest::get(confirmation_link)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    pub async fn get_subscribers_export(&self, query: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/subscribers/export?{}",
+                self.address, query
+            ))
+            .basic_auth(&self.admin_username, Some(&self.admin_password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
 // Launch the application in the background
 pub async fn spawn_app() -> TestApp {
     Lazy::force(&TRACING);
+    let email_server = MockServer::start().await;
+    let feed_server = MockServer::start().await;
+
     let mut configuration = get_configuration().expect("Failed to get configuration.");
     configuration.database.database_name = Uuid::new_v4().to_string();
     configuration.application.port = 0;
+    configuration.email_client.base_url = email_server.uri();
+    // Tests drive the scheduler themselves, see `TestApp::run_scheduler`
+    configuration.scheduler.enabled = false;
+    configuration.feed_ingestion.enabled = false;
+    configuration.feed_ingestion.feeds = vec![
+        FeedSettings {
+            url: format!("{}/blog/rss.xml", feed_server.uri()),
+            schedule_after_minutes: None,
+            lists: Vec::new(),
+        },
+        FeedSettings {
+            url: format!("{}/news/atom.xml", feed_server.uri()),
+            schedule_after_minutes: Some(60),
+            lists: Vec::new(),
+        },
+    ];
+    configuration.subscriber_fields.signup_form_tags = vec!["beta".into()];
+    configuration.tracking.open_tracking = true;
+    configuration.tracking.click_tracking = true;
 
     configure_database(&configuration.database).await;
 
     let application = Application::build(configuration.clone())
         .await
         .expect("Failed to build application.");
-    let address = format!("http://127.0.0.1:{}", application.port());
+    let port = application.port();
+    let address = format!("http://127.0.0.1:{}", port);
+    #[allow(clippy::let_underscore_future)]
     let _ = tokio::spawn(application.run_until_stopped());
 
     TestApp {
         address,
+        port,
         db_pool: get_connection_pool(&configuration.database),
+        email_server,
+        admin_username: configuration.admin.username.clone(),
+        admin_password: configuration.admin.password.expose_secret().clone(),
+        webhooks: configuration.webhooks.clone(),
+        scheduler: Scheduler::build(
+            &configuration,
+            EmailTemplates::from_directory(configuration.application.templates_directory.as_ref())
+                .expect("Failed to load email templates."),
+        ),
+        feed_server,
+        feed_ingester: FeedIngester::build(&configuration).expect("Failed to build feed ingester."),
     }
 
     // tracing::info!("Postgres URL: {:?}", configuration.database.with_db());
@@ -92,15 +463,7 @@ pub async fn spawn_app() -> TestApp {
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    // create database
-    let mut connection = PgConnection::connect_with(&config.without_db())
-        .await
-        .expect("Could not connect to Postgres.");
-
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
-        .await
-        .expect("Failed to create database");
+    create_database(config).await;
 
     // migrate
     let connection_pool = PgPool::connect_with(config.with_db())
@@ -114,3 +477,15 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 
     connection_pool
 }
+
+// Create an empty database, leaving migrations to the caller
+pub async fn create_database(config: &DatabaseSettings) {
+    let mut connection = PgConnection::connect_with(&config.without_db())
+        .await
+        .expect("Could not connect to Postgres.");
+
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database");
+}
@@ -0,0 +1,400 @@
+use crate::helpers::{spawn_app, TestApp};
+use email_newsletter::cli::add_subscriber;
+use email_newsletter::domain::{
+    NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriberTimeZone,
+    SubscriptionStatus,
+};
+use email_newsletter::routes::{find_list_id, DEFAULT_LIST};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(test_app: &TestApp) -> String {
+    let draft = serde_json::json!({ "title": "Tuesday issue", "content": "Hello." });
+    let issue: serde_json::Value = test_app.post_issue(&draft).await.json().await.unwrap();
+    issue["id"].as_str().unwrap().to_string()
+}
+
+fn tuesday_morning_in_sao_paulo() -> serde_json::Value {
+    serde_json::json!({ "send_at": "2099-03-24T09:00", "time_zone": "America/Sao_Paulo" })
+}
+
+async fn make_due(test_app: &TestApp, id: &str) {
+    sqlx::query(
+        "UPDATE newsletter_issues SET scheduled_at = now() - interval '1 minute' WHERE id = $1",
+    )
+    .bind(uuid::Uuid::parse_str(id).unwrap())
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn send_times_are_read_in_the_given_time_zone() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+
+    // Act
+    let response = test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    assert_eq!(issue["scheduled_at"], "2099-03-24T12:00:00Z");
+    assert_eq!(issue["schedule_time_zone"], "America/Sao_Paulo");
+}
+
+#[actix_web::test]
+async fn a_scheduled_issue_can_be_rescheduled() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+
+    // Act
+    let later = serde_json::json!({ "send_at": "2099-03-24T09:00", "time_zone": "Europe/Lisbon" });
+    let response = test_app.put_issue_schedule(&id, &later).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["scheduled_at"], "2099-03-24T09:00:00Z");
+    assert_eq!(issue["schedule_time_zone"], "Europe/Lisbon");
+}
+
+#[actix_web::test]
+async fn invalid_send_times_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "send_at": "2099-03-24T09:00", "time_zone": "Nowhere/Special" }),
+            "an unknown time zone",
+        ),
+        (
+            serde_json::json!({ "send_at": "2001-03-24T09:00", "time_zone": "UTC" }),
+            "a time in the past",
+        ),
+        (
+            serde_json::json!({ "send_at": "2099-03-08T02:30", "time_zone": "America/New_York" }),
+            "a time skipped by daylight saving",
+        ),
+        (
+            serde_json::json!({ "send_at": "next tuesday", "time_zone": "UTC" }),
+            "a malformed time",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = test_app.put_issue_schedule(&id, &body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn a_scheduled_issue_can_be_cancelled() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+
+    // Act
+    let response = test_app.delete_issue_schedule(&id).await;
+    let second_response = test_app.delete_issue_schedule(&id).await;
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(404, second_response.status().as_u16());
+    let issue: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert!(issue["scheduled_at"].is_null());
+}
+
+#[actix_web::test]
+async fn published_issues_cannot_be_scheduled() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    test_app.publish_issue(&id).await;
+
+    // Act
+    let response = test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn issues_that_are_not_due_yet_are_left_alone() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let issue: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "scheduled");
+}
+
+#[actix_web::test]
+async fn due_issues_are_sent_exactly_once_by_concurrent_schedulers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+    make_due(&test_app, &id).await;
+
+    // Act
+    // Two replicas ticking at the same time
+    futures_util::join!(test_app.run_scheduler(), test_app.run_scheduler());
+    test_app.run_scheduler().await;
+
+    // Assert
+    let issue: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "published");
+    assert_eq!(issue["published_title"], "Tuesday issue");
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[actix_web::test]
+async fn the_latest_revision_is_sent_when_the_issue_is_due() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+    let edit = serde_json::json!({ "title": "Tuesday issue, final", "content": "Hello again." });
+    test_app.put_issue(&id, &edit).await;
+    make_due(&test_app, &id).await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["content"]["subject"], "Tuesday issue, final");
+    assert!(email["content"]["html"]
+        .as_str()
+        .unwrap()
+        .contains("Hello again."));
+}
+
+#[actix_web::test]
+async fn failed_sends_stay_in_the_queue_for_a_retry() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+    make_due(&test_app, &id).await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let n_retries: i16 = sqlx::query_scalar("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_retries, 1);
+}
+
+#[actix_web::test]
+async fn emails_that_fail_to_render_are_retried_later() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    // Renders while the override is checked, with an empty title, but not for
+    // a real issue
+    let template = serde_json::json!({
+        "subject": "{{ title }}",
+        "html_body": "{% if title %}{{ missing_variable }}{% endif %}",
+    });
+    assert_eq!(
+        200,
+        test_app
+            .put_email_template("newsletter", &template)
+            .await
+            .status()
+            .as_u16()
+    );
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    test_app
+        .put_issue_schedule(&id, &tuesday_morning_in_sao_paulo())
+        .await;
+    make_due(&test_app, &id).await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.execute_after > chrono::Utc::now());
+}
+
+async fn add_confirmed_subscriber(test_app: &TestApp, email: &str, time_zone: Option<&str>) {
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.into()).unwrap(),
+        name: SubscriberName::parse("le guin".into()).unwrap(),
+        time_zone: time_zone.map(|time_zone| SubscriberTimeZone::parse(time_zone.into()).unwrap()),
+        tags: Vec::new(),
+        attributes: SubscriberAttributes::default(),
+    };
+    let list_id = find_list_id(&test_app.db_pool, DEFAULT_LIST)
+        .await
+        .unwrap()
+        .unwrap();
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &new_subscriber,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn issues_can_go_out_at_a_local_hour_in_each_subscribers_time_zone() {
+    // Arrange
+    let test_app = spawn_app().await;
+    add_confirmed_subscriber(&test_app, "tokyo@example.com", Some("Asia/Tokyo")).await;
+    add_confirmed_subscriber(
+        &test_app,
+        "sao_paulo@example.com",
+        Some("America/Sao_Paulo"),
+    )
+    .await;
+    add_confirmed_subscriber(&test_app, "unknown@example.com", None).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+    let schedule = serde_json::json!({
+        "send_at": "2099-03-24T08:00",
+        "time_zone": "Europe/Lisbon",
+        "in_subscriber_time_zone": true,
+    });
+
+    // Act
+    let response = test_app.put_issue_schedule(&id, &schedule).await;
+    make_due(&test_app, &id).await;
+    test_app.run_scheduler().await;
+
+    // Assert
+    let issue: serde_json::Value = response.json().await.unwrap();
+    // The first wave is when 08:00 comes round in the easternmost time zone
+    assert_eq!(issue["scheduled_at"], "2099-03-23T18:00:00Z");
+    assert_eq!(issue["local_send_time"], "2099-03-24T08:00:00");
+
+    let waves: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT s.email, to_char(q.execute_after AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI')
+        FROM issue_delivery_queue q JOIN subscriptions s ON s.id = q.subscriber_id
+        ORDER BY q.execute_after
+        "#,
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        waves,
+        vec![
+            ("tokyo@example.com".into(), "2099-03-23T23:00".into()),
+            // No time zone on file, so the issue's own zone is used
+            ("unknown@example.com".into(), "2099-03-24T08:00".into()),
+            ("sao_paulo@example.com".into(), "2099-03-24T11:00".into()),
+        ]
+    );
+}
@@ -0,0 +1,166 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+/// The path of the last tracking URL in some HTML, e.g. the pixel at `/t/o/`.
+fn tracking_path(html: &str, prefix: &str) -> String {
+    let start = html.rfind(prefix).expect("No tracking URL.");
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+async fn get(test_app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}{}", test_app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_stats(test_app: &TestApp, id: &str) -> serde_json::Value {
+    let response = test_app
+        .get_admin(&format!("/admin/issues/{}/stats", id))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[actix_web::test]
+async fn stats_count_sends_opens_clicks_and_unsubscribes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let issue = test_app
+        .publish_new_issue("Issue #1", "Read [the post](https://example.com/post).")
+        .await;
+    let email = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email.body).unwrap();
+    let html = email["content"]["html"].as_str().unwrap();
+    let link = html.find(">the post</a>").unwrap();
+    let click = tracking_path(&html[..link], "/t/c/");
+    let pixel = tracking_path(html, "/t/o/");
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    get(&test_app, &pixel).await;
+    get(&test_app, &pixel).await;
+    get(&test_app, &click).await;
+    test_app.post_unsubscribe(&token).await;
+
+    // Assert
+    let stats = get_stats(&test_app, issue["id"].as_str().unwrap()).await;
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["delivered"], 1);
+    assert_eq!(stats["opens"], 2);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["clicks"], 1);
+    assert_eq!(stats["unique_clicks"], 1);
+    assert_eq!(stats["unsubscribes"], 1);
+    assert_eq!(stats["bounces"], 0);
+    assert_eq!(stats["top_links"][0]["url"], "https://example.com/post");
+    assert_eq!(stats["top_links"][0]["clicks"], 1);
+    let hourly = stats["hourly"].as_array().unwrap();
+    assert_eq!(hourly.len(), 48);
+    assert_eq!(hourly[0]["opens"], 2);
+    assert_eq!(hourly[0]["clicks"], 1);
+}
+
+#[actix_web::test]
+async fn issues_without_events_have_empty_stats() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Draft", "content": "Not sent yet." });
+    let issue: serde_json::Value = test_app.post_issue(&draft).await.json().await.unwrap();
+
+    // Act
+    let stats = get_stats(&test_app, issue["id"].as_str().unwrap()).await;
+
+    // Assert
+    assert_eq!(stats["sent"], 0);
+    assert_eq!(stats["unique_opens"], 0);
+    assert!(stats["top_links"].as_array().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn stats_add_up_the_shards_of_each_total() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = serde_json::json!({ "title": "Draft", "content": "Not sent yet." });
+    let issue: serde_json::Value = test_app.post_issue(&draft).await.json().await.unwrap();
+    let issue_id: uuid::Uuid = issue["id"].as_str().unwrap().parse().unwrap();
+    for shard in [0i16, 1] {
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_stats (issue_id, kind, shard, total, unique_subscribers)
+            VALUES ($1, 'open', $2, 3, 2)
+            "#,
+            issue_id,
+            shard
+        )
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_hourly_stats (issue_id, kind, hour, shard, total)
+            VALUES ($1, 'open', 0, $2, 3)
+            "#,
+            issue_id,
+            shard
+        )
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_link_stats (issue_id, url, shard, total, unique_subscribers)
+            VALUES ($1, 'https://example.com/post', $2, 1, 1)
+            "#,
+            issue_id,
+            shard
+        )
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let stats = get_stats(&test_app, issue["id"].as_str().unwrap()).await;
+
+    // Assert
+    assert_eq!(stats["opens"], 6);
+    assert_eq!(stats["unique_opens"], 4);
+    assert_eq!(stats["hourly"][0]["opens"], 6);
+    assert_eq!(stats["top_links"].as_array().unwrap().len(), 1);
+    assert_eq!(stats["top_links"][0]["clicks"], 2);
+}
+
+#[actix_web::test]
+async fn stats_for_an_unknown_issue_return_a_404() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .get_admin(&format!("/admin/issues/{}/stats", uuid::Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,175 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(test_app: &TestApp) -> String {
+    let draft = serde_json::json!({ "title": "Draft title", "content": "First paragraph." });
+    let response = test_app.post_issue(&draft).await;
+    assert_eq!(201, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert_eq!(issue["revision"], 1);
+    issue["id"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn every_edit_of_a_draft_is_kept_as_a_revision() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+
+    // Act
+    let edit = serde_json::json!({ "title": "Final title", "content": "First paragraph, edited." });
+    let response = test_app.put_issue(&id, &edit).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["revision"], 2);
+    assert_eq!(issue["title"], "Final title");
+
+    let revisions: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}/revisions", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let revisions = revisions.as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[1]["title"], "Draft title");
+
+    let first: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}/revisions/1", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(first["content"], "First paragraph.");
+}
+
+#[actix_web::test]
+async fn revisions_can_be_diffed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    let edit = serde_json::json!({ "title": "Draft title", "content": "Second paragraph." });
+    test_app.put_issue(&id, &edit).await;
+
+    // Act
+    let response = test_app
+        .get_admin(&format!("/admin/issues/{}/diff?from=1", id))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let diff = response.text().await.unwrap();
+    assert!(diff.contains("--- revision 1\n+++ revision 2\n"));
+    assert!(diff.contains("-First paragraph.\n+Second paragraph.\n"));
+    assert!(!diff.contains("-# Draft title"));
+}
+
+#[actix_web::test]
+async fn edits_based_on_an_old_revision_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    let edit = serde_json::json!({ "title": "A", "content": "A", "base_revision": 1 });
+    assert_eq!(200, test_app.put_issue(&id, &edit).await.status().as_u16());
+
+    // Act
+    let edit = serde_json::json!({ "title": "B", "content": "B", "base_revision": 1 });
+    let response = test_app.put_issue(&id, &edit).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn publishing_snapshots_the_content_that_was_sent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let id = create_draft(&test_app).await;
+
+    // Act
+    let response = test_app.publish_issue(&id).await;
+    let edit = serde_json::json!({ "title": "Edited afterwards", "content": "Changed." });
+    test_app.put_issue(&id, &edit).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+
+    let issue: serde_json::Value = test_app
+        .get_admin(&format!("/admin/issues/{}", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "published");
+    assert_eq!(issue["revision"], 2);
+    assert_eq!(issue["published_revision"], 1);
+    assert_eq!(issue["published_title"], "Draft title");
+    assert_eq!(issue["published_html"], "<p>First paragraph.</p>\n");
+}
+
+#[actix_web::test]
+async fn an_issue_is_only_published_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let id = create_draft(&test_app).await;
+    assert_eq!(200, test_app.publish_issue(&id).await.status().as_u16());
+
+    // Act
+    let response = test_app.publish_issue(&id).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn drafts_can_be_deleted_but_published_issues_cannot() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let draft = create_draft(&test_app).await;
+    let published = create_draft(&test_app).await;
+    test_app.publish_issue(&published).await;
+
+    // Act
+    let draft_response = test_app.delete_issue(&draft).await;
+    let published_response = test_app.delete_issue(&published).await;
+
+    // Assert
+    assert_eq!(204, draft_response.status().as_u16());
+    assert_eq!(409, published_response.status().as_u16());
+    let issues: serde_json::Value = test_app
+        .get_admin("/admin/issues")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issues = issues.as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["id"], published.as_str());
+}
+
+#[actix_web::test]
+async fn unknown_issues_are_not_found() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .get_admin(&format!("/admin/issues/{}", uuid::Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,232 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(test_app: &TestApp, slug: &str, name: &str) {
+    let response = test_app
+        .post_list(&serde_json::json!({ "slug": slug, "name": name }))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+}
+
+/// Subscribe to `list` through the signup form and return the confirmation link.
+async fn subscribe(test_app: &TestApp, email: &str, list: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}&list={}", email, list);
+    let response = test_app.post_subscriptions(body).await;
+    assert_eq!(200, response.status().as_u16());
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    test_app.get_links(&email_request).remove(0)
+}
+
+async fn subscribe_and_confirm(test_app: &TestApp, email: &str, list: &str) -> reqwest::Url {
+    let confirmation_link = subscribe(test_app, email, list).await;
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    confirmation_link
+}
+
+async fn list_status(test_app: &TestApp, email: &str, list: &str) -> String {
+    sqlx::query_scalar!(
+        r#"
+        SELECT ls.status
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        JOIN lists l ON l.id = ls.list_id
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        list
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn created_lists_are_listed_with_their_subscriber_counts() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_list(&test_app, "release-notes", "Release notes").await;
+    subscribe(&test_app, "ursula%40gmail.com", "release-notes").await;
+
+    // Act
+    let response = test_app.get_admin("/admin/lists").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let lists: Vec<serde_json::Value> = response.json().await.unwrap();
+    let slugs: Vec<_> = lists.iter().map(|list| list["slug"].clone()).collect();
+    assert_eq!(slugs, vec!["default", "release-notes"]);
+    assert_eq!(lists[1]["name"], "Release notes");
+    assert_eq!(lists[1]["pending_confirmation"], 1);
+    assert_eq!(lists[0]["pending_confirmation"], 0);
+}
+
+#[actix_web::test]
+async fn creating_a_list_twice_returns_a_409() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_list(&test_app, "release-notes", "Release notes").await;
+
+    // Act
+    let response = test_app
+        .post_list(&serde_json::json!({ "slug": "release-notes", "name": "Again" }))
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn create_list_returns_a_400_for_invalid_input() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "slug": "Release Notes", "name": "Release notes" }),
+            "a slug with spaces and capitals",
+        ),
+        (
+            serde_json::json!({ "slug": "release-notes", "name": "  " }),
+            "an empty name",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = test_app.post_list(&body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the body had {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribing_to_an_unknown_list_returns_a_400() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula%40gmail.com&list=nope".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn confirming_and_unsubscribing_only_affect_one_list() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_list(&test_app, "release-notes", "Release notes").await;
+    subscribe_and_confirm(&test_app, "ursula%40gmail.com", "default").await;
+    let mut link = subscribe(&test_app, "ursula%40gmail.com", "release-notes").await;
+    assert_eq!(
+        list_status(&test_app, "ursula@gmail.com", "release-notes").await,
+        "pending_confirmation"
+    );
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+
+    // Act
+    reqwest::get(link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    link.set_path("/subscriptions/unsubscribe");
+    reqwest::Client::new()
+        .post(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        list_status(&test_app, "ursula@gmail.com", "release-notes").await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        list_status(&test_app, "ursula@gmail.com", "default").await,
+        "confirmed"
+    );
+}
+
+#[actix_web::test]
+async fn issues_only_reach_the_subscribers_of_their_lists() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_list(&test_app, "release-notes", "Release notes").await;
+    create_list(&test_app, "events", "Events").await;
+    subscribe_and_confirm(&test_app, "ursula%40gmail.com", "release-notes").await;
+    subscribe_and_confirm(&test_app, "octavia%40gmail.com", "events").await;
+    subscribe_and_confirm(&test_app, "octavia%40gmail.com", "release-notes").await;
+    subscribe_and_confirm(&test_app, "ted%40gmail.com", "default").await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Version 2",
+            "content": "Out now.",
+            "lists": ["release-notes", "events"],
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    // Octavia is on both lists but gets a single copy
+    assert_eq!(report["queued"], 2);
+}
+
+#[actix_web::test]
+async fn issues_targeting_an_unknown_list_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_issue(&serde_json::json!({
+            "title": "Version 2",
+            "content": "Out now.",
+            "lists": ["nope"],
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
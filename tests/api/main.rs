@@ -1,3 +1,26 @@
+mod admin_export;
+mod admin_import;
+mod admin_templates;
+mod archive;
+mod cli;
+mod click_tracking;
+mod email_change;
+mod feed_ingestion;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issue_scheduling;
+mod issue_stats;
+mod issues;
+mod lists;
+mod migrations;
+mod newsletters;
+mod open_tracking;
+mod preferences;
+mod privacy;
+mod segments;
+mod subscribers;
 mod subscriptions;
+mod subscriptions_confirm;
+mod suppressions;
+mod webhooks;
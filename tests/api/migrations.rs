@@ -0,0 +1,54 @@
+use crate::helpers::create_database;
+use email_newsletter::configuration::get_configuration;
+use email_newsletter::migration::run_migrations;
+use email_newsletter::startup::{get_connection_pool, Application};
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn migrations_run_on_startup_when_enabled() {
+    // Arrange
+    let mut configuration = get_configuration().expect("Failed to get configuration.");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    configuration.application.port = 0;
+    configuration.application.run_migrations_on_startup = true;
+    create_database(&configuration.database).await;
+
+    // Act
+    let _application = Application::build(configuration.clone())
+        .await
+        .expect("Failed to build application.");
+
+    // Assert
+    let db_pool = get_connection_pool(&configuration.database);
+    sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_optional(&db_pool)
+        .await
+        .expect("The subscriptions table was not created.");
+}
+
+#[actix_web::test]
+async fn concurrent_migrations_do_not_race() {
+    // Arrange
+    let mut configuration = get_configuration().expect("Failed to get configuration.");
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    create_database(&configuration.database).await;
+    let db_pool = get_connection_pool(&configuration.database);
+
+    // Act
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let db_pool = db_pool.clone();
+            tokio::spawn(async move { run_migrations(&db_pool).await })
+        })
+        .collect();
+
+    // Assert
+    for handle in handles {
+        let outcome = handle.await.expect("Migration task panicked.");
+        assert!(
+            outcome.is_ok(),
+            "A concurrent migration failed: {:?}",
+            outcome
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Issue #1",
+        "content": "# Hello\n\nRead [the post](https://example.com/post).<script>alert(1)</script>",
+    })
+}
+
+#[actix_web::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_unconfirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_newsletters(&newsletter()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn newsletters_are_rendered_from_markdown_for_confirmed_subscribers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_newsletters(&newsletter()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["content"]["subject"], "Issue #1");
+    let html = email["content"]["html"].as_str().unwrap();
+    assert!(html.contains("<h1>Hello</h1>"));
+    assert!(!html.contains("<script>"));
+    let text = email["content"]["text"].as_str().unwrap();
+    assert!(text.contains("Hello"));
+    assert!(text.contains("https://example.com/post"));
+    assert!(text.contains("/subscriptions/unsubscribe?subscription_token="));
+    assert!(!text.contains("<h1>"));
+    let list_unsubscribe = email["content"]["headers"]["List-Unsubscribe"]
+        .as_str()
+        .unwrap();
+    assert!(list_unsubscribe.contains("/subscriptions/unsubscribe?subscription_token="));
+    assert_eq!(
+        email["content"]["headers"]["List-Unsubscribe-Post"],
+        "List-Unsubscribe=One-Click"
+    );
+}
+
+#[actix_web::test]
+async fn preview_renders_the_newsletter_without_sending_it() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_newsletter_preview(&newsletter()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let preview: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(preview["subject"], "Issue #1");
+    let html = preview["html"].as_str().unwrap();
+    assert!(html.contains("<title>Issue #1</title>"));
+    assert!(html
+        .contains(r#"<a href="https://example.com/post" rel="noopener noreferrer">the post</a>"#));
+    assert!(preview["lint"].as_array().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn preview_inlines_css_resolves_relative_links_and_reports_lint() {
+    // Arrange
+    let test_app = spawn_app().await;
+    // Styles belong to the template; Markdown bodies are sanitized of them
+    let template = serde_json::json!({
+        "subject": "{{ title }}",
+        "html_body": "<html><head><style>p { color: red; } .cols { display: flex; }</style></head>\
+                      <body><div class=\"cols\">{{ content | safe }}</div></body></html>",
+    });
+    test_app.put_email_template("newsletter", &template).await;
+    let newsletter = serde_json::json!({
+        "title": "Issue #2",
+        "content": "Read [the archive](/archive).",
+    });
+
+    // Act
+    let response = test_app.post_newsletter_preview(&newsletter).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let preview: serde_json::Value = response.json().await.unwrap();
+    let html = preview["html"].as_str().unwrap();
+    assert!(html.contains(r#"href="http://127.0.0.1/archive""#));
+    assert!(html.contains(r#"style="color: red;""#));
+    assert_eq!(preview["lint"][0]["construct"], "display:flex");
+}
+
+#[actix_web::test]
+async fn requests_without_admin_credentials_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", &test_app.address))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn publishing_queues_the_issue_instead_of_sending_it_inline() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let before = test_app.email_server.received_requests().await.unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", &test_app.address))
+        .basic_auth(&test_app.admin_username, Some(&test_app.admin_password))
+        .json(&newsletter())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let after = test_app.email_server.received_requests().await.unwrap();
+    assert_eq!(before.len(), after.len());
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 1);
+    test_app.run_scheduler().await;
+}
@@ -0,0 +1,120 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+/// Send an issue to the confirmed subscriber and return the path of the
+/// tracking pixel in it, if there is one.
+async fn send_issue(test_app: &TestApp) -> Option<String> {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Issue #1",
+            "content": "Hello there.",
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let email = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email.body).unwrap();
+    let html = email["content"]["html"].as_str().unwrap();
+    let start = html.find("/t/o/")?;
+    let end = start + html[start..].find(".gif")? + ".gif".len();
+    Some(html[start..end].to_string())
+}
+
+async fn get_pixel(test_app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}{}", test_app.address, path))
+        .header("User-Agent", "Thunderbird/115.0")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn count_opens(test_app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM email_events WHERE kind = 'open'"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn opening_an_issue_records_an_open_event() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let pixel = send_issue(&test_app).await.expect("No tracking pixel.");
+
+    // Act
+    let response = get_pixel(&test_app, &pixel).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["content-type"], "image/gif");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    assert!(response.bytes().await.unwrap().starts_with(b"GIF89a"));
+    let event = sqlx::query!("SELECT kind, user_agent FROM email_events WHERE kind = 'open'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "open");
+    assert_eq!(event.user_agent.as_deref(), Some("Thunderbird/115.0"));
+}
+
+#[actix_web::test]
+async fn an_invalid_token_still_gets_the_pixel_but_records_nothing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let pixel = send_issue(&test_app).await.expect("No tracking pixel.");
+    let forged = pixel.replacen("/t/o/", "/t/o/x", 1);
+
+    // Act
+    let response = get_pixel(&test_app, &forged).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["content-type"], "image/gif");
+    assert_eq!(count_opens(&test_app).await, 0);
+}
+
+#[actix_web::test]
+async fn subscribers_who_opted_out_are_not_tracked() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let pixel = send_issue(&test_app).await.expect("No tracking pixel.");
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = test_app
+        .post_preferences(format!(
+            "subscription_token={}&name=le%20guin&frequency=every_issue\
+            &lists%5Bdefault%5D=on&tracking_opt_out=on",
+            token
+        ))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains(r#"name="tracking_opt_out" checked"#));
+
+    // Act
+    get_pixel(&test_app, &pixel).await;
+    let next_pixel = send_issue(&test_app).await;
+
+    // Assert
+    assert_eq!(count_opens(&test_app).await, 0);
+    assert_eq!(next_pixel, None);
+}
@@ -0,0 +1,261 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{any, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscription_token(test_app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn save_preferences(test_app: &TestApp, fields: &str) -> reqwest::Response {
+    let token = subscription_token(test_app).await;
+    test_app
+        .post_preferences(format!("subscription_token={}&{}", token, fields))
+        .await
+}
+
+async fn add_list(test_app: &TestApp, slug: &str, name: &str) {
+    let response = test_app
+        .post_list(&serde_json::json!({ "slug": slug, "name": name }))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn the_preferences_page_shows_the_current_choices() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    add_list(&test_app, "release-notes", "Release notes").await;
+    let token = subscription_token(&test_app).await;
+
+    // Act
+    let response = test_app.get_preferences(&token).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"name="name" value="le guin""#));
+    assert!(html.contains(r#"name="lists[default]" checked"#));
+    assert!(html.contains(r#"name="lists[release-notes]">"#));
+    assert!(html.contains(r#"value="every_issue" checked"#));
+}
+
+#[actix_web::test]
+async fn preferences_need_a_valid_token() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let page = test_app.get_preferences("not-a-token").await;
+    let saved = test_app
+        .post_preferences(
+            "subscription_token=not-a-token&name=someone&frequency=every_issue".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(401, page.status().as_u16());
+    assert_eq!(401, saved.status().as_u16());
+}
+
+#[actix_web::test]
+async fn saving_preferences_updates_the_subscriber_and_logs_the_changes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    add_list(&test_app, "release-notes", "Release notes").await;
+
+    // Act
+    let response = save_preferences(
+        &test_app,
+        "name=Ursula&frequency=weekly_digest&lists%5Brelease-notes%5D=on",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT name, frequency FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.frequency, "weekly_digest");
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.id = ls.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+    let lists: Vec<_> = lists.iter().map(|l| (&*l.slug, &*l.status)).collect();
+    assert_eq!(
+        lists,
+        vec![("default", "unsubscribed"), ("release-notes", "confirmed")]
+    );
+    let changes =
+        sqlx::query!("SELECT field, old_value, new_value FROM preference_changes ORDER BY field")
+            .fetch_all(&test_app.db_pool)
+            .await
+            .unwrap();
+    let changes: Vec<_> = changes
+        .iter()
+        .map(|c| (&*c.field, c.old_value.as_deref(), c.new_value.as_deref()))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            ("frequency", Some("every_issue"), Some("weekly_digest")),
+            ("list:default", Some("confirmed"), Some("unsubscribed")),
+            ("list:release-notes", None, Some("confirmed")),
+            ("name", Some("le guin"), Some("Ursula")),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn invalid_preferences_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let test_cases = vec![
+        ("name=%3Cscript%3E&frequency=every_issue", "an invalid name"),
+        ("name=Ursula&frequency=daily", "an unknown frequency"),
+        (
+            "name=Ursula&frequency=every_issue&pause_weeks=53",
+            "too long a pause",
+        ),
+        (
+            "name=Ursula&frequency=every_issue&lists%5Bnope%5D=on",
+            "an unknown list",
+        ),
+    ];
+
+    for (fields, description) in test_cases {
+        // Act
+        let response = save_preferences(&test_app, fields).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+    let name = sqlx::query_scalar!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(name, "le guin");
+}
+
+#[actix_web::test]
+async fn paused_subscribers_get_no_issues_until_they_resume() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    save_preferences(
+        &test_app,
+        "name=le%20guin&frequency=every_issue&lists%5Bdefault%5D=on&pause_weeks=2",
+    )
+    .await;
+    let newsletter = serde_json::json!({ "title": "Issue", "content": "Hello." });
+
+    // Act - Part 1 - Publish while paused
+    let paused: serde_json::Value = {
+        let _mock_guard = Mock::given(path("/api/v1/transmissions"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&test_app.email_server)
+            .await;
+        test_app
+            .post_newsletters(&newsletter)
+            .await
+            .json()
+            .await
+            .unwrap()
+    };
+
+    // Act - Part 2 - Resume, then publish again
+    save_preferences(
+        &test_app,
+        "name=le%20guin&frequency=every_issue&lists%5Bdefault%5D=on&pause_weeks=0",
+    )
+    .await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let resumed: serde_json::Value = test_app
+        .post_newsletters(&newsletter)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(paused["queued"], 0);
+    assert_eq!(resumed["queued"], 1);
+}
+
+#[actix_web::test]
+async fn weekly_digest_subscribers_get_the_issues_of_the_week_in_one_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    save_preferences(
+        &test_app,
+        "name=le%20guin&frequency=weekly_digest&lists%5Bdefault%5D=on",
+    )
+    .await;
+    for title in ["Spring issue", "Summer issue"] {
+        let report: serde_json::Value = test_app
+            .post_newsletters(&serde_json::json!({ "title": title, "content": "Hello." }))
+            .await
+            .json()
+            .await
+            .unwrap();
+        // Queued for the digest rather than sent right away
+        assert_eq!(report["queued"], 1);
+    }
+    let not_due: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM issue_delivery_queue WHERE execute_after > now()")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(not_due, 2);
+    sqlx::query("UPDATE issue_delivery_queue SET execute_after = now() - interval '1 minute'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["content"]["subject"], "Your weekly digest");
+    let html = email["content"]["html"].as_str().unwrap();
+    assert!(html.contains("Spring issue") && html.contains("Summer issue"));
+    assert!(html.contains("/preferences?subscription_token="));
+}
@@ -0,0 +1,134 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscribe(test_app: &TestApp) {
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+}
+
+async fn request_privacy_links(test_app: &TestApp) -> Vec<reqwest::Url> {
+    let response = test_app
+        .post_privacy_request("email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    // The email is sent after the response, in the background
+    for _ in 0..50 {
+        let email_request = test_app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop();
+        if let Some(email_request) =
+            email_request.filter(|r| r.url.path() == "/api/v1/transmissions")
+        {
+            let links = test_app.get_links(&email_request);
+            if links.iter().any(|link| link.path() == "/privacy/data") {
+                return links;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("No privacy links email was sent.");
+}
+
+#[actix_web::test]
+async fn privacy_request_for_an_unknown_email_sends_nothing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_privacy_request("email=nobody%40gmail.com".into())
+        .await;
+    // Give the background task time to send anything it would send
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn the_data_link_returns_everything_stored_about_the_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    subscribe(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+
+    // Act
+    let response = reqwest::get(links[0].clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(data["subscription"]["name"], "le guin");
+    assert_eq!(data["privacy_requests"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn an_invalid_token_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/privacy/data?token=forged", test_app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn erasure_deletes_the_subscriber_and_blocks_reimport() {
+    // Arrange
+    let test_app = spawn_app().await;
+    subscribe(&test_app).await;
+    let links = request_privacy_links(&test_app).await;
+    let token = links[1]
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap();
+
+    // Act
+    let form = reqwest::get(links[1].clone()).await.unwrap();
+    let response = reqwest::Client::new()
+        .post(format!("{}/privacy/erase", test_app.address))
+        .form(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, form.status().as_u16());
+    assert_eq!(200, response.status().as_u16());
+    let remaining = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+
+    let report: serde_json::Value = test_app
+        .post_subscribers_import("", "email,name\nUrsula_Le_Guin@gmail.com,Ursula\n".into())
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["rejected"], 1);
+    assert_eq!(report["accepted"], 0);
+}
@@ -0,0 +1,290 @@
+use crate::helpers::{spawn_app, TestApp};
+use email_newsletter::cli::add_subscriber;
+use email_newsletter::domain::{
+    NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriberTag,
+    SubscriptionStatus,
+};
+use email_newsletter::routes::{find_list_id, DEFAULT_LIST};
+use wiremock::matchers::{any, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn add_subscriber_with(
+    test_app: &TestApp,
+    email: &str,
+    tags: &[&str],
+    attributes: serde_json::Value,
+    status: SubscriptionStatus,
+) {
+    let list_id = find_list_id(&test_app.db_pool, DEFAULT_LIST)
+        .await
+        .unwrap()
+        .unwrap();
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email.into()).unwrap(),
+        name: SubscriberName::parse("le guin".into()).unwrap(),
+        time_zone: None,
+        tags: SubscriberTag::parse_all(tags.iter().map(|tag| tag.to_string()).collect()).unwrap(),
+        attributes: SubscriberAttributes::default(),
+    };
+    let record = add_subscriber(&test_app.db_pool, list_id, &new_subscriber, status)
+        .await
+        .unwrap();
+    let response = test_app
+        .patch_subscriber(
+            &record.id.to_string(),
+            &serde_json::json!({ "attributes": attributes }),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+/// Rust fans at three companies, one of them yet to confirm, and someone else.
+async fn add_subscribers(test_app: &TestApp) {
+    for (email, tags, company, status) in [
+        (
+            "ursula@acme.com",
+            &["rust"][..],
+            "Acme",
+            SubscriptionStatus::Confirmed,
+        ),
+        (
+            "octavia@globex.com",
+            &["rust"],
+            "Globex",
+            SubscriptionStatus::Confirmed,
+        ),
+        (
+            "ted@initech.com",
+            &["rust"],
+            "Initech",
+            SubscriptionStatus::PendingConfirmation,
+        ),
+        ("nk@example.com", &[], "Acme", SubscriptionStatus::Confirmed),
+    ] {
+        add_subscriber_with(
+            test_app,
+            email,
+            tags,
+            serde_json::json!({ "company": company }),
+            status,
+        )
+        .await;
+    }
+    add_subscriber_with(
+        test_app,
+        "anonymous@example.com",
+        &[],
+        serde_json::json!({}),
+        SubscriptionStatus::Confirmed,
+    )
+    .await;
+}
+
+async fn preview(test_app: &TestApp, definition: &str) -> serde_json::Value {
+    let response = test_app
+        .post_segments("/preview", &serde_json::json!({ "definition": definition }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+async fn save_segment(test_app: &TestApp, name: &str, definition: &str) {
+    let response = test_app
+        .post_segments(
+            "",
+            &serde_json::json!({ "name": name, "definition": definition }),
+        )
+        .await;
+    assert_eq!(201, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn the_preview_counts_confirmed_subscribers_in_the_segment() {
+    // Arrange
+    let test_app = spawn_app().await;
+    add_subscribers(&test_app).await;
+
+    // Act & Assert
+    let count = |preview: serde_json::Value| preview["count"].as_i64().unwrap();
+    assert_eq!(count(preview(&test_app, "tag:rust").await), 2);
+    assert_eq!(
+        count(preview(&test_app, r#"tag:rust AND NOT attr.company = "Acme""#).await),
+        1
+    );
+    // Subscribers without a company are not at Acme either
+    assert_eq!(
+        count(preview(&test_app, r#"NOT attr.company = "Acme""#).await),
+        2
+    );
+    assert_eq!(
+        count(preview(&test_app, "subscribed_at > 2000-01-01 OR tag:rust").await),
+        4
+    );
+    assert_eq!(
+        count(preview(&test_app, r#"email = "x' OR '1'='1""#).await),
+        0
+    );
+}
+
+#[actix_web::test]
+async fn saved_segments_are_listed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    save_segment(&test_app, "Rustaceans", "tag:rust").await;
+
+    // Act
+    let response = test_app.get_admin("/admin/segments").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let segments: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["name"], "Rustaceans");
+    assert_eq!(segments[0]["definition"], "tag:rust");
+}
+
+#[actix_web::test]
+async fn saving_a_segment_name_twice_returns_a_409() {
+    // Arrange
+    let test_app = spawn_app().await;
+    save_segment(&test_app, "Rustaceans", "tag:rust").await;
+
+    // Act
+    let response = test_app
+        .post_segments(
+            "",
+            &serde_json::json!({ "name": "Rustaceans", "definition": "tag:ferris" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn invalid_segments_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let test_cases = vec![
+        ("tag:rust AND", "an incomplete definition"),
+        (r#"attr.country = "BR""#, "an undeclared attribute"),
+        (
+            "attr.company > 5",
+            "a number compared with a text attribute",
+        ),
+        ("name = ursula; DROP TABLE subscriptions", "stray SQL"),
+    ];
+
+    for (definition, description) in test_cases {
+        // Act
+        let saved = test_app
+            .post_segments(
+                "",
+                &serde_json::json!({ "name": "Broken", "definition": definition }),
+            )
+            .await;
+        let previewed = test_app
+            .post_segments("/preview", &serde_json::json!({ "definition": definition }))
+            .await;
+
+        // Assert
+        for response in [saved, previewed] {
+            assert_eq!(
+                400,
+                response.status().as_u16(),
+                "The API did not fail with 400 Bad Request for {}.",
+                description
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn issues_targeting_a_segment_only_reach_its_members() {
+    // Arrange
+    let test_app = spawn_app().await;
+    add_subscribers(&test_app).await;
+    save_segment(&test_app, "Rustaceans", "tag:rust").await;
+    Mock::given(path("/api/v1/transmissions"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Ferris turns 8",
+            "content": "Cake in the break room.",
+            "segment": "Rustaceans",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 2);
+}
+
+#[actix_web::test]
+async fn scheduled_issues_are_only_queued_for_segment_members() {
+    // Arrange
+    let test_app = spawn_app().await;
+    add_subscribers(&test_app).await;
+    save_segment(&test_app, "Acme", r#"attr.company = "Acme""#).await;
+    let draft = serde_json::json!({ "title": "For Acme", "content": "Hello." });
+    let issue: serde_json::Value = test_app.post_issue(&draft).await.json().await.unwrap();
+    let id = issue["id"].as_str().unwrap();
+    let response = test_app
+        .put_issue(
+            id,
+            &serde_json::json!({ "title": "For Acme", "content": "Hello.", "segment": "Acme" }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["segment"], "Acme");
+    test_app
+        .put_issue_schedule(
+            id,
+            &serde_json::json!({ "send_at": "2099-01-01T09:00", "time_zone": "UTC" }),
+        )
+        .await;
+    sqlx::query("UPDATE newsletter_issues SET scheduled_at = now() - interval '1 minute'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app.run_scheduler().await;
+
+    // Assert
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let sent = test_app.email_server.received_requests().await.unwrap();
+    // Both confirmed Acme subscribers, whether or not they are queued still
+    assert_eq!(queued + sent.len() as i64, 2);
+}
+
+#[actix_web::test]
+async fn issues_targeting_an_unknown_segment_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_issue(&serde_json::json!({
+            "title": "Ferris turns 8",
+            "content": "Cake in the break room.",
+            "segment": "Nobody",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
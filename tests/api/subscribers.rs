@@ -0,0 +1,373 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn mock_email_server(test_app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+}
+
+async fn saved_fields(test_app: &TestApp) -> (Vec<String>, serde_json::Value) {
+    let saved = sqlx::query!("SELECT tags, attributes FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    (saved.tags, saved.attributes)
+}
+
+async fn subscriber_id(test_app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .to_string()
+}
+
+#[actix_web::test]
+async fn the_signup_form_sets_whitelisted_tags_and_attributes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+    let body = "name=le%20guin&email=ursula%40gmail.com&tags=Beta\
+        &attributes%5Blocale%5D=pt-BR&attributes%5Bsignup_source%5D=landing-page\
+        &utm_campaign=spring";
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let (tags, attributes) = saved_fields(&test_app).await;
+    assert_eq!(tags, vec!["beta"]);
+    assert_eq!(
+        attributes,
+        serde_json::json!({ "locale": "pt-BR", "signup_source": "landing-page" })
+    );
+}
+
+#[actix_web::test]
+async fn the_signup_form_rejects_tags_and_attributes_that_are_not_whitelisted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+    let test_cases = vec![
+        ("tags=vip", "a tag that is not whitelisted"),
+        (
+            "attributes%5Bcompany%5D=Acme",
+            "an attribute only admins set",
+        ),
+        ("attributes%5Bcountry%5D=Brazil", "an undeclared attribute"),
+        (
+            "attributes%5Blocale%5D=a-locale-that-is-far-too-long-to-be-real",
+            "an attribute that breaks its rule",
+        ),
+    ];
+
+    for (fields, description) in test_cases {
+        // Act
+        let body = format!("name=le%20guin&email=ursula%40gmail.com&{}", fields);
+        let response = test_app.post_subscriptions(body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the form had {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn signing_up_again_adds_tags_but_keeps_existing_attributes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+    test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula%40gmail.com&attributes%5Blocale%5D=en".into(),
+        )
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions(
+            "name=someone&email=ursula%40gmail.com&tags=beta\
+            &attributes%5Blocale%5D=pt-BR&attributes%5Bsignup_source%5D=blog"
+                .into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let (tags, attributes) = saved_fields(&test_app).await;
+    assert_eq!(tags, vec!["beta"]);
+    assert_eq!(
+        attributes,
+        serde_json::json!({ "locale": "en", "signup_source": "blog" })
+    );
+}
+
+#[actix_web::test]
+async fn admins_can_see_a_subscriber_with_their_tags_attributes_and_lists() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let id = subscriber_id(&test_app).await;
+
+    // Act
+    let response = test_app
+        .get_admin(&format!("/admin/subscribers/{}", id))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["tags"], serde_json::json!([]));
+    assert_eq!(subscriber["attributes"], serde_json::json!({}));
+    assert_eq!(
+        subscriber["lists"],
+        serde_json::json!([{ "list": "default", "status": "confirmed" }])
+    );
+}
+
+#[actix_web::test]
+async fn admins_can_replace_tags_and_merge_attributes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+    test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula%40gmail.com&tags=beta\
+            &attributes%5Blocale%5D=en&attributes%5Bsignup_source%5D=blog"
+                .into(),
+        )
+        .await;
+    let id = subscriber_id(&test_app).await;
+
+    // Act
+    let response = test_app
+        .patch_subscriber(
+            &id,
+            &serde_json::json!({
+                "tags": ["vip", "early-adopter"],
+                "attributes": { "company": "Acme", "locale": null },
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        subscriber["tags"],
+        serde_json::json!(["early-adopter", "vip"])
+    );
+    assert_eq!(
+        subscriber["attributes"],
+        serde_json::json!({ "company": "Acme", "signup_source": "blog" })
+    );
+}
+
+#[actix_web::test]
+async fn admin_updates_are_checked_against_the_attribute_rules() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let id = subscriber_id(&test_app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "tags": ["not a tag"] }),
+            "an invalid tag",
+        ),
+        (
+            serde_json::json!({ "attributes": { "company": 42 } }),
+            "a number for a text attribute",
+        ),
+        (
+            serde_json::json!({ "attributes": { "country": "Brazil" } }),
+            "an undeclared attribute",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = test_app.patch_subscriber(&id, &body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the update had {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn updating_an_unknown_subscriber_returns_a_404() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .patch_subscriber(
+            &uuid::Uuid::new_v4().to_string(),
+            &serde_json::json!({ "tags": ["vip"] }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn timeline(test_app: &TestApp) -> Vec<serde_json::Value> {
+    let id = subscriber_id(test_app).await;
+    let subscriber: serde_json::Value = test_app
+        .get_admin(&format!("/admin/subscribers/{}", id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    subscriber["timeline"].as_array().unwrap().clone()
+}
+
+fn kinds(timeline: &[serde_json::Value]) -> Vec<&str> {
+    timeline
+        .iter()
+        .map(|entry| entry["kind"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn the_timeline_follows_a_subscriber_from_signup_to_unsubscribe() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    test_app.publish_new_issue("Issue #1", "Hello.").await;
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app
+        .post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let timeline = timeline(&test_app).await;
+
+    // Assert
+    assert_eq!(
+        kinds(&timeline),
+        vec![
+            "signed_up",
+            "confirmation_email",
+            "confirmed",
+            "delivery_attempt",
+            "unsubscribe"
+        ]
+    );
+    assert_eq!(timeline[0]["list"], "default");
+    assert_eq!(timeline[3]["issue_title"], "Issue #1");
+    assert_eq!(timeline[3]["detail"], "Accepted by the provider (200 OK)");
+}
+
+#[actix_web::test]
+async fn provider_events_and_preference_changes_are_on_the_timeline() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    mock_email_server(&test_app).await;
+    test_app.publish_new_issue("Issue #1", "Hello.").await;
+    let delivery = serde_json::json!([{
+        "msys": {
+            "message_event": { "type": "delivery", "rcpt_to": "ursula_le_guin@gmail.com" }
+        }
+    }]);
+    test_app.post_email_events(&delivery).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO preference_changes
+            (id, subscriber_id, field, old_value, new_value, changed_at)
+        SELECT gen_random_uuid(), id, 'frequency', 'every_issue', 'weekly_digest', now()
+        FROM subscriptions
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let timeline = timeline(&test_app).await;
+
+    // Assert
+    assert_eq!(
+        kinds(&timeline[3..]),
+        vec!["delivery_attempt", "delivered", "preference_change"]
+    );
+    assert_eq!(timeline[4]["issue_title"], "Issue #1");
+    assert_eq!(
+        timeline[5]["detail"],
+        "frequency: every_issue -> weekly_digest"
+    );
+}
+
+#[actix_web::test]
+async fn failed_deliveries_show_what_the_provider_answered() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app.publish_new_issue("Issue #1", "Hello.").await;
+
+    // Assert
+    let timeline = timeline(&test_app).await;
+    let attempt = timeline.last().unwrap();
+    assert_eq!(attempt["kind"], "delivery_attempt");
+    let detail = attempt["detail"].as_str().unwrap();
+    assert!(detail.starts_with("Failed:"), "{}", detail);
+    assert!(detail.contains("500"), "{}", detail);
+}
+
+#[actix_web::test]
+async fn issues_held_back_from_a_subscriber_are_on_the_timeline() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let bounce = serde_json::json!([{
+        "msys": {
+            "message_event": {
+                "type": "bounce",
+                "bounce_class": "10",
+                "rcpt_to": "ursula_le_guin@gmail.com"
+            }
+        }
+    }]);
+    test_app.post_email_events(&bounce).await;
+    // Put back on the list by someone else
+    sqlx::query!("UPDATE list_subscriptions SET status = 'confirmed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    mock_email_server(&test_app).await;
+
+    // Act
+    test_app.publish_new_issue("Issue #1", "Hello.").await;
+
+    // Assert
+    let timeline = timeline(&test_app).await;
+    assert_eq!(kinds(&timeline[3..]), vec!["bounce", "delivery_skipped"]);
+    assert_eq!(timeline[3]["detail"], "Hard bounce, class 10");
+    assert_eq!(timeline[4]["detail"], "Address suppressed after a bounce");
+}
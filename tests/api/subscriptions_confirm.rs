@@ -0,0 +1,119 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_web::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/subscriptions/confirm", test_app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn clicking_on_the_confirmation_link_confirms_and_welcomes_a_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = test_app.get_links(email_request).remove(0);
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM list_subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+
+    let welcome_request = &test_app.email_server.received_requests().await.unwrap()[1];
+    let welcome: serde_json::Value = serde_json::from_slice(&welcome_request.body).unwrap();
+    assert_eq!(welcome["content"]["subject"], "Welcome!");
+    let unsubscribe_link = test_app.get_links(welcome_request).remove(0);
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_link_unsubscribes_and_sends_a_receipt() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let mut link = test_app.get_links(email_request).remove(0);
+    link.set_path("/subscriptions/unsubscribe");
+
+    // Act
+    let client = reqwest::Client::new();
+    let response = client.post(link.clone()).send().await.unwrap();
+    // Unsubscribing twice sends a single receipt
+    client.post(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM list_subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn opening_the_unsubscribe_link_only_asks_for_confirmation() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/api/v1/transmissions"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let mut link = test_app.get_links(email_request).remove(0);
+    link.set_path("/subscriptions/unsubscribe");
+
+    // Act
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"method="post""#));
+    assert!(page.contains(&format!(
+        "action=\"{}?{}\"",
+        link.path(),
+        link.query().unwrap()
+    )));
+    let saved = sqlx::query!("SELECT status FROM list_subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
@@ -0,0 +1,262 @@
+use crate::helpers::{spawn_app, TestApp};
+use email_newsletter::suppression::email_hash;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn unsubscribe(test_app: &TestApp) {
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .named("Unsubscribe receipt")
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = test_app.post_unsubscribe(&token).await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+async fn suppression(test_app: &TestApp) -> Option<String> {
+    sqlx::query_scalar!(
+        "SELECT reason FROM suppressions WHERE email_hash = $1",
+        email_hash(EMAIL)
+    )
+    .fetch_optional(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn send_issue(test_app: &TestApp) -> serde_json::Value {
+    let response = test_app
+        .post_newsletters(&serde_json::json!({
+            "title": "Issue #1",
+            "content": "Hello.",
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[actix_web::test]
+async fn suppressed_addresses_are_not_mailed_even_when_added_back() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    unsubscribe(&test_app).await;
+    // Someone other than the subscriber puts them back on the list
+    sqlx::query!("UPDATE list_subscriptions SET status = 'confirmed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let report = send_issue(&test_app).await;
+
+    // Assert
+    assert_eq!(suppression(&test_app).await.as_deref(), Some("unsubscribe"));
+    // Queued, then skipped by the delivery worker
+    assert_eq!(report["queued"], 1);
+}
+
+#[actix_web::test]
+async fn suppressed_addresses_are_rejected_by_imports() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    unsubscribe(&test_app).await;
+
+    // Act
+    let report: serde_json::Value = test_app
+        .post_subscribers_import("", format!("email,name\n{},Ursula\n", EMAIL.to_uppercase()))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report["rejected"], 1);
+    assert_eq!(
+        report["rows"][0]["reason"],
+        "Address unsubscribed from every list."
+    );
+}
+
+#[actix_web::test]
+async fn leaving_one_of_several_lists_does_not_suppress_the_address() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let response = test_app
+        .post_list(&serde_json::json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT l.id, s.id, 'confirmed', now() FROM lists l, subscriptions s
+        WHERE l.slug = 'release-notes'
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    unsubscribe(&test_app).await;
+
+    // Assert
+    assert_eq!(suppression(&test_app).await, None);
+}
+
+#[actix_web::test]
+async fn confirming_a_new_subscription_lifts_an_unsubscribe() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    unsubscribe(&test_app).await;
+
+    // Act
+    test_app.create_confirmed_subscriber().await;
+
+    // Assert
+    assert_eq!(suppression(&test_app).await, None);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    send_issue(&test_app).await;
+}
+
+#[actix_web::test]
+async fn bounced_addresses_get_no_confirmation_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let bounce = serde_json::json!([{
+        "msys": {
+            "message_event": { "type": "bounce", "bounce_class": "10", "rcpt_to": EMAIL }
+        }
+    }]);
+    assert_eq!(
+        200,
+        test_app.post_email_events(&bounce).await.status().as_u16()
+    );
+    let response = test_app
+        .post_list(&serde_json::json!({ "slug": "release-notes", "name": "Release notes" }))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list=release-notes".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(suppression(&test_app).await.as_deref(), Some("bounce"));
+}
+
+/// Have the confirmed subscriber report our email as spam.
+async fn complain(test_app: &TestApp) {
+    let complaint = serde_json::json!([{
+        "msys": {
+            "message_event": { "type": "spam_complaint", "rcpt_to": EMAIL }
+        }
+    }]);
+    assert_eq!(
+        200,
+        test_app
+            .post_email_events(&complaint)
+            .await
+            .status()
+            .as_u16()
+    );
+}
+
+#[actix_web::test]
+async fn complained_addresses_get_no_privacy_links() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    complain(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_privacy_request("email=ursula_le_guin%40gmail.com".into())
+        .await;
+    // The links are sent in the background
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let requests: i64 =
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM privacy_request_tokens"#)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(requests, 1);
+}
+
+#[actix_web::test]
+async fn complained_addresses_get_no_email_change_notice() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    complain(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = test_app
+        .post_email_change(format!(
+            "subscription_token={}&email=ursula%40example.com",
+            token
+        ))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let sent = test_app.email_server.received_requests().await.unwrap();
+    let confirmation_link = test_app.get_links(sent.last().unwrap()).remove(0);
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(confirmation_link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved_email: String = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved_email, "ursula@example.com");
+    // No notice went to the old address
+    let sent_after = test_app.email_server.received_requests().await.unwrap();
+    assert_eq!(sent_after.len(), sent.len());
+}
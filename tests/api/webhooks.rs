@@ -0,0 +1,236 @@
+use crate::helpers::{spawn_app, TestApp};
+use email_newsletter::cli::add_subscriber;
+use email_newsletter::domain::{
+    NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+use email_newsletter::routes::{find_list_id, DEFAULT_LIST};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+/// A message event as SparkPost posts it, about the confirmed subscriber.
+fn message_event(kind: &str, bounce_class: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "msys": {
+            "message_event": {
+                "type": kind,
+                "bounce_class": bounce_class,
+                "rcpt_to": "ursula_le_guin@gmail.com",
+                "timestamp": "1460989507",
+            }
+        }
+    })
+}
+
+async fn post_events(test_app: &TestApp, events: &[serde_json::Value]) {
+    let response = test_app
+        .post_email_events(&serde_json::Value::from(events.to_vec()))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+async fn subscription_status(test_app: &TestApp) -> String {
+    sqlx::query_scalar!("SELECT status FROM list_subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn hard_bounces_stop_further_sends() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let issue = test_app.publish_new_issue("Issue #1", "Hello.").await;
+
+    // Act
+    post_events(&test_app, &[message_event("bounce", Some("10"))]).await;
+
+    // Assert
+    assert_eq!(subscription_status(&test_app).await, "bounced");
+    let stats: serde_json::Value = test_app
+        .get_admin(&format!(
+            "/admin/issues/{}/stats",
+            issue["id"].as_str().unwrap()
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["bounces"], 1);
+    assert_eq!(stats["delivered"], 0);
+    // The mock expects the first issue only
+    test_app.publish_new_issue("Issue #2", "Hello again.").await;
+}
+
+#[actix_web::test]
+async fn spam_complaints_mark_the_subscriber_as_complained() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+
+    // Act
+    post_events(&test_app, &[message_event("spam_complaint", None)]).await;
+
+    // Assert
+    assert_eq!(subscription_status(&test_app).await, "complained");
+}
+
+#[actix_web::test]
+async fn soft_bounces_are_escalated_past_the_limit() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let soft_bounce = message_event("bounce", Some("22"));
+    let limit = test_app.webhooks.soft_bounce_limit as usize;
+
+    // Act & Assert
+    post_events(&test_app, &vec![soft_bounce.clone(); limit - 1]).await;
+    assert_eq!(subscription_status(&test_app).await, "confirmed");
+    post_events(&test_app, &[soft_bounce]).await;
+    assert_eq!(subscription_status(&test_app).await, "bounced");
+}
+
+#[actix_web::test]
+async fn a_delivery_resets_the_soft_bounce_count() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let soft_bounce = message_event("bounce", Some("22"));
+    let limit = test_app.webhooks.soft_bounce_limit as usize;
+    post_events(&test_app, &vec![soft_bounce.clone(); limit - 1]).await;
+
+    // Act
+    post_events(&test_app, &[message_event("delivery", None), soft_bounce]).await;
+
+    // Assert
+    assert_eq!(subscription_status(&test_app).await, "confirmed");
+}
+
+#[actix_web::test]
+async fn events_sent_again_are_only_applied_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let mut soft_bounce = message_event("bounce", Some("22"));
+    soft_bounce["msys"]["message_event"]["event_id"] = "92356927693813856".into();
+
+    // Act
+    post_events(&test_app, &[soft_bounce.clone()]).await;
+    post_events(&test_app, &[soft_bounce]).await;
+
+    // Assert
+    let soft_bounces = sqlx::query_scalar!("SELECT soft_bounces FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(soft_bounces, 1);
+}
+
+#[actix_web::test]
+async fn events_apply_to_every_subscriber_sharing_the_mailbox() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let list_id = find_list_id(&test_app.db_pool, DEFAULT_LIST)
+        .await
+        .unwrap()
+        .unwrap();
+    let case_variant = NewSubscriber {
+        email: SubscriberEmail::parse("Ursula_Le_Guin@Gmail.com".into()).unwrap(),
+        name: SubscriberName::parse("Ursula".into()).unwrap(),
+        time_zone: None,
+        tags: Vec::new(),
+        attributes: SubscriberAttributes::default(),
+    };
+    add_subscriber(
+        &test_app.db_pool,
+        list_id,
+        &case_variant,
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .unwrap();
+
+    // Act
+    post_events(&test_app, &[message_event("bounce", Some("10"))]).await;
+
+    // Assert
+    let statuses = sqlx::query_scalar!("SELECT status FROM list_subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, ["bounced", "bounced"]);
+}
+
+#[actix_web::test]
+async fn soft_bounces_do_not_count_against_deliveries() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let issue = test_app.publish_new_issue("Issue #1", "Hello.").await;
+
+    // Act
+    post_events(&test_app, &[message_event("bounce", Some("22"))]).await;
+
+    // Assert
+    let stats: serde_json::Value = test_app
+        .get_admin(&format!(
+            "/admin/issues/{}/stats",
+            issue["id"].as_str().unwrap()
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["soft_bounces"], 1);
+    assert_eq!(stats["bounces"], 0);
+    assert_eq!(stats["delivered"], 1);
+}
+
+#[actix_web::test]
+async fn test_pings_and_unknown_recipients_are_accepted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let mut unknown = message_event("bounce", Some("10"));
+    unknown["msys"]["message_event"]["rcpt_to"] = "nobody@example.com".into();
+
+    // Act & Assert
+    post_events(&test_app, &[serde_json::json!({ "msys": {} }), unknown]).await;
+}
+
+#[actix_web::test]
+async fn webhook_calls_without_valid_credentials_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.create_confirmed_subscriber().await;
+    let events = serde_json::json!([message_event("bounce", Some("10"))]);
+
+    // Act
+    let anonymous = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", test_app.address))
+        .json(&events)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let forged = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", test_app.address))
+        .basic_auth(&test_app.webhooks.username, Some("wrong-password"))
+        .json(&events)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, anonymous.status().as_u16());
+    assert_eq!(401, forged.status().as_u16());
+    assert_eq!(subscription_status(&test_app).await, "confirmed");
+}